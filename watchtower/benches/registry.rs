@@ -0,0 +1,130 @@
+use actix::Actor;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures_util::future::join_all;
+use std::{collections::HashMap, sync::Arc};
+use tokio::runtime::Runtime;
+use watchtower::{
+    resources::Dispatcher,
+    types::{InstanceInfo, InstanceStatus, Metrics, ServiceRegistry}
+};
+
+const SERVICE_COUNT: usize = 20;
+const INSTANCES_PER_SERVICE: usize = 50;
+const OPERATIONS_PER_TASK: usize = 200;
+const TASK_COUNTS: [usize; 3] = [1, 8, 32];
+
+fn create_registry() -> Arc<ServiceRegistry> {
+    let metrics = Arc::new(Metrics::new());
+    // The dispatcher is never sent any message since every operation is flagged as replicated
+    let dispatcher_metrics = metrics.clone();
+    let dispatcher = actix::System::new("bench").block_on(async move {
+        Dispatcher::new(vec![], dispatcher_metrics).start()
+    });
+    Arc::new(ServiceRegistry::new(dispatcher, metrics))
+}
+
+fn create_runtime() -> Runtime {
+    tokio::runtime::Builder::new()
+        .threaded_scheduler()
+        .core_threads(4)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn service_id(index: usize) -> String {
+    format!("service-{}", index % SERVICE_COUNT)
+}
+
+fn instance_id(index: usize) -> String {
+    format!("instance-{}", index % INSTANCES_PER_SERVICE)
+}
+
+fn populate(runtime: &mut Runtime, registry: &ServiceRegistry) {
+    runtime.block_on(async {
+        for service in 0..SERVICE_COUNT {
+            for instance in 0..INSTANCES_PER_SERVICE {
+                registry.register_instance(&service_id(service), InstanceInfo {
+                    instance_id: instance_id(instance),
+                    ip_addr: "127.0.0.1".to_string(),
+                    port: 8080,
+                    status: InstanceStatus::Up,
+                    weight: 1,
+                    metadata: HashMap::new()
+                }, true).await.unwrap();
+            }
+        }
+    });
+}
+
+async fn renew(registry: Arc<ServiceRegistry>, task: usize) {
+    for operation in 0..OPERATIONS_PER_TASK {
+        let index = task * OPERATIONS_PER_TASK + operation;
+        registry.renew_lease(&service_id(index), &instance_id(index / SERVICE_COUNT), true).await.unwrap();
+    }
+}
+
+async fn read(registry: Arc<ServiceRegistry>, task: usize) {
+    for operation in 0..OPERATIONS_PER_TASK {
+        registry.get_all_instances(&service_id(task + operation)).await.unwrap();
+    }
+}
+
+fn bench_renew(c: &mut Criterion) {
+    let mut runtime = create_runtime();
+    let registry = create_registry();
+    populate(&mut runtime, &registry);
+
+    let mut group = c.benchmark_group("renew");
+    for tasks in TASK_COUNTS.iter() {
+        group.throughput(Throughput::Elements((tasks * OPERATIONS_PER_TASK) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(tasks), tasks, |b, &tasks| {
+            b.iter(|| runtime.block_on(async {
+                join_all((0..tasks).map(|task| tokio::spawn(renew(registry.clone(), task)))).await;
+            }));
+        });
+    }
+    group.finish();
+}
+
+fn bench_read(c: &mut Criterion) {
+    let mut runtime = create_runtime();
+    let registry = create_registry();
+    populate(&mut runtime, &registry);
+
+    let mut group = c.benchmark_group("read");
+    for tasks in TASK_COUNTS.iter() {
+        group.throughput(Throughput::Elements((tasks * OPERATIONS_PER_TASK) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(tasks), tasks, |b, &tasks| {
+            b.iter(|| runtime.block_on(async {
+                join_all((0..tasks).map(|task| tokio::spawn(read(registry.clone(), task)))).await;
+            }));
+        });
+    }
+    group.finish();
+}
+
+/// Half of the tasks renew leases while the other half read the same services.
+fn bench_mixed(c: &mut Criterion) {
+    let mut runtime = create_runtime();
+    let registry = create_registry();
+    populate(&mut runtime, &registry);
+
+    let mut group = c.benchmark_group("renew_and_read");
+    for tasks in TASK_COUNTS.iter().filter(|tasks| **tasks > 1) {
+        group.throughput(Throughput::Elements((tasks * OPERATIONS_PER_TASK) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(tasks), tasks, |b, &tasks| {
+            b.iter(|| runtime.block_on(async {
+                join_all((0..tasks).map(|task| if task % 2 == 0 {
+                    tokio::spawn(renew(registry.clone(), task))
+                } else {
+                    tokio::spawn(read(registry.clone(), task))
+                })).await;
+            }));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_renew, bench_read, bench_mixed);
+criterion_main!(benches);
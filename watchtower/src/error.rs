@@ -31,6 +31,27 @@ impl From<actix::MailboxError> for WatchtowerError {
     }
 }
 
+impl From<prometheus::Error> for WatchtowerError {
+    fn from(error: prometheus::Error) -> Self {
+        error!("{}", error);
+        WatchtowerError::InternalError
+    }
+}
+
+impl From<std::string::FromUtf8Error> for WatchtowerError {
+    fn from(error: std::string::FromUtf8Error) -> Self {
+        error!("{}", error);
+        WatchtowerError::InternalError
+    }
+}
+
+impl From<error::BlockingError<std::io::Error>> for WatchtowerError {
+    fn from(error: error::BlockingError<std::io::Error>) -> Self {
+        error!("{}", error);
+        WatchtowerError::InternalError
+    }
+}
+
 impl error::ResponseError for WatchtowerError {
     fn error_response(&self) -> HttpResponse {
         HttpResponseBuilder::new(self.status_code())
@@ -0,0 +1,5 @@
+pub mod routes;
+pub mod utils;
+pub mod types;
+pub mod error;
+pub mod resources;
@@ -1,16 +1,16 @@
 use actix::Actor;
-use actix_web::{middleware, web, App, HttpServer};
-
-mod routes;
-mod utils;
-mod types;
-mod error;
-mod resources;
+use actix_web::{dev::Service, middleware, web, App, HttpServer};
+use futures_util::future::FutureExt;
+use std::{
+    sync::Arc,
+    time::Instant
+};
 
-use crate::{
-    types::{AppState, ServiceRegistry},
-    resources::{spawn_runner, Dispatcher},
-    utils::env
+use watchtower::{
+    routes,
+    types::{AppState, ServiceRegistry, Metrics, Readiness},
+    resources::{create_scheduler, spawn_scheduler, spawn_startup_sync, Dispatcher},
+    utils::{self, env}
 };
 
 #[actix_web::main]
@@ -18,23 +18,47 @@ async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "actix_web=info,watchtower=info");
     env_logger::init();
 
-    let dispatcher = Dispatcher::new(utils::env::get_cluster_nodes()).start();
+    let metrics = Arc::new(Metrics::new());
+    let dispatcher = Dispatcher::new(utils::env::get_cluster_nodes(), metrics.clone()).start();
     let app_state = web::Data::new(AppState {
-        service_registry: ServiceRegistry::new(dispatcher)
+        service_registry: ServiceRegistry::new(dispatcher, metrics.clone()),
+        metrics,
+        readiness: Readiness::new(),
+        scheduler: create_scheduler()
     });
 
-    spawn_runner(app_state.clone());
+    spawn_startup_sync(app_state.clone());
+    spawn_scheduler(app_state.clone());
 
     HttpServer::new(move || App::new()
         .wrap(middleware::Logger::default())
+        .wrap_fn(|req, srv| {
+            // Records the latency of every request, labelled by its matched route pattern
+            let start = Instant::now();
+            srv.call(req).map(move |res| {
+                if let Ok(res) = &res {
+                    if let Some(data) = res.request().app_data::<web::Data<AppState>>() {
+                        let route = res.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+                        data.metrics.request_latency
+                            .with_label_values(&[res.request().method().as_str(), &route, res.status().as_str()])
+                            .observe(start.elapsed().as_secs_f64());
+                    }
+                }
+                res
+            })
+        })
         .app_data(app_state.clone())
+        .configure(routes::metrics::config)
+        .configure(routes::probes::config)
         .service(
             web::scope("/api/v1")
             .configure(routes::v1::services::config)
             .configure(routes::v1::health::config)
+            .configure(routes::v1::jobs::config)
+            .configure(routes::v1::leases::config)
         )
     )
     .bind(env::get_hostname())?
     .run()
     .await
-}
\ No newline at end of file
+}
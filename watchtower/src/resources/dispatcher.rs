@@ -1,13 +1,15 @@
 use actix::{Actor, Context, Handler, Message, prelude::ResponseFuture};
 use std::{
     net::SocketAddr,
-    sync::Arc
+    sync::Arc,
+    time::Instant
 };
 use futures_util::future::join_all;
-use log::error;
+use log::{error, warn};
 
 use crate::{
-    types::{InstanceInfo, Result},
+    types::{InstanceInfo, InstanceUpdate, LeaseInfo, Result},
+    resources::Metrics,
     utils::{env, auth::REPLICATION_HEADER}
 };
 
@@ -15,12 +17,20 @@ pub enum DispatcherMessage {
     Register(String, InstanceInfo),
     Renew(String, InstanceInfo),
     Cancel(String, String),
+    Update(String, String, InstanceUpdate),
 }
 
 impl Message for DispatcherMessage {
     type Result = Result<bool>;
 }
 
+/// A request for the leases of every instance on the first reachable peer.
+pub struct SyncRequest;
+
+impl Message for SyncRequest {
+    type Result = Option<Vec<LeaseInfo>>;
+}
+
 const USER_AGENT_KEY: &str = "User-Agent";
 const USER_AGENT_VALUE: &str = "WatchtowerDispatcher";
 
@@ -28,25 +38,34 @@ pub struct Node {
     client: reqwest::Client,
     url: SocketAddr,
     username: String,
-    password: String
+    password: String,
+    metrics: Arc<Metrics>
 }
 
 impl Node {
-    pub fn new(url: SocketAddr) -> Self {
+    pub fn new(url: SocketAddr, metrics: Arc<Metrics>) -> Self {
         let auth = env::get_auth_info();
         Node {
             client: reqwest::Client::new(),
             url,
             username: auth.username,
-            password: auth.password
+            password: auth.password,
+            metrics
         }
     }
 
+    fn record(&self, operation: &str, is_success: bool, start: Instant) {
+        self.metrics.record_replication(&self.url.to_string(), operation, is_success, start.elapsed().as_secs_f64());
+    }
+
     /// Sends an instance register request to the node.
-    pub async fn register(&self, service_id: &str, instance_info: &InstanceInfo) {
+    ///
+    /// Returns `true` if the node registered the instance.
+    pub async fn register(&self, service_id: &str, instance_info: &InstanceInfo) -> bool {
         let url = format!("http://{}/api/v1/services/{}", self.url, service_id);
         let instance_info = serde_json::to_string(&instance_info).expect("Fails to serialize instance_info");
-        match self.client.post(&url).body(instance_info)
+        let start = Instant::now();
+        let is_success = match self.client.post(&url).body(instance_info)
             .basic_auth(&self.username, Some(&self.password))
             .header("content-type", "application/json")
             .header(REPLICATION_HEADER, "true")
@@ -55,12 +74,18 @@ impl Node {
             Ok(res) => {
                 if res.status() != reqwest::StatusCode::NO_CONTENT {
                     error!("Unexpected status code {}", res.status());
+                    false
+                } else {
+                    true
                 }
             },
             Err(err) => {
                 error!("Unable to replicate register request: {}", err);
+                false
             }
-        }
+        };
+        self.record("register", is_success, start);
+        is_success
     }
 
     /// Sends an instance renew request to the node.
@@ -68,30 +93,36 @@ impl Node {
     /// If the instance does not exist on the node, it will subsequently send an instance register request.
     pub async fn renew(&self, service_id: &str, instance_info: &InstanceInfo) {
         let url = format!("http://{}/api/v1/services/{}/{}", self.url, service_id, instance_info.instance_id);
-        match self.client.put(&url)
+        let start = Instant::now();
+        let is_success = match self.client.put(&url)
             .basic_auth(&self.username, Some(&self.password))
             .header(REPLICATION_HEADER, "true")
             .header(USER_AGENT_KEY, USER_AGENT_VALUE)
             .send().await {
             Ok(res) => {
                 if res.status() == reqwest::StatusCode::OK {
+                    true
                 } else if res.status() == reqwest::StatusCode::NOT_FOUND {
                     // If the instance does not exist, register the instance instead
-                    self.register(service_id, &instance_info).await;
+                    self.register(service_id, instance_info).await
                 } else {
                     error!("Unexpected status code: {}", res.status());
+                    false
                 }
             },
             Err(err) => {
                 error!("Unable to replicate renew request: {}", err);
+                false
             }
-        }
+        };
+        self.record("renew", is_success, start);
     }
 
     /// Sends an lease cancel request to the node.
     pub async fn cancel(&self, service_id: &str, instance_id: &str) {
         let url = format!("http://{}/api/v1/services/{}/{}", self.url, service_id, instance_id);
-        match self.client.delete(&url)
+        let start = Instant::now();
+        let is_success = match self.client.delete(&url)
             .basic_auth(&self.username, Some(&self.password))
             .header(REPLICATION_HEADER, "true")
             .header(USER_AGENT_KEY, USER_AGENT_VALUE)
@@ -99,10 +130,69 @@ impl Node {
             Ok(res) => {
                 if res.status() != reqwest::StatusCode::OK {
                     error!("Unexpected status code: {}", res.status());
+                    false
+                } else {
+                    true
                 }
             },
             Err(err) => {
                 error!("Unable to replicate cancel request: {}", err);
+                false
+            }
+        };
+        self.record("cancel", is_success, start);
+    }
+
+    /// Sends an instance update request to the node.
+    pub async fn update(&self, service_id: &str, instance_id: &str, update: &InstanceUpdate) {
+        let url = format!("http://{}/api/v1/services/{}/{}", self.url, service_id, instance_id);
+        let start = Instant::now();
+        let is_success = match self.client.patch(&url).json(update)
+            .basic_auth(&self.username, Some(&self.password))
+            .header(REPLICATION_HEADER, "true")
+            .header(USER_AGENT_KEY, USER_AGENT_VALUE)
+            .send().await {
+            Ok(res) => {
+                if res.status() != reqwest::StatusCode::OK {
+                    error!("Unexpected status code: {}", res.status());
+                    false
+                } else {
+                    true
+                }
+            },
+            Err(err) => {
+                error!("Unable to replicate update request: {}", err);
+                false
+            }
+        };
+        self.record("update", is_success, start);
+    }
+
+    /// Fetches the leases of every instance from the node.
+    pub async fn get_every_lease(&self) -> Option<Vec<LeaseInfo>> {
+        let url = format!("http://{}/api/v1/leases", self.url);
+        match self.client.get(&url)
+            .basic_auth(&self.username, Some(&self.password))
+            .header(REPLICATION_HEADER, "true")
+            .header(USER_AGENT_KEY, USER_AGENT_VALUE)
+            .send().await {
+            Ok(res) => {
+                if res.status() == reqwest::StatusCode::OK {
+                    match res.json().await {
+                        Ok(lease_infos) => Some(lease_infos),
+                        Err(err) => {
+                            error!("Unable to parse leases from {}: {}", self.url, err);
+                            None
+                        }
+                    }
+                } else {
+                    error!("Unexpected status code: {}", res.status());
+                    None
+                }
+            },
+            Err(err) => {
+                warn!("Unable to fetch leases from {}: {}", self.url, err);
+                None
             }
         }
     }
@@ -114,9 +204,9 @@ pub struct Dispatcher {
 }
 
 impl Dispatcher {
-    pub fn new(nodes_urls: Vec<SocketAddr>) -> Dispatcher {
+    pub fn new(nodes_urls: Vec<SocketAddr>, metrics: Arc<Metrics>) -> Dispatcher {
         Dispatcher {
-            nodes: nodes_urls.iter().map(|url| Arc::new(Node::new(*url))).collect()
+            nodes: nodes_urls.iter().map(|url| Arc::new(Node::new(*url, metrics.clone()))).collect()
         }
     }
 }
@@ -129,7 +219,7 @@ impl Handler<DispatcherMessage> for Dispatcher {
     type Result = ResponseFuture<Result<bool>>;
 
     fn handle(&mut self, event: DispatcherMessage, _ctx: &mut Context<Self>) -> Self::Result {
-        let nodes: Vec<Arc<Node>> = self.nodes.iter().map(|node| node.clone()).collect();
+        let nodes: Vec<Arc<Node>> = self.nodes.to_vec();
         Box::pin(async move {
             match event {
                 DispatcherMessage::Register(service_id, instance_info) => {
@@ -141,8 +231,31 @@ impl Handler<DispatcherMessage> for Dispatcher {
                 DispatcherMessage::Cancel(service_id, instance_id) => {
                     join_all(nodes.iter().map(|node| node.cancel(&service_id, &instance_id))).await;
                 }
+                DispatcherMessage::Update(service_id, instance_id, update) => {
+                    join_all(nodes.iter().map(|node| node.update(&service_id, &instance_id, &update))).await;
+                }
             };
             Ok(true)
         })
     }
 }
+
+impl Handler<SyncRequest> for Dispatcher {
+    type Result = ResponseFuture<Option<Vec<LeaseInfo>>>;
+
+    fn handle(&mut self, _: SyncRequest, _ctx: &mut Context<Self>) -> Self::Result {
+        let nodes: Vec<Arc<Node>> = self.nodes.to_vec();
+        Box::pin(async move {
+            // A registry without peers is trivially in sync
+            if nodes.is_empty() {
+                return Some(Vec::new());
+            }
+            for node in nodes.iter() {
+                if let Some(lease_infos) = node.get_every_lease().await {
+                    return Some(lease_infos);
+                }
+            }
+            None
+        })
+    }
+}
@@ -0,0 +1,114 @@
+use prometheus::{
+    Encoder, TextEncoder, Registry, IntCounter, IntCounterVec, IntGauge, HistogramVec, HistogramOpts, Opts
+};
+
+use crate::types::Result;
+
+const NAMESPACE: &str = "watchtower";
+
+/// Prometheus metrics exposed by the registry.
+pub struct Metrics {
+    registry: Registry,
+    pub registrations: IntCounter,
+    pub renewals: IntCounter,
+    pub cancellations: IntCounter,
+    pub evictions: IntCounter,
+    pub replication_successes: IntCounterVec,
+    pub replication_failures: IntCounterVec,
+    pub replication_latency: HistogramVec,
+    pub auth_failures: IntCounter,
+    pub request_latency: HistogramVec,
+    pub job_runs: IntCounterVec,
+    pub job_failures: IntCounterVec,
+    pub services: IntGauge,
+    pub instances: IntGauge
+}
+
+impl Metrics {
+    /// Creates a `Metrics` with all collectors registered.
+    pub fn new() -> Metrics {
+        let registry = Registry::new_custom(Some(NAMESPACE.to_string()), None)
+            .expect("Fails to create metrics registry");
+
+        let metrics = Metrics {
+            registrations: IntCounter::new("registrations_total", "Number of instance registrations").unwrap(),
+            renewals: IntCounter::new("renewals_total", "Number of lease renewals").unwrap(),
+            cancellations: IntCounter::new("cancellations_total", "Number of lease cancellations").unwrap(),
+            evictions: IntCounter::new("evictions_total", "Number of expired leases evicted").unwrap(),
+            replication_successes: IntCounterVec::new(
+                Opts::new("replication_successes_total", "Number of requests successfully replicated to a peer"),
+                &["peer", "operation"]
+            ).unwrap(),
+            replication_failures: IntCounterVec::new(
+                Opts::new("replication_failures_total", "Number of requests that failed to replicate to a peer"),
+                &["peer", "operation"]
+            ).unwrap(),
+            replication_latency: HistogramVec::new(
+                HistogramOpts::new("replication_duration_seconds", "Latency of replication requests to a peer"),
+                &["peer", "operation"]
+            ).unwrap(),
+            auth_failures: IntCounter::new("auth_failures_total", "Number of requests rejected as unauthorized").unwrap(),
+            request_latency: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "Latency of HTTP requests by route"),
+                &["method", "route", "status"]
+            ).unwrap(),
+            job_runs: IntCounterVec::new(
+                Opts::new("job_runs_total", "Number of runs of a scheduled job"),
+                &["job"]
+            ).unwrap(),
+            job_failures: IntCounterVec::new(
+                Opts::new("job_failures_total", "Number of failed runs of a scheduled job"),
+                &["job"]
+            ).unwrap(),
+            services: IntGauge::new("services", "Number of services in the registry").unwrap(),
+            instances: IntGauge::new("instances", "Number of instances in the registry").unwrap(),
+            registry
+        };
+
+        metrics.registry.register(Box::new(metrics.registrations.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.renewals.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.cancellations.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.evictions.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.replication_successes.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.replication_failures.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.replication_latency.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.auth_failures.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.request_latency.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.job_runs.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.job_failures.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.services.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.instances.clone())).unwrap();
+        metrics
+    }
+
+    /// Records the outcome of a replication request to a peer.
+    pub fn record_replication(&self, peer: &str, operation: &str, is_success: bool, seconds: f64) {
+        if is_success {
+            self.replication_successes.with_label_values(&[peer, operation]).inc();
+        } else {
+            self.replication_failures.with_label_values(&[peer, operation]).inc();
+        }
+        self.replication_latency.with_label_values(&[peer, operation]).observe(seconds);
+    }
+
+    /// Records the outcome of a run of a scheduled job.
+    pub fn record_job_run(&self, job: &str, is_success: bool) {
+        self.job_runs.with_label_values(&[job]).inc();
+        if !is_success {
+            self.job_failures.with_label_values(&[job]).inc();
+        }
+    }
+
+    /// Encodes all metrics in the Prometheus text format.
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
@@ -1,7 +1,15 @@
 mod registry;
 mod task_runner;
 mod dispatcher;
+mod metrics;
+mod readiness;
+mod scheduler;
+mod snapshot;
 
-pub use registry::{ServiceRegistry, InstanceInfo};
-pub use task_runner::spawn_runner;
-pub use dispatcher::{Dispatcher, DispatcherMessage};
+pub use registry::{ServiceRegistry, InstanceInfo, InstanceStatus, InstanceUpdate, LeaseInfo};
+pub use task_runner::{create_scheduler, spawn_startup_sync};
+pub use scheduler::{Scheduler, spawn_scheduler};
+pub use dispatcher::{Dispatcher, DispatcherMessage, SyncRequest};
+pub use metrics::Metrics;
+pub use readiness::{Readiness, ReadinessReport};
+pub use snapshot::{Snapshot, read_snapshot, write_snapshot};
@@ -0,0 +1,68 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex
+    },
+    time::{Duration, Instant}
+};
+use serde::Serialize;
+
+/// The eviction runner is considered stalled if it has not completed a run within this period.
+const EVICTION_STALL_THRESHOLD_SEC: u64 = 45;
+
+/// A snapshot of the registry's readiness.
+#[derive(Serialize, Debug)]
+pub struct ReadinessReport {
+    pub is_ready: bool,
+    pub is_synced: bool,
+    pub is_eviction_stalled: bool,
+    pub seconds_since_last_eviction: u64
+}
+
+/// Tracks whether the registry is ready to serve traffic.
+///
+/// The registry is ready once its startup state sync has completed, as decided by `spawn_startup_sync`, and while the
+/// eviction runner keeps running.
+pub struct Readiness {
+    is_synced: AtomicBool,
+    last_eviction_run: Mutex<Instant>
+}
+
+impl Readiness {
+    /// Creates a `Readiness` that is not yet synced.
+    pub fn new() -> Readiness {
+        Readiness {
+            is_synced: AtomicBool::new(false),
+            last_eviction_run: Mutex::new(Instant::now())
+        }
+    }
+
+    /// Marks the startup state sync as done.
+    pub fn mark_synced(&self) {
+        self.is_synced.store(true, Ordering::SeqCst);
+    }
+
+    /// Records a successful run of the eviction runner.
+    pub fn record_eviction_run(&self) {
+        *self.last_eviction_run.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns the current readiness of the registry.
+    pub fn report(&self) -> ReadinessReport {
+        let is_synced = self.is_synced.load(Ordering::SeqCst);
+        let since_last_eviction = self.last_eviction_run.lock().unwrap().elapsed();
+        let is_eviction_stalled = since_last_eviction > Duration::from_secs(EVICTION_STALL_THRESHOLD_SEC);
+        ReadinessReport {
+            is_ready: is_synced && !is_eviction_stalled,
+            is_synced,
+            is_eviction_stalled,
+            seconds_since_last_eviction: since_last_eviction.as_secs()
+        }
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness::new()
+    }
+}
@@ -1,23 +1,76 @@
 use actix::Addr;
-use std::collections::HashMap;
+use dashmap::DashMap;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError
+    }
+};
 use rand::Rng;
-use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use crate::{
     types::Result,
-    utils::time::get_time_since_epoch,
-    resources::{Dispatcher, DispatcherMessage}
+    utils::time::{Clock, MonotonicClock, get_time_since_epoch_millis},
+    resources::{Dispatcher, DispatcherMessage, SyncRequest, Metrics, Snapshot}
 };
+use log::{info, warn};
 
-const LEASE_TTL_SECONDS: u64 = 30;
+const LEASE_TTL_MS: u64 = 30_000;
 const MAX_LEASE_TO_EVICT: usize = 50;
 
+/// The status of an instance.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InstanceStatus {
+    #[default]
+    Up,
+    Down,
+    Starting,
+    OutOfService
+}
+
 /// An instance info.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct InstanceInfo {
     pub instance_id: String,
     pub ip_addr: String,
-    pub port: u16
+    pub port: u16,
+    #[serde(default)]
+    pub status: InstanceStatus,
+    /// The share of traffic the instance receives relative to the other instances of its service. 0 drains it.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Free-form attributes of the instance, such as the port of a secondary protocol
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>
+}
+
+pub const DEFAULT_WEIGHT: u32 = 1;
+
+fn default_weight() -> u32 {
+    DEFAULT_WEIGHT
+}
+
+/// A partial update of an instance info. Fields left as `None` are unchanged.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct InstanceUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<InstanceStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>
+}
+
+impl InstanceUpdate {
+    fn apply(&self, instance_info: &mut InstanceInfo) {
+        if let Some(status) = self.status {
+            instance_info.status = status;
+        }
+        if let Some(weight) = self.weight {
+            instance_info.weight = weight;
+        }
+    }
 }
 
 /// A lease information.
@@ -25,108 +78,318 @@ pub struct InstanceInfo {
 pub struct LeaseInfo {
     pub service_id: String,
     pub instance_info: InstanceInfo,
-    last_updated_timestamp: u64
+    /// Milliseconds elapsed since the lease was last renewed.
+    pub lease_age_ms: u64,
+    /// Wall-clock time of the last renewal in milliseconds since the Unix epoch, for display only.
+    pub last_renewed_timestamp_ms: u64
 }
 
 impl LeaseInfo {
     /// Returns `true` if the lease is expired.
-    /// 
-    /// The life time of a lease is limited by `LEASE_TTL_SECONDS`.
-    pub fn is_expired(&self) -> Result<bool> {
-        Ok((self.last_updated_timestamp + LEASE_TTL_SECONDS) < get_time_since_epoch()?)
+    ///
+    /// The life time of a lease is limited by `LEASE_TTL_MS`.
+    pub fn is_expired(&self) -> bool {
+        self.lease_age_ms > LEASE_TTL_MS
+    }
+}
+
+/// A lease held by a registered instance.
+///
+/// The timestamp is atomic so that renewing a lease only needs a read lock on its service.
+struct Lease {
+    instance_info: InstanceInfo,
+    generation: u64,
+    last_renewed_millis: AtomicU64
+}
+
+impl Lease {
+    fn expiry_millis(&self) -> u64 {
+        self.last_renewed_millis.load(Ordering::Relaxed) + LEASE_TTL_MS
+    }
+
+    fn to_lease_info(&self, service_id: &str, now: u64) -> LeaseInfo {
+        let lease_age_ms = now.saturating_sub(self.last_renewed_millis.load(Ordering::Relaxed));
+        LeaseInfo {
+            service_id: service_id.to_string(),
+            instance_info: self.instance_info.clone(),
+            lease_age_ms,
+            last_renewed_timestamp_ms: get_time_since_epoch_millis().saturating_sub(lease_age_ms)
+        }
     }
 }
 
-/// A type alias for a hashmap with `LeaseInfo` as its values.
-pub type LeaseHashMap = HashMap<String, LeaseInfo>;
+/// An entry of the expiry index.
+///
+/// Entries are not updated when a lease is renewed. Instead, an entry whose lease has been renewed
+/// is pushed back with the new expiry when it is popped.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct ExpiryEntry {
+    expiry_millis: u64,
+    service_id: String,
+    instance_id: String,
+    generation: u64
+}
+
+/// A type alias for a hashmap with `Lease` as its values.
+type LeaseHashMap = HashMap<String, Lease>;
 
 /// A service registry for storing information about services and their leases.
+///
+/// Services are stored in a sharded concurrent map. Renewals and reads only take a read lock on the
+/// shard of their service, while registrations and cancellations take a write lock.
+/// No lock is held while replicating to other nodes.
+///
+/// Lease expiry is measured on a monotonic `Clock`, so changes to the system time do not affect it.
 pub struct ServiceRegistry {
-    services: RwLock<HashMap<String, LeaseHashMap>>,
-    dispatcher: Addr<Dispatcher>
+    services: DashMap<String, LeaseHashMap>,
+    expiry_index: Mutex<BinaryHeap<Reverse<ExpiryEntry>>>,
+    next_generation: AtomicU64,
+    dispatcher: Addr<Dispatcher>,
+    metrics: Arc<Metrics>,
+    clock: Arc<dyn Clock>
 }
 
 impl ServiceRegistry {
     /// Creates a `serviceRegistry`.
-    pub fn new(dispatcher: Addr<Dispatcher>) -> ServiceRegistry {
+    pub fn new(dispatcher: Addr<Dispatcher>, metrics: Arc<Metrics>) -> ServiceRegistry {
+        ServiceRegistry::with_clock(dispatcher, metrics, Arc::new(MonotonicClock::new()))
+    }
+
+    /// Creates a `serviceRegistry` that measures leases with `clock`.
+    pub fn with_clock(dispatcher: Addr<Dispatcher>, metrics: Arc<Metrics>, clock: Arc<dyn Clock>) -> ServiceRegistry {
         ServiceRegistry {
-            services: RwLock::new(HashMap::new()),
-            dispatcher
+            services: DashMap::new(),
+            expiry_index: Mutex::new(BinaryHeap::new()),
+            next_generation: AtomicU64::new(0),
+            dispatcher,
+            metrics,
+            clock
         }
     }
 
+    /// Locks the expiry index.
+    ///
+    /// A panic while the index is locked, such as in a job caught by the scheduler, poisons it. Entries are only ever
+    /// pushed and popped whole, so the index is still consistent and the poison is ignored.
+    fn lock_expiry_index(&self) -> MutexGuard<'_, BinaryHeap<Reverse<ExpiryEntry>>> {
+        self.expiry_index.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     /// Runs the service registry.
     pub async fn run(&self) -> Result<()> {
         self.evict().await?;
         Ok(())
     }
 
-    /// Registers a new service.
-    pub async fn register_instance(&self, service_id: &str, instance_info: InstanceInfo, is_replicated: bool) -> Result<()> {
-        let mut services = self.services.write().await;
-        if !services.contains_key(service_id) {
-            services.insert(service_id.to_string(), HashMap::new());
+    /// Merges the leases of the first reachable peer into this registry.
+    ///
+    /// Returns `false` if no peer could be reached.
+    pub async fn sync_from_peers(&self) -> Result<bool> {
+        match self.dispatcher.send(SyncRequest).await? {
+            Some(lease_infos) => {
+                let merged_count = lease_infos.into_iter()
+                    .filter(|lease_info| self.merge_lease(lease_info))
+                    .count();
+                info!("Synced {} instances from peers", merged_count);
+                Ok(true)
+            },
+            None => {
+                warn!("Unable to sync from any peer");
+                Ok(false)
+            }
         }
+    }
 
-        if let Some(service) = services.get_mut(service_id) {
-            service.insert(instance_info.instance_id.to_string(), LeaseInfo {
-                instance_info: instance_info.clone(),
-                service_id: service_id.to_string(),
-                last_updated_timestamp: get_time_since_epoch()?
+    /// Registers a new service.
+    pub async fn register_instance(&self, service_id: &str, instance_info: InstanceInfo, is_replicated: bool) -> Result<()> {
+        let now = self.clock.now_millis();
+        let new_entry = {
+            let mut service = self.services.entry(service_id.to_string()).or_insert_with(|| {
+                self.metrics.services.inc();
+                HashMap::new()
             });
 
-            if !is_replicated {
-                self.dispatcher.send(DispatcherMessage::Register(service_id.to_string(), instance_info)).await??;
+            match service.get_mut(&instance_info.instance_id) {
+                Some(lease) => {
+                    lease.instance_info = instance_info.clone();
+                    lease.last_renewed_millis.store(now, Ordering::Relaxed);
+                    None
+                },
+                None => {
+                    let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+                    service.insert(instance_info.instance_id.to_string(), Lease {
+                        instance_info: instance_info.clone(),
+                        generation,
+                        last_renewed_millis: AtomicU64::new(now)
+                    });
+                    self.metrics.instances.inc();
+                    Some(ExpiryEntry {
+                        expiry_millis: now + LEASE_TTL_MS,
+                        service_id: service_id.to_string(),
+                        instance_id: instance_info.instance_id.to_string(),
+                        generation
+                    })
+                }
             }
+        };
+        // The expiry index is only locked once the service is released to keep a single lock order
+        if let Some(entry) = new_entry {
+            self.lock_expiry_index().push(Reverse(entry));
+        }
+        self.metrics.registrations.inc();
+
+        if !is_replicated {
+            self.dispatcher.send(DispatcherMessage::Register(service_id.to_string(), instance_info)).await??;
         }
         Ok(())
     }
 
-    /// Renews a lease by updating its `last_updated_timestamp`.
-    /// 
-    /// If the lease does not exists, this method will return false.
-    pub async fn renew_lease(&self, service_id: &str, instance_id: &str, is_replicated: bool) -> Result<bool> {
-        let mut services = self.services.write().await;
-        if let Some(service) = services.get_mut(service_id) {
-            if let Some(lease) = service.get_mut(instance_id) {
-                lease.last_updated_timestamp = get_time_since_epoch()?;
-                if !is_replicated {
-                    self.dispatcher.send(DispatcherMessage::Renew(service_id.to_string(), lease.instance_info.clone())).await??;
+    /// Merges a lease of a peer into this registry, keeping the most recent renewal.
+    ///
+    /// The age of the lease is kept, so that merging never extends a lease beyond its last renewal.
+    /// Expired leases are skipped and the instance info of a known lease is left unchanged.
+    /// Returns `true` if the lease was added or renewed.
+    pub fn merge_lease(&self, lease_info: &LeaseInfo) -> bool {
+        if lease_info.is_expired() {
+            return false;
+        }
+        let now = self.clock.now_millis();
+        let renewed_millis = now.saturating_sub(lease_info.lease_age_ms);
+        let instance_info = &lease_info.instance_info;
+        let new_entry = {
+            let mut service = self.services.entry(lease_info.service_id.to_string()).or_insert_with(|| {
+                self.metrics.services.inc();
+                HashMap::new()
+            });
+
+            match service.get(&instance_info.instance_id) {
+                Some(lease) => {
+                    let previous_millis = lease.last_renewed_millis.fetch_max(renewed_millis, Ordering::Relaxed);
+                    return previous_millis < renewed_millis;
+                },
+                None => {
+                    let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+                    service.insert(instance_info.instance_id.to_string(), Lease {
+                        instance_info: instance_info.clone(),
+                        generation,
+                        last_renewed_millis: AtomicU64::new(renewed_millis)
+                    });
+                    self.metrics.instances.inc();
+                    ExpiryEntry {
+                        expiry_millis: renewed_millis + LEASE_TTL_MS,
+                        service_id: lease_info.service_id.to_string(),
+                        instance_id: instance_info.instance_id.to_string(),
+                        generation
+                    }
                 }
-                return Ok(true)
             }
+        };
+        self.lock_expiry_index().push(Reverse(new_entry));
+        true
+    }
+
+    /// Restores the instances of `snapshot` with fresh leases, without replicating them.
+    ///
+    /// The snapshot does not record lease ages, so instances that are no longer running are only evicted once their
+    /// restored lease expires. Returns the number of instances that were added or renewed.
+    pub fn restore_snapshot(&self, snapshot: Snapshot) -> usize {
+        let now = get_time_since_epoch_millis();
+        snapshot.services.into_iter()
+            .flat_map(|(service_id, instance_infos)| instance_infos.into_iter().map(move |instance_info| LeaseInfo {
+                service_id: service_id.clone(),
+                instance_info,
+                lease_age_ms: 0,
+                last_renewed_timestamp_ms: now
+            }))
+            .filter(|lease_info| self.merge_lease(lease_info))
+            .count()
+    }
+
+    /// Renews a lease by updating its `last_renewed_millis`.
+    ///
+    /// If the lease does not exists, this method will return false.
+    pub async fn renew_lease(&self, service_id: &str, instance_id: &str, is_replicated: bool) -> Result<bool> {
+        let now = self.clock.now_millis();
+        let instance_info = match self.services.get(service_id) {
+            Some(service) => match service.get(instance_id) {
+                Some(lease) => {
+                    lease.last_renewed_millis.store(now, Ordering::Relaxed);
+                    lease.instance_info.clone()
+                },
+                None => return Ok(false)
+            },
+            None => return Ok(false)
+        };
+        self.metrics.renewals.inc();
+
+        if !is_replicated {
+            self.dispatcher.send(DispatcherMessage::Renew(service_id.to_string(), instance_info)).await??;
         }
-        Ok(false)
+        Ok(true)
+    }
+
+    /// Updates the instance info of a lease without renewing it.
+    ///
+    /// If the lease does not exists, this method will return false.
+    pub async fn update_instance(&self, service_id: &str, instance_id: &str, update: InstanceUpdate, is_replicated: bool) -> Result<bool> {
+        match self.services.get_mut(service_id) {
+            Some(mut service) => match service.get_mut(instance_id) {
+                Some(lease) => update.apply(&mut lease.instance_info),
+                None => return Ok(false)
+            },
+            None => return Ok(false)
+        };
+
+        if !is_replicated {
+            self.dispatcher.send(DispatcherMessage::Update(service_id.to_string(), instance_id.to_string(), update)).await??;
+        }
+        Ok(true)
     }
 
     /// Cancels a lease by removing it from the `ServiceRegistry`.
-    /// 
+    ///
     /// If the lease does not exists, this method will return None.
     pub async fn cancel_lease(&self, service_id: &str, instance_id: &str, is_replicated: bool) -> Result<Option<LeaseInfo>> {
-        let mut services = self.services.write().await;
-        match services.get_mut(service_id) {
-            Some(service) => {
-                let lease_option = service.remove(instance_id);
-                if !is_replicated {   
-                    if let Some(_) = &lease_option {
-                        self.dispatcher.send(DispatcherMessage::Cancel(service_id.to_string(), instance_id.to_string())).await??;
-                    }
-                }
+        let now = self.clock.now_millis();
+        let lease_option = self.remove_lease(service_id, instance_id, now, |_| true);
+        if lease_option.is_some() {
+            self.metrics.cancellations.inc();
+            if !is_replicated {
+                self.dispatcher.send(DispatcherMessage::Cancel(service_id.to_string(), instance_id.to_string())).await??;
+            }
+        }
+        Ok(lease_option)
+    }
 
-                if service.is_empty() {
-                    services.remove(service_id);
+    /// Removes a lease from the `ServiceRegistry` if it satisfies `predicate`.
+    ///
+    /// The service is removed as well once its last lease is gone.
+    fn remove_lease<F: FnOnce(&Lease) -> bool>(&self, service_id: &str, instance_id: &str, now: u64, predicate: F) -> Option<LeaseInfo> {
+        let lease_option = match self.services.get_mut(service_id) {
+            Some(mut service) => {
+                if service.get(instance_id).is_some_and(predicate) {
+                    service.remove(instance_id).map(|lease| lease.to_lease_info(service_id, now))
+                } else {
+                    None
                 }
-                
-                Ok(lease_option)
             },
-            None => Ok(None)
+            None => None
+        };
+
+        if lease_option.is_some() {
+            self.metrics.instances.dec();
+            if self.services.remove_if(service_id, |_, service| service.is_empty()).is_some() {
+                self.metrics.services.dec();
+            }
         }
+        lease_option
     }
 
     /// Evicts expired instances.
-    /// 
+    ///
     /// The number of evict instances will be limited by `MAX_LEASE_TO_EVICT`.
     pub async fn evict(&self) -> Result<()> {
+        let now = self.clock.now_millis();
         let mut expired_leases = self.get_expired_instances().await?;
         let to_evict = std::cmp::min(expired_leases.len(), MAX_LEASE_TO_EVICT);
         for i in 0..to_evict {
@@ -138,33 +401,255 @@ impl ServiceRegistry {
             expired_leases.swap(i, next);
 
             let lease = &expired_leases[i];
-            self.cancel_lease(&lease.service_id, &lease.instance_info.instance_id, true).await?;
+            // The lease may have been renewed since it was found to be expired
+            let is_expired = |lease: &Lease| lease.expiry_millis() < now;
+            if self.remove_lease(&lease.service_id, &lease.instance_info.instance_id, now, is_expired).is_some() {
+                self.metrics.evictions.inc();
+            }
         }
         Ok(())
     }
 
     /// Returns all expired instances.
+    ///
+    /// Only the leases whose indexed expiry has passed are inspected.
     pub async fn get_expired_instances(&self) -> Result<Vec<LeaseInfo>> {
-        let services = self.services.read().await;
+        let now = self.clock.now_millis();
+
+        let mut due_entries = Vec::new();
+        {
+            let mut expiry_index = self.lock_expiry_index();
+            while let Some(Reverse(entry)) = expiry_index.peek() {
+                if entry.expiry_millis >= now {
+                    break;
+                }
+                if let Some(Reverse(entry)) = expiry_index.pop() {
+                    due_entries.push(entry);
+                }
+            }
+        }
 
         let mut expired_leases = Vec::new();
-        for (_, leases) in services.iter() {
-            for (_, lease) in leases.iter() {
-                if lease.is_expired()? {
-                    expired_leases.push(lease.clone());
+        let mut requeued_entries = Vec::new();
+        for entry in due_entries {
+            if let Some(service) = self.services.get(&entry.service_id) {
+                if let Some(lease) = service.get(&entry.instance_id) {
+                    // Entries of cancelled leases are dropped
+                    if lease.generation == entry.generation {
+                        let expiry_millis = lease.expiry_millis();
+                        if expiry_millis < now {
+                            expired_leases.push(lease.to_lease_info(&entry.service_id, now));
+                        }
+                        requeued_entries.push(Reverse(ExpiryEntry { expiry_millis, ..entry }));
+                    }
                 }
             }
         }
+        self.lock_expiry_index().extend(requeued_entries);
         Ok(expired_leases)
     }
 
+    /// Returns the number of services and instances in the registry.
+    pub async fn count(&self) -> (usize, usize) {
+        self.services.iter()
+            .fold((0, 0), |(services, instances), service| (services + 1, instances + service.len()))
+    }
+
+    /// Returns the `InstanceInfo` of every instance, grouped by service.
+    pub async fn get_all_services(&self) -> HashMap<String, Vec<InstanceInfo>> {
+        self.services.iter()
+            .map(|service| (service.key().to_string(), service.values().map(|lease| lease.instance_info.clone()).collect()))
+            .collect()
+    }
+
     /// Returns all the `InstanceInfo` of the interested service.
     pub async fn get_all_instances(&self, service_id: &str) -> Option<Vec<InstanceInfo>> {
-        let services = self.services.read().await;
-        if let Some(leases) = services.get(service_id) {
-            Some(leases.values().cloned().map(|lease| lease.instance_info).collect())
-        } else {
-            None
+        self.services.get(service_id)
+            .map(|leases| leases.values().map(|lease| lease.instance_info.clone()).collect())
+    }
+
+    /// Returns the `LeaseInfo` of every instance.
+    pub async fn get_every_lease(&self) -> Vec<LeaseInfo> {
+        let now = self.clock.now_millis();
+        self.services.iter()
+            .flat_map(|service| service.values().map(|lease| lease.to_lease_info(service.key(), now)).collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// Returns the `LeaseInfo` of every instance of the interested service.
+    pub async fn get_all_leases(&self, service_id: &str) -> Option<Vec<LeaseInfo>> {
+        let now = self.clock.now_millis();
+        self.services.get(service_id)
+            .map(|leases| leases.values().map(|lease| lease.to_lease_info(service_id, now)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Actor;
+    use std::time::Duration;
+    use crate::utils::time::ManualClock;
+
+    fn create_registry(clock: Arc<ManualClock>) -> ServiceRegistry {
+        let metrics = Arc::new(Metrics::new());
+        let dispatcher = Dispatcher::new(vec![], metrics.clone()).start();
+        ServiceRegistry::with_clock(dispatcher, metrics, clock)
+    }
+
+    fn create_instance(instance_id: &str) -> InstanceInfo {
+        InstanceInfo {
+            instance_id: instance_id.to_string(),
+            ip_addr: "0.0.0.0".to_string(),
+            port: 8888,
+            status: InstanceStatus::Up,
+            weight: 1,
+            metadata: HashMap::new()
         }
     }
+
+    #[actix_rt::test]
+    async fn test_evict_expired_instances() {
+        let clock = Arc::new(ManualClock::new());
+        let registry = create_registry(clock.clone());
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+        registry.register_instance("test", create_instance("test2"), true).await.unwrap();
+
+        clock.advance(Duration::from_secs(20));
+        assert!(registry.renew_lease("test", "test1", true).await.unwrap());
+        clock.advance(Duration::from_secs(15));
+        registry.evict().await.unwrap();
+
+        let instances = registry.get_all_instances("test").await.unwrap();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].instance_id, "test1");
+
+        clock.advance(Duration::from_secs(31));
+        registry.evict().await.unwrap();
+        assert!(registry.get_all_instances("test").await.is_none());
+        assert_eq!(registry.count().await, (0, 0));
+    }
+
+    #[actix_rt::test]
+    async fn test_lease_expiry_precision() {
+        let clock = Arc::new(ManualClock::new());
+        let registry = create_registry(clock.clone());
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+
+        clock.advance(Duration::from_millis(LEASE_TTL_MS));
+        assert!(registry.get_expired_instances().await.unwrap().is_empty());
+
+        clock.advance(Duration::from_millis(1));
+        let expired_leases = registry.get_expired_instances().await.unwrap();
+        assert_eq!(expired_leases.len(), 1);
+        assert!(expired_leases[0].is_expired());
+        assert_eq!(expired_leases[0].lease_age_ms, LEASE_TTL_MS + 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_cancelled_lease_is_not_evicted() {
+        let clock = Arc::new(ManualClock::new());
+        let registry = create_registry(clock.clone());
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+        assert!(registry.cancel_lease("test", "test1", true).await.unwrap().is_some());
+
+        // A new lease for the same instance must not be tracked by the expiry of the cancelled one
+        clock.advance(Duration::from_secs(10));
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+        clock.advance(Duration::from_secs(25));
+        assert!(registry.get_expired_instances().await.unwrap().is_empty());
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(registry.get_expired_instances().await.unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_all_leases() {
+        let clock = Arc::new(ManualClock::new());
+        let registry = create_registry(clock.clone());
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+        clock.advance(Duration::from_secs(10));
+        registry.register_instance("test", create_instance("test2"), true).await.unwrap();
+        clock.advance(Duration::from_secs(5));
+
+        let mut leases = registry.get_all_leases("test").await.unwrap();
+        leases.sort_by(|a, b| a.instance_info.instance_id.cmp(&b.instance_info.instance_id));
+        assert_eq!(leases.iter().map(|lease| lease.lease_age_ms).collect::<Vec<_>>(), vec![15_000, 5_000]);
+        assert!(leases.iter().all(|lease| lease.service_id == "test"));
+        assert!(registry.get_all_leases("other").await.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_merge_lease_keeps_lease_age() {
+        let clock = Arc::new(ManualClock::new());
+        clock.advance(Duration::from_secs(60));
+        let registry = create_registry(clock.clone());
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+
+        let lease_info = |instance_id: &str, lease_age_ms: u64| LeaseInfo {
+            service_id: "test".to_string(),
+            instance_info: create_instance(instance_id),
+            lease_age_ms,
+            last_renewed_timestamp_ms: 0
+        };
+        assert!(registry.merge_lease(&lease_info("test2", 20_000)));
+        assert!(!registry.merge_lease(&lease_info("test3", LEASE_TTL_MS + 1)));
+        // An older renewal of a known lease does not extend it
+        clock.advance(Duration::from_secs(5));
+        assert!(!registry.merge_lease(&lease_info("test1", 10_000)));
+        assert!(registry.merge_lease(&lease_info("test1", 1_000)));
+
+        let mut leases = registry.get_every_lease().await;
+        leases.sort_by(|a, b| a.instance_info.instance_id.cmp(&b.instance_info.instance_id));
+        assert_eq!(leases.iter().map(|lease| lease.lease_age_ms).collect::<Vec<_>>(), vec![1_000, 25_000]);
+
+        clock.advance(Duration::from_secs(6));
+        let expired_leases = registry.get_expired_instances().await.unwrap();
+        assert_eq!(expired_leases.len(), 1);
+        assert_eq!(expired_leases[0].instance_info.instance_id, "test2");
+    }
+
+    #[actix_rt::test]
+    async fn test_panic_does_not_break_registry() {
+        let clock = Arc::new(ManualClock::new());
+        let registry = create_registry(clock.clone());
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _expiry_index = registry.lock_expiry_index();
+            panic!("job panicked");
+        }));
+        assert!(panic.is_err());
+        assert!(registry.expiry_index.is_poisoned());
+
+        registry.register_instance("test", create_instance("test2"), true).await.unwrap();
+        clock.advance(Duration::from_secs(20));
+        assert!(registry.renew_lease("test", "test2", true).await.unwrap());
+        clock.advance(Duration::from_secs(15));
+        registry.evict().await.unwrap();
+
+        let instances = registry.get_all_instances("test").await.unwrap();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].instance_id, "test2");
+    }
+
+    #[actix_rt::test]
+    async fn test_update_instance() {
+        let registry = create_registry(Arc::new(ManualClock::new()));
+        registry.register_instance("test", create_instance("test1"), true).await.unwrap();
+
+        let update = InstanceUpdate { status: Some(InstanceStatus::OutOfService), weight: None };
+        assert!(registry.update_instance("test", "test1", update.clone(), true).await.unwrap());
+        assert!(!registry.update_instance("test", "test2", update, true).await.unwrap());
+
+        let instances = registry.get_all_instances("test").await.unwrap();
+        assert_eq!(instances[0].status, InstanceStatus::OutOfService);
+        assert_eq!(instances[0].weight, DEFAULT_WEIGHT);
+
+        let update = InstanceUpdate { status: None, weight: Some(0) };
+        assert!(registry.update_instance("test", "test1", update, true).await.unwrap());
+        let instances = registry.get_all_instances("test").await.unwrap();
+        assert_eq!(instances[0].status, InstanceStatus::OutOfService);
+        assert_eq!(instances[0].weight, 0);
+    }
 }
@@ -0,0 +1,196 @@
+use actix_web::web::Data;
+use futures_util::future::{Future, FutureExt, LocalBoxFuture};
+use std::{
+    any::Any,
+    cmp::min,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+use serde::Serialize;
+use log::error;
+
+use crate::types::{AppState, Result};
+
+const BASE_BACKOFF_MS: u64 = 500;
+const MAX_BACKOFF_SEC: u64 = 60;
+
+type Task = Box<dyn Fn(Data<AppState>) -> LocalBoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// The run history of a job.
+#[derive(Default)]
+struct JobHistory {
+    runs: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    last_success: Option<Instant>,
+    next_run: Option<Instant>
+}
+
+/// A periodic job run by the `Scheduler`.
+struct Job {
+    name: String,
+    interval: Duration,
+    task: Task,
+    history: Mutex<JobHistory>
+}
+
+/// A snapshot of a job's status.
+#[derive(Serialize, Debug)]
+pub struct JobReport {
+    pub name: String,
+    pub interval_seconds: u64,
+    pub runs: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub seconds_since_last_success: Option<u64>,
+    pub seconds_until_next_run: Option<u64>
+}
+
+impl Job {
+    /// Records the outcome of a run and returns the delay before the next one.
+    ///
+    /// Failed runs are retried with an exponential backoff capped by `MAX_BACKOFF_SEC` and the job's interval.
+    fn record(&self, outcome: std::result::Result<(), String>) -> Duration {
+        let mut history = self.history.lock().unwrap();
+        history.runs += 1;
+        let delay = match outcome {
+            Ok(()) => {
+                history.consecutive_failures = 0;
+                history.last_success = Some(Instant::now());
+                self.interval
+            },
+            Err(message) => {
+                error!("Job {} failed: {}", self.name, message);
+                history.failures += 1;
+                history.consecutive_failures += 1;
+                history.last_error = Some(message);
+                let exponent = min(history.consecutive_failures - 1, 16);
+                let backoff = Duration::from_millis(BASE_BACKOFF_MS * 2u64.pow(exponent));
+                min(backoff, min(self.interval, Duration::from_secs(MAX_BACKOFF_SEC)))
+            }
+        };
+        history.next_run = Some(Instant::now() + delay);
+        delay
+    }
+
+    fn report(&self) -> JobReport {
+        let history = self.history.lock().unwrap();
+        JobReport {
+            name: self.name.to_string(),
+            interval_seconds: self.interval.as_secs(),
+            runs: history.runs,
+            failures: history.failures,
+            consecutive_failures: history.consecutive_failures,
+            last_error: history.last_error.clone(),
+            seconds_since_last_success: history.last_success.map(|instant| instant.elapsed().as_secs()),
+            seconds_until_next_run: history.next_run
+                .map(|instant| instant.saturating_duration_since(Instant::now()).as_secs())
+        }
+    }
+}
+
+/// A scheduler that runs periodic jobs and keeps them alive through errors and panics.
+pub struct Scheduler {
+    jobs: Vec<Arc<Job>>
+}
+
+impl Scheduler {
+    /// Creates a `Scheduler` with no jobs.
+    pub fn new() -> Scheduler {
+        Scheduler {
+            jobs: Vec::new()
+        }
+    }
+
+    /// Registers a job to be run every `interval` once the scheduler is spawned.
+    pub fn register<F, Fut>(&mut self, name: &str, interval: Duration, task: F)
+    where
+        F: Fn(Data<AppState>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + 'static
+    {
+        self.jobs.push(Arc::new(Job {
+            name: name.to_string(),
+            interval,
+            task: Box::new(move |app_state| task(app_state).boxed_local()),
+            history: Mutex::new(JobHistory::default())
+        }));
+    }
+
+    /// Returns the status of every registered job.
+    pub fn report(&self) -> Vec<JobReport> {
+        self.jobs.iter().map(|job| job.report()).collect()
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        format!("panicked: {}", message)
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        format!("panicked: {}", message)
+    } else {
+        "panicked".to_string()
+    }
+}
+
+/// Generate a background task for every job registered on the scheduler of `app_state`.
+pub fn spawn_scheduler(app_state: Data<AppState>) {
+    for job in app_state.scheduler.jobs.iter() {
+        let job = job.clone();
+        let app_state = app_state.clone();
+        actix::spawn(async move {
+            loop {
+                let outcome = match AssertUnwindSafe((job.task)(app_state.clone())).catch_unwind().await {
+                    Ok(Ok(())) => Ok(()),
+                    Ok(Err(error)) => Err(error.to_string()),
+                    Err(panic) => Err(panic_message(panic))
+                };
+                let is_success = outcome.is_ok();
+                app_state.metrics.record_job_run(&job.name, is_success);
+                tokio::time::delay_for(job.record(outcome)).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_job(interval: Duration) -> Job {
+        Job {
+            name: "test".to_string(),
+            interval,
+            task: Box::new(|_| async { Ok(()) }.boxed_local()),
+            history: Mutex::new(JobHistory::default())
+        }
+    }
+
+    #[test]
+    fn test_record_backoff() {
+        let job = create_job(Duration::from_secs(15));
+        assert_eq!(job.record(Err("error".to_string())), Duration::from_millis(BASE_BACKOFF_MS));
+        assert_eq!(job.record(Err("error".to_string())), Duration::from_millis(BASE_BACKOFF_MS * 2));
+        assert_eq!(job.record(Err("error".to_string())), Duration::from_millis(BASE_BACKOFF_MS * 4));
+        for _ in 0..10 {
+            job.record(Err("error".to_string()));
+        }
+        assert_eq!(job.record(Err("error".to_string())), Duration::from_secs(15));
+        assert_eq!(job.record(Ok(())), Duration::from_secs(15));
+
+        let report = job.report();
+        assert_eq!(report.runs, 15);
+        assert_eq!(report.failures, 14);
+        assert_eq!(report.consecutive_failures, 0);
+        assert_eq!(report.last_error, Some("error".to_string()));
+        assert_eq!(report.seconds_since_last_success, Some(0));
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf}
+};
+use serde::{Serialize, Deserialize};
+use log::{info, warn};
+
+use crate::{
+    types::{InstanceInfo, Result},
+    utils::time::get_time_since_epoch_millis
+};
+
+/// The version of the snapshot format, shared with `watchtowerctl snapshot restore`.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The instances of every service at a point in time.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub version: u32,
+    pub taken_at_ms: u64,
+    pub services: BTreeMap<String, Vec<InstanceInfo>>
+}
+
+impl Snapshot {
+    pub fn new<I: IntoIterator<Item = (String, Vec<InstanceInfo>)>>(services: I) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            taken_at_ms: get_time_since_epoch_millis(),
+            services: services.into_iter().collect()
+        }
+    }
+}
+
+/// Writes `snapshot` to `path` on a blocking thread.
+///
+/// The snapshot is written to a temporary file first and renamed, so that `path` always holds a complete snapshot.
+pub async fn write_snapshot(snapshot: Snapshot, path: PathBuf) -> Result<()> {
+    let contents = serde_json::to_vec_pretty(&snapshot)?;
+    let instance_count: usize = snapshot.services.values().map(|instance_infos| instance_infos.len()).sum();
+    actix_web::web::block(move || {
+        let temp_path = temp_path(&path);
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, &path)
+    }).await?;
+    info!("Saved a snapshot of {} instances of {} services", instance_count, snapshot.services.len());
+    Ok(())
+}
+
+/// Reads the snapshot at `path` on a blocking thread.
+///
+/// Returns `None` if there is no snapshot at `path`, or if it was written in an unsupported format.
+pub async fn read_snapshot(path: PathBuf) -> Result<Option<Snapshot>> {
+    let contents = actix_web::web::block(move || match std::fs::read(&path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err)
+    }).await?;
+    let snapshot: Snapshot = match contents {
+        Some(contents) => serde_json::from_slice(&contents)?,
+        None => return Ok(None)
+    };
+    if snapshot.version != SNAPSHOT_VERSION {
+        warn!("Ignoring a snapshot of unsupported version {}, expected {}", snapshot.version, SNAPSHOT_VERSION);
+        return Ok(None);
+    }
+    Ok(Some(snapshot))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(file_name)
+}
@@ -1,17 +1,173 @@
 use actix_web::web::Data;
-use std::time::Duration;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant}
+};
+use log::{error, info, warn};
 
-use crate::types::AppState;
+use crate::{
+    resources::{Scheduler, Snapshot, read_snapshot, write_snapshot},
+    types::AppState,
+    utils::env
+};
 
-const RUN_INTERVAL_SEC: u64 = 15;
+const EVICTION_INTERVAL_SEC: u64 = 15;
+const METRICS_INTERVAL_SEC: u64 = 60;
+const ANTI_ENTROPY_INTERVAL_SEC: u64 = 60;
+const SNAPSHOT_INTERVAL_SEC: u64 = 300;
+const STARTUP_SYNC_RETRY_SEC: u64 = 5;
+const STARTUP_SYNC_TIMEOUT_SEC: u64 = 300;
 
-/// Generate a background task to evict expired leases 
-pub fn spawn_runner (app_state: Data<AppState>) {
-    actix::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(RUN_INTERVAL_SEC));
-        loop {
-            interval.tick().await;
-            app_state.service_registry.run().await.expect("Service registry failed to execute!");
+/// Creates a `Scheduler` with the registry's periodic jobs.
+///
+/// - `eviction` evicts expired leases.
+/// - `metrics` reconciles the service and instance gauges with the registry.
+/// - `anti-entropy` merges the leases of a peer, to repair replication requests that were lost.
+/// - `snapshot` writes the registry state to the file set by `SNAPSHOT_FILE`, if any.
+pub fn create_scheduler() -> Scheduler {
+    let mut scheduler = Scheduler::new();
+    scheduler.register("eviction", Duration::from_secs(EVICTION_INTERVAL_SEC), |app_state: Data<AppState>| async move {
+        app_state.service_registry.run().await?;
+        app_state.readiness.record_eviction_run();
+        Ok(())
+    });
+    scheduler.register("metrics", Duration::from_secs(METRICS_INTERVAL_SEC), |app_state: Data<AppState>| async move {
+        let (services, instances) = app_state.service_registry.count().await;
+        app_state.metrics.services.set(services as i64);
+        app_state.metrics.instances.set(instances as i64);
+        Ok(())
+    });
+    scheduler.register("anti-entropy", Duration::from_secs(ANTI_ENTROPY_INTERVAL_SEC), |app_state: Data<AppState>| async move {
+        app_state.service_registry.sync_from_peers().await?;
+        Ok(())
+    });
+    scheduler.register("snapshot", Duration::from_secs(SNAPSHOT_INTERVAL_SEC), |app_state: Data<AppState>| async move {
+        if let Some(path) = env::get_snapshot_file() {
+            let snapshot = Snapshot::new(app_state.service_registry.get_all_services().await);
+            write_snapshot(snapshot, path).await?;
         }
+        Ok(())
+    });
+    scheduler
+}
+
+/// Generate a background task to sync the registry on startup and mark it as ready once done
+pub fn spawn_startup_sync(app_state: Data<AppState>) {
+    actix::spawn(async move {
+        sync_on_startup(&app_state, env::get_snapshot_file(), Duration::from_secs(STARTUP_SYNC_TIMEOUT_SEC)).await;
     });
-}
\ No newline at end of file
+}
+
+/// Replays the snapshot at `snapshot_file`, if any, then syncs the registry from its peers and marks it as ready.
+///
+/// The registry is marked as ready once it has synced, which it trivially has without peers, or once it has replayed
+/// a snapshot and tried every peer. Otherwise, syncing is retried until `timeout` and the registry starts empty.
+async fn sync_on_startup(app_state: &AppState, snapshot_file: Option<PathBuf>, timeout: Duration) {
+    let is_replayed = match snapshot_file {
+        Some(path) => replay_snapshot(app_state, path).await,
+        None => false
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        match app_state.service_registry.sync_from_peers().await {
+            Ok(true) => break,
+            Ok(false) => (),
+            Err(error) => error!("Startup sync failed: {}", error)
+        }
+        if is_replayed {
+            warn!("Starting with the replayed snapshot until a peer is reachable");
+            break;
+        }
+        if Instant::now() >= deadline {
+            warn!("No peer was reachable within {:?}, starting with an empty registry", timeout);
+            break;
+        }
+        tokio::time::delay_for(Duration::from_secs(STARTUP_SYNC_RETRY_SEC)).await;
+    }
+    app_state.readiness.mark_synced();
+}
+
+/// Restores the snapshot at `path` into the registry, and returns `true` if any instance was restored.
+async fn replay_snapshot(app_state: &AppState, path: PathBuf) -> bool {
+    match read_snapshot(path).await {
+        Ok(Some(snapshot)) => {
+            let restored_count = app_state.service_registry.restore_snapshot(snapshot);
+            info!("Restored {} instances from the snapshot", restored_count);
+            restored_count > 0
+        },
+        Ok(None) => false,
+        Err(error) => {
+            error!("Unable to read the snapshot: {}", error);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Actor;
+    use actix_web::{http::StatusCode, test, App};
+    use std::{collections::HashMap, net::TcpListener, sync::Arc};
+    use crate::{
+        resources::Dispatcher,
+        routes,
+        types::{InstanceInfo, Metrics, Readiness, ServiceRegistry}
+    };
+
+    /// Creates the state of a registry whose only peer refuses connections
+    fn create_app_state() -> Data<AppState> {
+        let unreachable_peer = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let metrics = Arc::new(Metrics::new());
+        let dispatcher = Dispatcher::new(vec![unreachable_peer], metrics.clone()).start();
+        Data::new(AppState {
+            service_registry: ServiceRegistry::new(dispatcher, metrics.clone()),
+            metrics,
+            readiness: Readiness::new(),
+            scheduler: create_scheduler()
+        })
+    }
+
+    async fn get_readiness_status(app_state: &Data<AppState>) -> StatusCode {
+        let mut app = test::init_service(App::new().app_data(app_state.clone()).configure(routes::probes::config)).await;
+        let req = test::TestRequest::get().uri("/readyz").to_request();
+        test::call_service(&mut app, req).await.status()
+    }
+
+    #[actix_rt::test]
+    async fn test_not_ready_while_every_peer_is_unreachable() {
+        let app_state = create_app_state();
+        let sync = sync_on_startup(&app_state, None, Duration::from_secs(60));
+        assert!(tokio::time::timeout(Duration::from_secs(1), sync).await.is_err());
+        assert_eq!(get_readiness_status(&app_state).await, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_rt::test]
+    async fn test_ready_once_startup_sync_times_out() {
+        let app_state = create_app_state();
+        sync_on_startup(&app_state, None, Duration::from_secs(0)).await;
+        assert_eq!(get_readiness_status(&app_state).await, StatusCode::OK);
+        assert_eq!(app_state.service_registry.count().await, (0, 0));
+    }
+
+    #[actix_rt::test]
+    async fn test_ready_once_snapshot_is_replayed() {
+        let path = std::env::temp_dir().join(format!("watchtower-snapshot-{}.json", std::process::id()));
+        let instance_info = InstanceInfo {
+            instance_id: "test1".to_string(),
+            ip_addr: "0.0.0.0".to_string(),
+            port: 8888,
+            status: Default::default(),
+            weight: 1,
+            metadata: HashMap::new()
+        };
+        write_snapshot(Snapshot::new(vec![("test".to_string(), vec![instance_info])]), path.clone()).await.unwrap();
+
+        let app_state = create_app_state();
+        let sync = sync_on_startup(&app_state, Some(path.clone()), Duration::from_secs(60));
+        tokio::time::timeout(Duration::from_secs(1), sync).await.unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(get_readiness_status(&app_state).await, StatusCode::OK);
+        assert_eq!(app_state.service_registry.get_all_instances("test").await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,13 @@
+use actix_web::{web, HttpResponse};
+use crate::types::{Result, AppState};
+
+pub async fn get_metrics(data: web::Data<AppState>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(data.metrics.encode()?))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/metrics")
+            .route(web::get().to(get_metrics))
+    );
+}
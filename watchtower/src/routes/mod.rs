@@ -1 +1,3 @@
-pub mod v1;
\ No newline at end of file
+pub mod v1;
+pub mod metrics;
+pub mod probes;
\ No newline at end of file
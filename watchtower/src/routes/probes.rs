@@ -0,0 +1,27 @@
+use actix_web::{web, HttpResponse};
+use crate::types::{Result, AppState};
+
+/// Reports that the process is alive.
+pub async fn liveness() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Reports whether the registry is ready to serve traffic.
+pub async fn readiness(data: web::Data<AppState>) -> Result<HttpResponse> {
+    let report = data.readiness.report();
+    if report.is_ready {
+        Ok(HttpResponse::Ok().json(report))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(report))
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/livez")
+            .route(web::get().to(liveness))
+    ).service(
+        web::resource("/readyz")
+            .route(web::get().to(readiness))
+    );
+}
@@ -1,8 +1,25 @@
 use actix_web::{web, HttpResponse};
-use crate::types::{Result, AuthorizedReq};
+use serde::Serialize;
+use crate::{
+    resources::ReadinessReport,
+    types::{Result, AppState, AuthorizedReq}
+};
 
-pub async fn health_check(_: AuthorizedReq) -> Result<HttpResponse> {
-    Ok(HttpResponse::Ok().finish())
+/// A detailed health report for operators.
+#[derive(Serialize)]
+struct HealthReport {
+    #[serde(flatten)]
+    readiness: ReadinessReport,
+    services: i64,
+    instances: i64
+}
+
+pub async fn health_check(_: AuthorizedReq, data: web::Data<AppState>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(HealthReport {
+        readiness: data.readiness.report(),
+        services: data.metrics.services.get(),
+        instances: data.metrics.instances.get()
+    }))
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -10,4 +27,4 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::resource("/healthcheck")
             .route(web::get().to(health_check))
     );
-}
\ No newline at end of file
+}
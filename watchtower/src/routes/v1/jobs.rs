@@ -0,0 +1,13 @@
+use actix_web::{web, HttpResponse};
+use crate::types::{Result, AppState, AuthorizedReq};
+
+pub async fn get_all_jobs(_: AuthorizedReq, data: web::Data<AppState>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(data.scheduler.report()))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/jobs")
+            .route(web::get().to(get_all_jobs))
+    );
+}
@@ -0,0 +1,26 @@
+use actix_web::{web, HttpResponse};
+use crate::types::{Result, AppState, AuthorizedReq};
+
+/// Returns the leases of every instance, with their age, for peers to sync from.
+pub async fn get_every_lease(_: AuthorizedReq, data: web::Data<AppState>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(data.service_registry.get_every_lease().await))
+}
+
+/// Returns the leases of the instances of a service, with their age, for operators.
+pub async fn get_all_leases(_: AuthorizedReq, path: web::Path<(String,)>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let (service_id,) = path.into_inner();
+    match data.service_registry.get_all_leases(&service_id).await {
+        Some(leases) => Ok(HttpResponse::Ok().json(leases)),
+        None => Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/leases")
+            .route(web::get().to(get_every_lease))
+    ).service(
+        web::resource("/leases/{service_id}")
+            .route(web::get().to(get_all_leases))
+    );
+}
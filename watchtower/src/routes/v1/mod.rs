@@ -1,2 +1,4 @@
 pub mod services;
-pub mod health;
\ No newline at end of file
+pub mod health;
+pub mod jobs;
+pub mod leases;
\ No newline at end of file
@@ -1,5 +1,10 @@
 use actix_web::{web, HttpResponse};
-use crate::types::{Result, AppState, InstanceInfo, AuthorizedReq};
+use crate::types::{Result, AppState, InstanceInfo, InstanceUpdate, AuthorizedReq};
+
+pub async fn get_all_services(_: AuthorizedReq, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let services = data.service_registry.get_all_services().await;
+    Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&services)?))
+}
 
 pub async fn get_all_instances(_: AuthorizedReq, path: web::Path<(String,)>, data: web::Data<AppState>) -> Result<HttpResponse> {
     let (service_id,) = path.into_inner();
@@ -26,6 +31,16 @@ pub async fn renew_lease(req: AuthorizedReq, path: web::Path<(String, String)>,
     }
 }
 
+pub async fn update_instance(req: AuthorizedReq, update: web::Json<InstanceUpdate>, path: web::Path<(String, String)>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let (service_id, instance_id) = path.into_inner();
+
+    if data.service_registry.update_instance(&service_id, &instance_id, update.into_inner(), req.is_replicated).await? {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
 pub async fn cancel_lease(req: AuthorizedReq, path: web::Path<(String, String)>, data: web::Data<AppState>) -> Result<HttpResponse> {
     let (service_id, instance_id) = path.into_inner();
     match data.service_registry.cancel_lease(&service_id, &instance_id, req.is_replicated).await? {
@@ -36,12 +51,16 @@ pub async fn cancel_lease(req: AuthorizedReq, path: web::Path<(String, String)>,
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
+        web::resource("/services")
+            .route(web::get().to(get_all_services))
+    ).service(
         web::resource("/services/{service_id}")
             .route(web::get().to(get_all_instances))
             .route(web::post().to(register_instance))
     ).service(
         web::resource("/services/{service_id}/{instance_id}")
             .route(web::put().to(renew_lease))
+            .route(web::patch().to(update_instance))
             .route(web::delete().to(cancel_lease))
     );
 }
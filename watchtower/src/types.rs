@@ -1,10 +1,14 @@
 use crate::error::WatchtowerError;
-pub use crate::resources::{ServiceRegistry, InstanceInfo};
+use std::sync::Arc;
+pub use crate::resources::{ServiceRegistry, InstanceInfo, InstanceStatus, InstanceUpdate, LeaseInfo, Metrics, Readiness, Scheduler};
 pub use crate::utils::auth::AuthorizedReq;
 
 pub type Error = WatchtowerError;
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub struct AppState {
-    pub service_registry: ServiceRegistry
+    pub service_registry: ServiceRegistry,
+    pub metrics: Arc<Metrics>,
+    pub readiness: Readiness,
+    pub scheduler: Scheduler
 }
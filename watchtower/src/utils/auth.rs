@@ -1,9 +1,12 @@
-use actix_web::{dev, Error, HttpRequest, FromRequest};
+use actix_web::{dev, web, Error, HttpRequest, FromRequest};
 use actix_web::error::ErrorUnauthorized;
 use futures_util::future::{ok, err, Ready};
 use serde::Deserialize;
 use base64::decode;
-use crate::utils::env;
+use crate::{
+    types::AppState,
+    utils::env
+};
 
 #[derive(Debug, Deserialize)]
 pub struct AuthorizedReq {
@@ -21,7 +24,12 @@ impl FromRequest for AuthorizedReq {
     fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
         match check_auth(req) {
             Ok(is_replicated) => ok(AuthorizedReq { is_replicated }),
-            Err(error) => err(error)
+            Err(error) => {
+                if let Some(data) = req.app_data::<web::Data<AppState>>() {
+                    data.metrics.auth_failures.inc();
+                }
+                err(error)
+            }
         }
     }
 }
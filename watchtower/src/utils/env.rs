@@ -1,4 +1,7 @@
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf
+};
 
 const DEFAULT_USERNAME: &str = "admin";
 const DEFAULT_PASSWORD: &str = "password";
@@ -20,6 +23,11 @@ pub fn get_hostname() -> SocketAddr {
     std::env::var("HOSTNAME").unwrap_or(DEFAULT_HOSTNAME.to_string()).to_socket_addrs().unwrap().next().unwrap()
 }
 
+/// Returns the file the registry snapshots its state to, if any.
+pub fn get_snapshot_file() -> Option<PathBuf> {
+    std::env::var("SNAPSHOT_FILE").ok().map(PathBuf::from)
+}
+
 pub struct AuthInfo {
     pub username: String,
     pub password: String
@@ -0,0 +1,148 @@
+#ifndef WATCHTOWER_CLIENT_H
+#define WATCHTOWER_CLIENT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The outcome of a call, where every variant but `Ok` is an error
+ */
+typedef enum WatchtowerStatus {
+  WATCHTOWER_STATUS_OK = 0,
+  WATCHTOWER_STATUS_INTERNAL_ERROR,
+  WATCHTOWER_STATUS_NOT_FOUND,
+  WATCHTOWER_STATUS_UNAUTHORIZED,
+  WATCHTOWER_STATUS_INSTANCE_ALREADY_REGISTERED,
+  WATCHTOWER_STATUS_MAX_RETRY_REACHED,
+  WATCHTOWER_STATUS_INVALID_PING,
+  WATCHTOWER_STATUS_NO_AVAILABLE_INSTANCE,
+  WATCHTOWER_STATUS_TIMEOUT,
+  WATCHTOWER_STATUS_NO_RUNTIME,
+  WATCHTOWER_STATUS_INVALID_CONFIG,
+  WATCHTOWER_STATUS_INVALID_URL,
+  /**
+   * A pointer is null, or a string is not valid UTF-8
+   */
+  WATCHTOWER_STATUS_INVALID_ARGUMENT,
+} WatchtowerStatus;
+
+/**
+ * A client, which may be shared between threads
+ *
+ * Created by `watchtower_client_new` and released by `watchtower_client_free`.
+ */
+typedef struct WatchtowerClientHandle WatchtowerClientHandle;
+
+/**
+ * An instance registered through a client, whose lease is renewed in the background until the handle is released
+ *
+ * Created by `watchtower_register` and released by `watchtower_registration_free`.
+ */
+typedef struct WatchtowerRegistrationHandle WatchtowerRegistrationHandle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a client of the registries at `urls`, an array of `url_count` strings
+ *
+ * # Safety
+ *
+ * Strings must be valid and NUL-terminated, `urls` must point to `url_count` of them, and `client` must be writable.
+ */
+enum WatchtowerStatus watchtower_client_new(const char *const *urls,
+                                            size_t url_count,
+                                            const char *username,
+                                            const char *password,
+                                            struct WatchtowerClientHandle **client);
+
+/**
+ * Releases a client, stopping its heartbeat once its registrations are released
+ *
+ * # Safety
+ *
+ * `client` must be null or returned by `watchtower_client_new`, and must not be used afterwards.
+ */
+void watchtower_client_free(struct WatchtowerClientHandle *client);
+
+/**
+ * Registers an instance of a service, whose lease is renewed in the background until the registration is released
+ *
+ * # Safety
+ *
+ * `client` must be a live client, strings must be valid and NUL-terminated, and `registration` must be writable.
+ */
+enum WatchtowerStatus watchtower_register(const struct WatchtowerClientHandle *client,
+                                          const char *service_id,
+                                          const char *ip_addr,
+                                          uint16_t port,
+                                          struct WatchtowerRegistrationHandle **registration);
+
+/**
+ * Writes the instance id of a registration to `instance_id`
+ *
+ * # Safety
+ *
+ * `registration` must be a live registration, and `instance_id` must be writable.
+ */
+enum WatchtowerStatus watchtower_registration_instance_id(const struct WatchtowerRegistrationHandle *registration,
+                                                          char **instance_id);
+
+/**
+ * Cancels the lease of a registration, blocking until it is done, and releases it
+ *
+ * # Safety
+ *
+ * `registration` must be null or returned by `watchtower_register`, and must not be used afterwards.
+ */
+void watchtower_registration_free(struct WatchtowerRegistrationHandle *registration);
+
+/**
+ * Writes the address of an instance of a service, such as `127.0.0.1:8080`, to `url`
+ *
+ * # Safety
+ *
+ * `client` must be a live client, `service_id` must be valid and NUL-terminated, and `url` must be writable.
+ */
+enum WatchtowerStatus watchtower_get_service_url(const struct WatchtowerClientHandle *client,
+                                                 const char *service_id,
+                                                 char **url);
+
+/**
+ * Writes the url of `path` on an instance of a service with `scheme`, such as `https://127.0.0.1:8080/health`, to `url`
+ *
+ * # Safety
+ *
+ * `client` must be a live client, strings must be valid and NUL-terminated, and `url` must be writable.
+ */
+enum WatchtowerStatus watchtower_build_service_url(const struct WatchtowerClientHandle *client,
+                                                   const char *service_id,
+                                                   const char *scheme,
+                                                   const char *path,
+                                                   char **url);
+
+/**
+ * Releases a string returned by the library
+ *
+ * # Safety
+ *
+ * `string` must be null or returned by the library, and must not be used afterwards.
+ */
+void watchtower_string_free(char *string);
+
+/**
+ * Returns a static description of a status, which must not be released
+ */
+const char *watchtower_status_message(enum WatchtowerStatus status);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* WATCHTOWER_CLIENT_H */
@@ -0,0 +1,310 @@
+//! A synchronous client, for programs that do not run an async runtime
+//!
+//! Requests and background tasks, such as the heartbeat, run on a runtime owned by the client on a thread of its own.
+//! The methods block the calling thread, so they must not be called from async code.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    thread::{self, JoinHandle, ThreadId}
+};
+use futures_util::future::BoxFuture;
+use log::error;
+use tokio::{runtime::Handle, sync::oneshot};
+use crate::{
+    types::{InstanceInfo, Result, Error},
+    InstanceStatus,
+    RegistrationOptions,
+    RegistrationState,
+    Spawner,
+    WatchtowerClientBuilder
+};
+
+/// A tokio runtime driven by a thread of its own, which stops once every handle to it is dropped
+struct BackgroundRuntime {
+    handle: Handle,
+    thread_id: ThreadId,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl BackgroundRuntime {
+    fn start() -> Result<Self> {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .map_err(|err| {
+                error!("Unable to start the runtime of the blocking client: {:?}", err);
+                Error::NoRuntime
+            })?;
+        let handle = runtime.handle().clone();
+        let (shutdown, shutdown_receiver) = oneshot::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("watchtower-client".to_string())
+            .spawn(move || {
+                let _ = runtime.block_on(shutdown_receiver);
+            })
+            .map_err(|err| {
+                error!("Unable to start the thread of the blocking client: {:?}", err);
+                Error::NoRuntime
+            })?;
+        Ok(BackgroundRuntime {
+            handle,
+            thread_id: thread.thread().id(),
+            shutdown: Some(shutdown),
+            thread: Some(thread)
+        })
+    }
+
+    /// Runs `future` on the runtime and blocks until it completes
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send
+    {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.handle.spawn(async move {
+            // The future is dropped before its output is sent, so the caller gets back whatever it held
+            let output = future.await;
+            let _ = sender.send(output);
+        });
+        receiver.recv().expect("The runtime of the blocking client stopped")
+    }
+}
+
+impl Spawner for BackgroundRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> Result<()> {
+        self.handle.spawn(task);
+        Ok(())
+    }
+}
+
+impl Drop for BackgroundRuntime {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        // The last handle may be dropped by a task of the runtime, which cannot wait for its own thread
+        if thread::current().id() != self.thread_id {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// A synchronous `WatchtowerClient`
+///
+/// Leases are renewed by a heartbeat running in the background, as with the async client.
+pub struct WatchtowerClient {
+    client: Arc<crate::WatchtowerClient>,
+    runtime: Arc<BackgroundRuntime>
+}
+
+impl WatchtowerClient {
+    /// Creates a client with the default configuration
+    ///
+    /// Panics if `watchtower_urls` is empty or the client cannot be built. Use `WatchtowerClient::with_builder` to
+    /// handle these errors or to change the configuration.
+    pub fn new(watchtower_urls: Vec<String>, username: &str, password: &str) -> Self {
+        let builder = crate::WatchtowerClient::builder(watchtower_urls, username, password);
+        Self::with_builder(builder).expect("Fails to build the client")
+    }
+
+    /// Creates a client configured by `builder`, whose spawner is replaced by the runtime of the client
+    pub fn with_builder(builder: WatchtowerClientBuilder) -> Result<Self> {
+        let runtime = Arc::new(BackgroundRuntime::start()?);
+        let client = builder.spawner(runtime.clone()).build()?;
+        Ok(WatchtowerClient {
+            client: Arc::new(client),
+            runtime
+        })
+    }
+
+    /// Returns the async client, for example to use it from async code
+    pub fn client(&self) -> &Arc<crate::WatchtowerClient> {
+        &self.client
+    }
+
+    /// Runs `call` with the async client on the runtime, and blocks until it completes
+    fn call<T, F, Fut>(&self, call: F) -> T
+    where
+        F: FnOnce(Arc<crate::WatchtowerClient>) -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+        T: Send + 'static
+    {
+        self.runtime.block_on(call(self.client.clone()))
+    }
+
+    /// Registers an instance of a service, whose lease is renewed in the background
+    ///
+    /// The lease is cancelled when the returned handle is dropped, unless it has been detached.
+    pub fn register(&self, service_id: &str, ip_addr: &str, port: u16) -> Result<Registration> {
+        self.register_with_options(service_id, ip_addr, port, RegistrationOptions::default())
+    }
+
+    /// Registers an instance of a service with `options`, whose lease is renewed in the background
+    pub fn register_with_options(&self, service_id: &str, ip_addr: &str, port: u16, options: RegistrationOptions) -> Result<Registration> {
+        let service_id = service_id.to_string();
+        let ip_addr = ip_addr.to_string();
+        let registration = self.call(|client| async move {
+            client.register_with_options(&service_id, &ip_addr, port, options).await
+        })?;
+        Ok(Registration {
+            registration: Some(Arc::new(registration)),
+            runtime: self.runtime.clone()
+        })
+    }
+
+    /// Renews the leases of every instance registered through this client
+    pub fn ping(&self) -> Result<()> {
+        self.call(|client| async move { client.ping().await })
+    }
+
+    /// Cancels the leases of every instance registered through this client
+    pub fn cancel(&self) -> Result<()> {
+        self.call(|client| async move { client.cancel().await })
+    }
+
+    pub fn get_service_url(&self, service_id: &str) -> Result<String> {
+        let service_id = service_id.to_string();
+        self.call(|client| async move { client.get_service_url(&service_id).await })
+    }
+
+    /// Returns every instance of a service that is up
+    pub fn get_instances(&self, service_id: &str) -> Result<Vec<InstanceInfo>> {
+        let service_id = service_id.to_string();
+        self.call(|client| async move { client.get_instances(&service_id).await })
+    }
+
+    /// Returns the instance of a service selected by its load balancer
+    pub fn choose_instance(&self, service_id: &str) -> Result<InstanceInfo> {
+        let service_id = service_id.to_string();
+        self.call(|client| async move { client.choose_instance(&service_id).await })
+    }
+
+    /// Builds the url of `path` on an instance of a service with `scheme`, such as `https://127.0.0.1:8080/health`
+    pub fn build_service_url(&self, service_id: &str, scheme: &str, path: &str) -> Result<String> {
+        let service_id = service_id.to_string();
+        let scheme = scheme.to_string();
+        let path = path.to_string();
+        self.call(|client| async move { client.build_service_url(&service_id, &scheme, &path).await })
+    }
+}
+
+/// A handle to an instance registered through a blocking `WatchtowerClient`
+///
+/// The lease is cancelled when the handle is dropped, blocking until it is done, unless it has been detached.
+#[must_use = "dropping the handle cancels the lease; call detach() to keep it"]
+pub struct Registration {
+    /// Only taken by `detach` and `drop`
+    registration: Option<Arc<crate::Registration>>,
+    runtime: Arc<BackgroundRuntime>
+}
+
+impl Registration {
+    fn registration(&self) -> &Arc<crate::Registration> {
+        self.registration.as_ref().unwrap()
+    }
+
+    pub fn service_id(&self) -> &str {
+        self.registration().service_id()
+    }
+
+    pub fn instance_id(&self) -> &str {
+        self.registration().instance_id()
+    }
+
+    /// Returns the current instance info, or `None` once the registration is cancelled
+    pub fn instance_info(&self) -> Option<InstanceInfo> {
+        self.registration().instance_info()
+    }
+
+    /// Returns the last known state of the lease
+    pub fn state(&self) -> RegistrationState {
+        self.registration().state()
+    }
+
+    /// Updates the status of the instance on the registry
+    pub fn set_status(&self, status: InstanceStatus) -> Result<()> {
+        let registration = self.registration().clone();
+        self.runtime.block_on(async move { registration.set_status(status).await })
+    }
+
+    /// Updates the weight of the instance on the registry, where 0 drains it
+    pub fn set_weight(&self, weight: u32) -> Result<()> {
+        let registration = self.registration().clone();
+        self.runtime.block_on(async move { registration.set_weight(weight).await })
+    }
+
+    /// Cancels the lease of the instance
+    pub fn cancel(&self) -> Result<()> {
+        let registration = self.registration().clone();
+        self.runtime.block_on(async move { registration.cancel().await })
+    }
+
+    /// Releases the handle without cancelling the lease, which is still renewed by the client
+    pub fn detach(mut self) {
+        // Calls block until their future is dropped, so no other reference to the handle is left
+        if let Some(Ok(registration)) = self.registration.take().map(Arc::try_unwrap) {
+            registration.detach();
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let registration = match self.registration.take() {
+            Some(registration) => registration,
+            None => return
+        };
+        self.runtime.block_on(async move {
+            if registration.instance_info().is_none() {
+                return;
+            }
+            // On failure, the async handle retries in the background once dropped
+            if let Err(err) = registration.cancel().await {
+                error!("Unable to cancel dropped instance {}: {:?}", registration.instance_id(), err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use crate::test_utils::create_mock_builder;
+
+    fn create_client() -> WatchtowerClient {
+        WatchtowerClient::with_builder(create_mock_builder()).unwrap()
+    }
+
+    #[test]
+    fn test_get_instances() {
+        let _instances = mockito::mock("GET", "/api/v1/services/test_blocking_get_instances")
+            .with_status(200)
+            .with_body(r#"[{"instance_id": "a", "ip_addr": "127.0.0.1", "port": 8080, "status": "UP"}]"#)
+            .create();
+        let watchtower_client = create_client();
+        let instance_infos = watchtower_client.get_instances("test_blocking_get_instances").unwrap();
+        assert_eq!(instance_infos.len(), 1);
+        assert_eq!(watchtower_client.get_service_url("test_blocking_get_instances").unwrap(), "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_dropped_registration_is_cancelled() {
+        let path = Matcher::Regex("^/api/v1/services/test_blocking_registration/.+$".to_string());
+        let _register = mockito::mock("POST", "/api/v1/services/test_blocking_registration").with_status(204).create();
+        let _renew = mockito::mock("PUT", path.clone()).with_status(200).create();
+        let cancel = mockito::mock("DELETE", path).with_status(200).expect(1).create();
+        let watchtower_client = create_client();
+
+        let registration = watchtower_client.register("test_blocking_registration", "127.0.0.1", 8080).unwrap();
+        assert_eq!(registration.state(), RegistrationState::Registered);
+        drop(registration);
+        cancel.assert();
+        assert!(watchtower_client.client().get_registrations().is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration
+};
+
+use crate::{
+    resources::{
+        load_balancer::{LoadBalancer, LoadBalancerFactory, WeightedRoundRobinLoadBalancer},
+        DiskCache,
+        HttpClient,
+        OutlierDetectionConfig,
+        OutlierDetector,
+        ServiceCache,
+        UPDATE_INTERVAL_MS
+    },
+    types::{Result, Error},
+    utils::{
+        retry::RetryPolicy,
+        spawner::{Spawner, TokioSpawner}
+    },
+    WatchtowerClient
+};
+
+const DEFAULT_CONNECT_TIMEOUT_SEC: u64 = 5;
+const DEFAULT_REQUEST_TIMEOUT_SEC: u64 = 10;
+const DEFAULT_HEARTBEAT_INTERVAL_SEC: u64 = 15;
+const DEFAULT_REFRESH_INTERVAL_SEC: u64 = 5;
+const DEFAULT_IDLE_TIMEOUT_SEC: u64 = 300;
+const DEFAULT_MAX_STALENESS_SEC: u64 = 300;
+/// The lease duration of the registry, which the heartbeat interval must stay below
+const LEASE_DURATION_SEC: u64 = 30;
+const DEFAULT_USER_AGENT: &str = concat!("watchtower-client/", env!("CARGO_PKG_VERSION"));
+
+/// Configures and creates a `WatchtowerClient`.
+pub struct WatchtowerClientBuilder {
+    watchtower_urls: Vec<String>,
+    username: String,
+    password: String,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    retry_policy: RetryPolicy,
+    heartbeat_interval: Duration,
+    cache_ttl: Duration,
+    refresh_interval: Duration,
+    idle_timeout: Duration,
+    max_staleness: Duration,
+    cache_file: Option<PathBuf>,
+    user_agent: String,
+    http_client: Option<reqwest::Client>,
+    spawner: Arc<dyn Spawner>,
+    load_balancer_factory: LoadBalancerFactory,
+    outlier_detection: OutlierDetectionConfig
+}
+
+impl WatchtowerClientBuilder {
+    pub fn new(watchtower_urls: Vec<String>, username: &str, password: &str) -> Self {
+        WatchtowerClientBuilder {
+            watchtower_urls,
+            username: username.to_string(),
+            password: password.to_string(),
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SEC),
+            request_timeout: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SEC),
+            retry_policy: RetryPolicy::default(),
+            heartbeat_interval: Duration::from_secs(DEFAULT_HEARTBEAT_INTERVAL_SEC),
+            cache_ttl: Duration::from_millis(UPDATE_INTERVAL_MS),
+            refresh_interval: Duration::from_secs(DEFAULT_REFRESH_INTERVAL_SEC),
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SEC),
+            max_staleness: Duration::from_secs(DEFAULT_MAX_STALENESS_SEC),
+            cache_file: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            http_client: None,
+            spawner: Arc::new(TokioSpawner),
+            load_balancer_factory: Arc::new(|| Arc::new(WeightedRoundRobinLoadBalancer::new())),
+            outlier_detection: OutlierDetectionConfig::default()
+        }
+    }
+
+    /// Sets the timeout for connecting to a registry node, 5 seconds by default
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout of a single request to a registry node, 10 seconds by default
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Sets the number of attempts made for a request, across registry nodes, 3 by default
+    pub fn max_attempts(mut self, max_attempts: u16) -> Self {
+        self.retry_policy.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the exponential backoff between retries, from 100 milliseconds up to 5 seconds by default
+    ///
+    /// The same backoff is used by the heartbeat to retry failed renewals.
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.retry_policy.initial_backoff = initial_backoff;
+        self.retry_policy.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the interval at which the heartbeat renews leases, 15 seconds by default
+    ///
+    /// This should be well below the lease duration of the registry, which is 30 seconds. `build` rejects a zero interval
+    /// and one of 30 seconds or more.
+    pub fn heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Sets how long the instances of a service are cached before being fetched again, 30 seconds by default
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    /// Sets how often cached services are refreshed in the background, 5 seconds by default
+    ///
+    /// Every time, the services that would expire before the next refresh are fetched again.
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Sets how long a service is kept in the cache after it was last looked up, 5 minutes by default
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets how long after they expire the cached instances of a service are still served while the registry is
+    /// unreachable, 5 minutes by default
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
+
+    /// Persists fetched services to the file at `path`, and seeds the cache with the services it holds
+    ///
+    /// Seeded services are fetched from the registry when first used, and only served while it is unreachable.
+    pub fn cache_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.cache_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the user agent of requests to the registry
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Sends requests through `http_client` instead of one built from the timeouts and user agent of this builder
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Runs background tasks, such as the heartbeat, on `spawner` instead of the current tokio runtime
+    pub fn spawner(mut self, spawner: Arc<dyn Spawner>) -> Self {
+        self.spawner = spawner;
+        self
+    }
+
+    /// Sets how the load balancer of a service is created when none is set with `WatchtowerClient::set_load_balancer`
+    ///
+    /// Services use a `WeightedRoundRobinLoadBalancer` by default.
+    pub fn default_load_balancer<F>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> Arc<dyn LoadBalancer> + Send + Sync + 'static
+    {
+        self.load_balancer_factory = Arc::new(factory);
+        self
+    }
+
+    /// Sets when instances are ejected from selection, based on the outcomes of calls reported to the client
+    pub fn outlier_detection(mut self, config: OutlierDetectionConfig) -> Self {
+        self.outlier_detection = config;
+        self
+    }
+
+    /// Creates the client
+    ///
+    /// Returns `Error::InvalidConfig` if there is no registry url, no attempt is allowed, the heartbeat or refresh
+    /// interval is zero or the heartbeat interval is not below the lease duration, and `Error::InternalError` if the
+    /// HTTP client cannot be built.
+    pub fn build(self) -> Result<WatchtowerClient> {
+        if self.watchtower_urls.is_empty() || self.retry_policy.max_attempts == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        if self.heartbeat_interval.is_zero() || self.heartbeat_interval >= Duration::from_secs(LEASE_DURATION_SEC) {
+            return Err(Error::InvalidConfig);
+        }
+        if self.refresh_interval.is_zero() {
+            return Err(Error::InvalidConfig);
+        }
+
+        let client = match self.http_client {
+            Some(client) => client,
+            None => reqwest::Client::builder()
+                .connect_timeout(self.connect_timeout)
+                .timeout(self.request_timeout)
+                .user_agent(self.user_agent)
+                .build()?
+        };
+        let http_client = HttpClient::with_client(client, self.watchtower_urls, self.username, self.password, self.retry_policy);
+
+        let http_client = Arc::new(http_client);
+        let outlier_detector = Arc::new(OutlierDetector::new(self.outlier_detection));
+        let service_cache = ServiceCache::new(
+            http_client.clone(),
+            self.cache_ttl,
+            self.max_staleness,
+            self.load_balancer_factory,
+            outlier_detector.clone(),
+            self.cache_file.map(DiskCache::open)
+        );
+
+        Ok(WatchtowerClient {
+            http_client,
+            service_cache: Arc::new(service_cache),
+            registrations: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            is_heartbeat_running: AtomicBool::new(false),
+            is_refresher_running: Arc::new(AtomicBool::new(false)),
+            spawner: self.spawner,
+            heartbeat_interval: self.heartbeat_interval,
+            refresh_interval: self.refresh_interval,
+            idle_timeout: self.idle_timeout,
+            max_staleness: self.max_staleness,
+            outlier_detector
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_rejects_invalid_config() {
+        let builder = WatchtowerClientBuilder::new(Vec::new(), "admin", "password");
+        assert_eq!(builder.build().err(), Some(Error::InvalidConfig));
+
+        let builder = WatchtowerClientBuilder::new(vec!["http://localhost:8088".to_string()], "admin", "password")
+            .max_attempts(0);
+        assert_eq!(builder.build().err(), Some(Error::InvalidConfig));
+
+        let create_builder = || WatchtowerClientBuilder::new(vec!["http://localhost:8088".to_string()], "admin", "password");
+        assert_eq!(create_builder().heartbeat_interval(Duration::ZERO).build().err(), Some(Error::InvalidConfig));
+        assert_eq!(create_builder().heartbeat_interval(Duration::from_secs(LEASE_DURATION_SEC)).build().err(), Some(Error::InvalidConfig));
+        assert_eq!(create_builder().refresh_interval(Duration::ZERO).build().err(), Some(Error::InvalidConfig));
+        assert!(create_builder().heartbeat_interval(Duration::from_secs(LEASE_DURATION_SEC - 1)).build().is_ok());
+    }
+
+    #[test]
+    fn test_build() {
+        let client = WatchtowerClientBuilder::new(vec!["http://localhost:8088".to_string()], "admin", "password")
+            .max_attempts(5)
+            .backoff(Duration::from_millis(10), Duration::from_secs(1))
+            .heartbeat_interval(Duration::from_secs(5))
+            .refresh_interval(Duration::from_secs(1))
+            .idle_timeout(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        assert_eq!(client.http_client.retry_policy(), &RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1)
+        });
+        assert_eq!(client.heartbeat_interval, Duration::from_secs(5));
+        assert_eq!(client.refresh_interval, Duration::from_secs(1));
+        assert_eq!(client.idle_timeout, Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,233 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+use log::warn;
+use reqwest::{header::HeaderMap, Body, Method, Request, RequestBuilder, Response, Url};
+use serde::Serialize;
+use crate::{
+    resources::load_balancer::RequestContext,
+    types::{InstanceInfo, Result, Error},
+    CallOutcome,
+    WatchtowerClient,
+    WATCHTOWER_SCHEME
+};
+
+const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// A reqwest client that sends requests to logical urls, whose host is the name of a service
+///
+/// Requests to `http://my-service/path`, `https://my-service/path` or `watchtower://my-service/path` are sent to an
+/// instance of `my-service` selected by the `WatchtowerClient`. Requests that fail to connect are sent again to
+/// another instance, and the outcome of every attempt is reported for outlier detection.
+pub struct DiscoveryClient {
+    client: reqwest::Client,
+    watchtower_client: Arc<WatchtowerClient>,
+    max_attempts: usize
+}
+
+impl DiscoveryClient {
+    pub fn new(watchtower_client: Arc<WatchtowerClient>) -> Self {
+        DiscoveryClient::with_client(reqwest::Client::new(), watchtower_client)
+    }
+
+    /// Creates a `DiscoveryClient` that sends requests through `client`
+    pub fn with_client(client: reqwest::Client, watchtower_client: Arc<WatchtowerClient>) -> Self {
+        DiscoveryClient {
+            client,
+            watchtower_client,
+            max_attempts: DEFAULT_MAX_ATTEMPTS
+        }
+    }
+
+    /// Sets how many instances a request is sent to when it fails to connect, 3 by default
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Starts building a request to the logical `url`
+    pub fn request(&self, method: Method, url: &str) -> DiscoveryRequestBuilder<'_> {
+        DiscoveryRequestBuilder {
+            discovery_client: self,
+            builder: self.client.request(method, url)
+        }
+    }
+
+    pub fn get(&self, url: &str) -> DiscoveryRequestBuilder<'_> {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post(&self, url: &str) -> DiscoveryRequestBuilder<'_> {
+        self.request(Method::POST, url)
+    }
+
+    pub fn put(&self, url: &str) -> DiscoveryRequestBuilder<'_> {
+        self.request(Method::PUT, url)
+    }
+
+    pub fn patch(&self, url: &str) -> DiscoveryRequestBuilder<'_> {
+        self.request(Method::PATCH, url)
+    }
+
+    pub fn delete(&self, url: &str) -> DiscoveryRequestBuilder<'_> {
+        self.request(Method::DELETE, url)
+    }
+
+    /// Sends a request whose url is logical
+    ///
+    /// Responses are returned whatever their status, but server errors are reported as failures. Returns
+    /// `Error::InvalidUrl` if the url names no service, and `Error::MaxRetryReached` if no instance could be connected
+    /// to. Requests with a streamed body cannot be sent again, so they are only sent once.
+    pub async fn execute(&self, request: Request) -> Result<Response> {
+        let service_id = request.url().host_str().ok_or(Error::InvalidUrl)?.to_string();
+        let scheme = match request.url().scheme() {
+            WATCHTOWER_SCHEME => "http",
+            scheme => scheme
+        }.to_string();
+
+        let mut request = request;
+        let mut context = RequestContext::default();
+        let mut attempt = 1;
+        loop {
+            let selected_instance = self.watchtower_client.select_instance(&service_id, &context).await?;
+            let next_request = if attempt < self.max_attempts { request.try_clone() } else { None };
+            *request.url_mut() = resolve(request.url(), &scheme, selected_instance.instance_info())?;
+
+            let start = Instant::now();
+            match self.client.execute(request).await {
+                Ok(response) => {
+                    let outcome = if response.status().is_server_error() {
+                        CallOutcome::failure(start.elapsed())
+                    } else {
+                        CallOutcome::success(start.elapsed())
+                    };
+                    selected_instance.report(outcome);
+                    return Ok(response);
+                },
+                Err(err) => {
+                    selected_instance.report(CallOutcome::failure(start.elapsed()));
+                    if !err.is_connect() {
+                        return Err(if err.is_timeout() { Error::Timeout } else { err.into() });
+                    }
+                    let instance_id = &selected_instance.instance_info().instance_id;
+                    warn!("Unable to connect to instance {} of {}: {}", instance_id, service_id, err);
+                    match next_request {
+                        Some(next_request) => {
+                            context.exclude(instance_id);
+                            request = next_request;
+                            attempt += 1;
+                        },
+                        None => return Err(Error::MaxRetryReached)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the host of a logical url with the address of `instance_info`
+fn resolve(url: &Url, scheme: &str, instance_info: &InstanceInfo) -> Result<Url> {
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    Url::parse(&instance_info.url(scheme, &path)).map_err(|_| Error::InvalidUrl)
+}
+
+/// A request to a logical url, sent through a `DiscoveryClient`
+pub struct DiscoveryRequestBuilder<'a> {
+    discovery_client: &'a DiscoveryClient,
+    builder: RequestBuilder
+}
+
+impl<'a> DiscoveryRequestBuilder<'a> {
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.builder = self.builder.headers(headers);
+        self
+    }
+
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.builder = self.builder.query(query);
+        self
+    }
+
+    pub fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.builder = self.builder.json(json);
+        self
+    }
+
+    pub fn body<T: Into<Body>>(mut self, body: T) -> Self {
+        self.builder = self.builder.body(body);
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.builder = self.builder.bearer_auth(token);
+        self
+    }
+
+    pub fn basic_auth(mut self, username: &str, password: Option<&str>) -> Self {
+        self.builder = self.builder.basic_auth(username, password);
+        self
+    }
+
+    /// Sets the timeout of every attempt to send the request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.builder = self.builder.timeout(timeout);
+        self
+    }
+
+    /// Sends the request to an instance of the service named by the host of its url
+    pub async fn send(self) -> Result<Response> {
+        self.discovery_client.execute(self.builder.build()?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{create_mock_builder, create_mock_http_client},
+        CircuitState
+    };
+
+    const INSTANCES: &str = r#"[
+        {"instance_id": "unreachable", "ip_addr": "127.0.0.1", "port": 1, "status": "UP"},
+        {"instance_id": "reachable", "ip_addr": "127.0.0.1", "port": 1234, "status": "UP"}
+    ]"#;
+
+    fn create_discovery_client() -> DiscoveryClient {
+        let watchtower_client = create_mock_builder().build().unwrap();
+        DiscoveryClient::with_client(create_mock_http_client(), Arc::new(watchtower_client))
+    }
+
+    #[actix_rt::test]
+    async fn test_send_to_logical_url() {
+        let _instances = mockito::mock("GET", "/api/v1/services/test_discovery").with_status(200).with_body(INSTANCES).create();
+        let _hello = mockito::mock("GET", "/hello?name=watchtower").with_status(200).with_body("hello").create();
+        let discovery_client = create_discovery_client();
+
+        for url in &["watchtower://test_discovery/hello", "http://test_discovery/hello"] {
+            let response = discovery_client.get(url).query(&[("name", "watchtower")]).send().await.unwrap();
+            assert_eq!(response.text().await.unwrap(), "hello");
+        }
+
+        let instance_health = discovery_client.watchtower_client.get_instance_health();
+        let unreachable = instance_health.iter().find(|report| report.instance_id == "unreachable").unwrap();
+        assert!(unreachable.consecutive_failures >= 1);
+        assert_eq!(unreachable.state, CircuitState::Closed);
+        let reachable = instance_health.iter().find(|report| report.instance_id == "reachable").unwrap();
+        assert_eq!(reachable.failure_rate, 0.0);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_url() {
+        let discovery_client = create_discovery_client();
+        let request = Request::new(Method::GET, Url::parse("unix:/var/run/socket").unwrap());
+        assert_eq!(discovery_client.execute(request).await.err(), Some(Error::InvalidUrl));
+    }
+}
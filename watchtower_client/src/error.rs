@@ -1,19 +1,41 @@
 use log::error;
-use pyo3::{
-    exceptions::{PyException},
-    PyErr
-};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WatchtowerError {
     InternalError,
     NotFound,
     Unauthorized,
     InstanceAlreadyRegistered,
     MaxRetryReached,
-    InvalidPing
+    InvalidPing,
+    NoAvailableInstance,
+    Timeout,
+    NoRuntime,
+    InvalidConfig,
+    InvalidUrl
 }
 
+impl std::fmt::Display for WatchtowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            WatchtowerError::InternalError => "internal error",
+            WatchtowerError::NotFound => "not found",
+            WatchtowerError::Unauthorized => "unauthorized",
+            WatchtowerError::InstanceAlreadyRegistered => "instance already registered",
+            WatchtowerError::MaxRetryReached => "max retry reached",
+            WatchtowerError::InvalidPing => "no instance to ping",
+            WatchtowerError::NoAvailableInstance => "no available instance",
+            WatchtowerError::Timeout => "timed out",
+            WatchtowerError::NoRuntime => "no runtime to spawn background tasks on",
+            WatchtowerError::InvalidConfig => "invalid configuration",
+            WatchtowerError::InvalidUrl => "invalid url"
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for WatchtowerError {}
+
 impl From<reqwest::Error> for WatchtowerError {
     fn from(error: reqwest::Error) -> Self {
         error!("Reqwest Error: {:?}", error);
@@ -35,13 +57,16 @@ impl From<serde_json::Error> for WatchtowerError {
     }
 }
 
-impl From<WatchtowerError> for PyErr {
-    fn from(err: WatchtowerError) -> PyErr {
-        match err {
-            WatchtowerError::NotFound => PyException::new_err("NotFound"),
-            WatchtowerError::Unauthorized => PyException::new_err("Unauthorized"),
-            _ => PyException::new_err("Something went wrong")
-        }
-        
+impl From<std::io::Error> for WatchtowerError {
+    fn from(error: std::io::Error) -> Self {
+        error!("{:?}", error);
+        WatchtowerError::InternalError
+    }
+}
+
+impl From<tokio::task::JoinError> for WatchtowerError {
+    fn from(error: tokio::task::JoinError) -> Self {
+        error!("{:?}", error);
+        WatchtowerError::InternalError
     }
-}
\ No newline at end of file
+}
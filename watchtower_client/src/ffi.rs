@@ -0,0 +1,311 @@
+//! A C API over the blocking client, exported by the `cdylib` with the `ffi` feature
+//!
+//! Functions return a `WatchtowerStatus`, and hand their results back through out pointers, which are only written on
+//! success. Handles and strings returned by the library are released with the matching `_free` function. The header
+//! is generated by cbindgen into `include/watchtower_client.h`.
+
+use std::{
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    slice
+};
+use crate::{blocking, types::Error};
+
+/// The outcome of a call, where every variant but `Ok` is an error
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchtowerStatus {
+    Ok = 0,
+    InternalError,
+    NotFound,
+    Unauthorized,
+    InstanceAlreadyRegistered,
+    MaxRetryReached,
+    InvalidPing,
+    NoAvailableInstance,
+    Timeout,
+    NoRuntime,
+    InvalidConfig,
+    InvalidUrl,
+    /// A pointer is null, or a string is not valid UTF-8
+    InvalidArgument
+}
+
+impl From<Error> for WatchtowerStatus {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::InternalError => WatchtowerStatus::InternalError,
+            Error::NotFound => WatchtowerStatus::NotFound,
+            Error::Unauthorized => WatchtowerStatus::Unauthorized,
+            Error::InstanceAlreadyRegistered => WatchtowerStatus::InstanceAlreadyRegistered,
+            Error::MaxRetryReached => WatchtowerStatus::MaxRetryReached,
+            Error::InvalidPing => WatchtowerStatus::InvalidPing,
+            Error::NoAvailableInstance => WatchtowerStatus::NoAvailableInstance,
+            Error::Timeout => WatchtowerStatus::Timeout,
+            Error::NoRuntime => WatchtowerStatus::NoRuntime,
+            Error::InvalidConfig => WatchtowerStatus::InvalidConfig,
+            Error::InvalidUrl => WatchtowerStatus::InvalidUrl
+        }
+    }
+}
+
+/// A client, which may be shared between threads
+///
+/// Created by `watchtower_client_new` and released by `watchtower_client_free`.
+pub struct WatchtowerClientHandle {
+    client: blocking::WatchtowerClient
+}
+
+/// An instance registered through a client, whose lease is renewed in the background until the handle is released
+///
+/// Created by `watchtower_register` and released by `watchtower_registration_free`.
+pub struct WatchtowerRegistrationHandle {
+    registration: blocking::Registration
+}
+
+/// Runs `call`, turning its errors and panics into a status, as unwinding into C is undefined behavior
+fn run<F>(call: F) -> WatchtowerStatus
+where
+    F: FnOnce() -> Result<(), WatchtowerStatus>
+{
+    match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => WatchtowerStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => WatchtowerStatus::InternalError
+    }
+}
+
+/// Borrows a string passed by the caller
+unsafe fn to_str<'a>(string: *const c_char) -> Result<&'a str, WatchtowerStatus> {
+    if string.is_null() {
+        return Err(WatchtowerStatus::InvalidArgument);
+    }
+    CStr::from_ptr(string).to_str().map_err(|_| WatchtowerStatus::InvalidArgument)
+}
+
+/// Hands a string over to the caller, who releases it with `watchtower_string_free`
+unsafe fn write_string(string: String, out: *mut *mut c_char) -> Result<(), WatchtowerStatus> {
+    let string = CString::new(string).map_err(|_| WatchtowerStatus::InternalError)?;
+    *out = string.into_raw();
+    Ok(())
+}
+
+/// Creates a client of the registries at `urls`, an array of `url_count` strings
+///
+/// # Safety
+///
+/// Strings must be valid and NUL-terminated, `urls` must point to `url_count` of them, and `client` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_client_new(
+    urls: *const *const c_char,
+    url_count: usize,
+    username: *const c_char,
+    password: *const c_char,
+    client: *mut *mut WatchtowerClientHandle
+) -> WatchtowerStatus {
+    run(|| {
+        if urls.is_null() || client.is_null() {
+            return Err(WatchtowerStatus::InvalidArgument);
+        }
+        let watchtower_urls = slice::from_raw_parts(urls, url_count).iter()
+            .map(|url| to_str(*url).map(|url| url.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let builder = crate::WatchtowerClient::builder(watchtower_urls, to_str(username)?, to_str(password)?);
+        let handle = WatchtowerClientHandle {
+            client: blocking::WatchtowerClient::with_builder(builder)?
+        };
+        *client = Box::into_raw(Box::new(handle));
+        Ok(())
+    })
+}
+
+/// Releases a client, stopping its heartbeat once its registrations are released
+///
+/// # Safety
+///
+/// `client` must be null or returned by `watchtower_client_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_client_free(client: *mut WatchtowerClientHandle) {
+    if !client.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(client))));
+    }
+}
+
+/// Registers an instance of a service, whose lease is renewed in the background until the registration is released
+///
+/// # Safety
+///
+/// `client` must be a live client, strings must be valid and NUL-terminated, and `registration` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_register(
+    client: *const WatchtowerClientHandle,
+    service_id: *const c_char,
+    ip_addr: *const c_char,
+    port: u16,
+    registration: *mut *mut WatchtowerRegistrationHandle
+) -> WatchtowerStatus {
+    run(|| {
+        let client = client.as_ref().ok_or(WatchtowerStatus::InvalidArgument)?;
+        if registration.is_null() {
+            return Err(WatchtowerStatus::InvalidArgument);
+        }
+        let handle = WatchtowerRegistrationHandle {
+            registration: client.client.register(to_str(service_id)?, to_str(ip_addr)?, port)?
+        };
+        *registration = Box::into_raw(Box::new(handle));
+        Ok(())
+    })
+}
+
+/// Writes the instance id of a registration to `instance_id`
+///
+/// # Safety
+///
+/// `registration` must be a live registration, and `instance_id` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_registration_instance_id(
+    registration: *const WatchtowerRegistrationHandle,
+    instance_id: *mut *mut c_char
+) -> WatchtowerStatus {
+    run(|| {
+        let registration = registration.as_ref().ok_or(WatchtowerStatus::InvalidArgument)?;
+        if instance_id.is_null() {
+            return Err(WatchtowerStatus::InvalidArgument);
+        }
+        write_string(registration.registration.instance_id().to_string(), instance_id)
+    })
+}
+
+/// Cancels the lease of a registration, blocking until it is done, and releases it
+///
+/// # Safety
+///
+/// `registration` must be null or returned by `watchtower_register`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_registration_free(registration: *mut WatchtowerRegistrationHandle) {
+    if !registration.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(registration))));
+    }
+}
+
+/// Writes the address of an instance of a service, such as `127.0.0.1:8080`, to `url`
+///
+/// # Safety
+///
+/// `client` must be a live client, `service_id` must be valid and NUL-terminated, and `url` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_get_service_url(
+    client: *const WatchtowerClientHandle,
+    service_id: *const c_char,
+    url: *mut *mut c_char
+) -> WatchtowerStatus {
+    run(|| {
+        let client = client.as_ref().ok_or(WatchtowerStatus::InvalidArgument)?;
+        if url.is_null() {
+            return Err(WatchtowerStatus::InvalidArgument);
+        }
+        write_string(client.client.get_service_url(to_str(service_id)?)?, url)
+    })
+}
+
+/// Writes the url of `path` on an instance of a service with `scheme`, such as `https://127.0.0.1:8080/health`, to `url`
+///
+/// # Safety
+///
+/// `client` must be a live client, strings must be valid and NUL-terminated, and `url` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_build_service_url(
+    client: *const WatchtowerClientHandle,
+    service_id: *const c_char,
+    scheme: *const c_char,
+    path: *const c_char,
+    url: *mut *mut c_char
+) -> WatchtowerStatus {
+    run(|| {
+        let client = client.as_ref().ok_or(WatchtowerStatus::InvalidArgument)?;
+        if url.is_null() {
+            return Err(WatchtowerStatus::InvalidArgument);
+        }
+        let service_url = client.client.build_service_url(to_str(service_id)?, to_str(scheme)?, to_str(path)?)?;
+        write_string(service_url, url)
+    })
+}
+
+/// Releases a string returned by the library
+///
+/// # Safety
+///
+/// `string` must be null or returned by the library, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn watchtower_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Returns a static description of a status, which must not be released
+#[no_mangle]
+pub extern "C" fn watchtower_status_message(status: WatchtowerStatus) -> *const c_char {
+    let message: &'static [u8] = match status {
+        WatchtowerStatus::Ok => b"ok\0",
+        WatchtowerStatus::InternalError => b"internal error\0",
+        WatchtowerStatus::NotFound => b"not found\0",
+        WatchtowerStatus::Unauthorized => b"unauthorized\0",
+        WatchtowerStatus::InstanceAlreadyRegistered => b"instance already registered\0",
+        WatchtowerStatus::MaxRetryReached => b"max retry reached\0",
+        WatchtowerStatus::InvalidPing => b"no instance to ping\0",
+        WatchtowerStatus::NoAvailableInstance => b"no available instance\0",
+        WatchtowerStatus::Timeout => b"timed out\0",
+        WatchtowerStatus::NoRuntime => b"no runtime to spawn background tasks on\0",
+        WatchtowerStatus::InvalidConfig => b"invalid configuration\0",
+        WatchtowerStatus::InvalidUrl => b"invalid url\0",
+        WatchtowerStatus::InvalidArgument => b"invalid argument\0"
+    };
+    message.as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_get_service_url() {
+        let _instances = mockito::mock("GET", "/api/v1/services/test_ffi_get_service_url")
+            .with_status(200)
+            .with_body(r#"[{"instance_id": "a", "ip_addr": "127.0.0.1", "port": 8080, "status": "UP"}]"#)
+            .create();
+        let server_url = CString::new(mockito::SERVER_URL).unwrap();
+        let urls = [server_url.as_ptr()];
+        let username = CString::new("admin").unwrap();
+        let password = CString::new("password").unwrap();
+        let service_id = CString::new("test_ffi_get_service_url").unwrap();
+        unsafe {
+            let mut client = ptr::null_mut();
+            let status = watchtower_client_new(urls.as_ptr(), urls.len(), username.as_ptr(), password.as_ptr(), &mut client);
+            assert_eq!(status, WatchtowerStatus::Ok);
+
+            let mut url = ptr::null_mut();
+            assert_eq!(watchtower_get_service_url(client, service_id.as_ptr(), &mut url), WatchtowerStatus::Ok);
+            assert_eq!(CStr::from_ptr(url).to_str().unwrap(), "127.0.0.1:8080");
+            watchtower_string_free(url);
+            watchtower_client_free(client);
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let service_id = CString::new("test_ffi_invalid_arguments").unwrap();
+        unsafe {
+            let mut client = ptr::null_mut();
+            let status = watchtower_client_new(ptr::null(), 0, ptr::null(), ptr::null(), &mut client);
+            assert_eq!(status, WatchtowerStatus::InvalidArgument);
+            assert!(client.is_null());
+
+            let mut url = ptr::null_mut();
+            assert_eq!(watchtower_get_service_url(ptr::null(), service_id.as_ptr(), &mut url), WatchtowerStatus::InvalidArgument);
+            assert_eq!(CStr::from_ptr(watchtower_status_message(WatchtowerStatus::NotFound)).to_str().unwrap(), "not found");
+        }
+    }
+}
@@ -1,208 +1,638 @@
 use std::{
-    collections::HashMap,
-    sync::Arc
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc
+    },
+    time::Duration
 };
-use pyo3::prelude::*;
-use tokio::sync::Mutex;
+use futures_util::future::{join_all, FutureExt};
+use log::{error, warn};
 use uuid::Uuid;
 
 mod utils;
 mod resources;
 mod error;
 mod types;
+mod builder;
+pub mod blocking;
+#[cfg(feature = "reqwest-client")]
+mod discovery_client;
+#[cfg(feature = "tower")]
+mod tower_support;
+#[cfg(feature = "tonic")]
+mod tonic_support;
+#[cfg(feature = "py")]
+mod python;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(test)]
+mod test_utils;
+
+use crate::{
+    resources::{
+        load_balancer::{LoadBalancer, RequestContext, SelectedInstance},
+        OutlierDetector,
+        ServiceCache,
+        Registrations, cancel_all_registrations, renew_or_register, run_heartbeat,
+        refresh_stale_service, run_refresher
+    },
+    utils::signal::wait_for_shutdown_signal
+};
 
 pub use crate::{
-    resources::{InstanceInfo, Service, HttpClient, load_balancer},
+    builder::WatchtowerClientBuilder,
+    resources::{
+        InstanceInfo, InstanceStatus, InstanceUpdate, LeaseInfo, RegistryHealth, Service, HttpClient,
+        Registration, RegisteredInstance, RegistrationOptions, RegistrationState,
+        CallOutcome, CircuitState, InstanceHealthReport, OutlierDetectionConfig,
+        load_balancer
+    },
     types::{Result, Error},
+    utils::{
+        retry::RetryPolicy,
+        spawner::Spawner
+    },
 };
 
+#[cfg(feature = "actix")]
+pub use crate::utils::spawner::ActixSpawner;
+#[cfg(feature = "reqwest-client")]
+pub use crate::discovery_client::{DiscoveryClient, DiscoveryRequestBuilder};
+#[cfg(feature = "tower")]
+pub use crate::tower_support::{WatchtowerDiscover, WatchtowerLayer, WatchtowerService};
+#[cfg(feature = "tonic")]
+pub use crate::tonic_support::{WatchtowerChannel, GRPC_PORT_METADATA_KEY};
 #[cfg(feature = "py")]
-#[pyclass]
-pub struct PyWatchtowerClient {
-    client: Arc<WatchtowerClient>,
-}
-
-#[cfg(feature = "py")]
-#[pymethods]
-impl PyWatchtowerClient {
-    #[new]
-    pub fn new(watchtower_urls: Vec<String>, username: &str, password: &str) -> Self {
-        let client = Arc::new(WatchtowerClient::new(
-            watchtower_urls,
-            username,
-            password
-        ));
-        PyWatchtowerClient {
-            client
-        }
-    }
+pub use crate::python::{
+    PyWatchtowerClient, PyWatchtowerClientBuilder, PyInstanceInfo, PyAsyncWatchtowerClient, PyAsyncRegistration
+};
 
-    pub fn register(self_: PyRef<Self>, service_id: &str, ip_addr: &str, port: u16) -> PyResult<()> {
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
-        let client = self_.client.clone();
-        rt.block_on(async {
-            client.register_without_pinging(&service_id, ip_addr, port).await
-        })?;
-        Ok(())
-    }
+/// The scheme of logical urls that are sent over http, such as `watchtower://my-service/path`
+pub const WATCHTOWER_SCHEME: &str = "watchtower";
 
-    pub fn ping(self_: PyRef<Self>) -> PyResult<()> {
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
-        let client = self_.client.clone();
-        rt.block_on(async {
-            client.ping().await
-        })?;
-        Ok(())
-    }
+pub struct WatchtowerClient {
+    http_client: Arc<HttpClient>,
+    service_cache: Arc<ServiceCache>,
+    registrations: Registrations,
+    is_heartbeat_running: AtomicBool,
+    is_refresher_running: Arc<AtomicBool>,
+    spawner: Arc<dyn Spawner>,
+    heartbeat_interval: Duration,
+    refresh_interval: Duration,
+    idle_timeout: Duration,
+    max_staleness: Duration,
+    outlier_detector: Arc<OutlierDetector>,
+}
 
-    pub fn cancel(self_: PyRef<Self>) -> PyResult<()> {
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
-        let client = self_.client.clone();
-        rt.block_on(async {
-            client.cancel().await
-        })?;
-        Ok(())
-    }
+/// Resets the flag of a background task once the task is done, or dropped along with its runtime
+struct RunningGuard(Arc<AtomicBool>);
 
-    pub fn get_service_url(self_: PyRef<Self>, service_id: &str) -> PyResult<String> {
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
-        let client = self_.client.clone();
-        let service_url = rt.block_on(async {
-            client.get_service_url(service_id).await
-        })?;
-        Ok(service_url)
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
     }
 }
 
-#[cfg(feature = "py")]
-#[pymodule]
-fn watchtower_client(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_class::<PyWatchtowerClient>()?;
-    Ok(())
-}
-
-pub struct WatchtowerClient {
-    http_client: Arc<HttpClient>,
-    services: Mutex<HashMap<String, Service>>,
-    instance_info: Arc<Mutex<Option<(String, InstanceInfo)>>>,
+async fn deregister_all(http_client: &HttpClient, registrations: &Registrations, timeout: Duration) -> Result<()> {
+    match tokio::time::timeout(timeout, cancel_all_registrations(http_client, registrations)).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::Timeout)
+    }
 }
 
-const HEARTBEAT_INTERVAL_SEC: u64 = 15;
-
 impl WatchtowerClient {
+    /// Creates a client with the default configuration
+    ///
+    /// Panics if `watchtower_urls` is empty or the HTTP client cannot be built. Use `WatchtowerClient::builder` to
+    /// handle these errors or to change the configuration.
     pub fn new(watchtower_urls: Vec<String>, username: &str, password: &str) -> Self {
-        let http_client = Arc::new(HttpClient::new(watchtower_urls, username.to_string(), password.to_string()));
-        WatchtowerClient {
-            http_client,
-            services: Mutex::new(HashMap::new()),
-            instance_info: Arc::new(Mutex::new(None))
-        }
+        Self::builder(watchtower_urls, username, password).build().expect("Fails to build the client")
     }
 
-    fn generate_new_instance(ip_addr: &str, port: u16) -> InstanceInfo {
+    /// Creates a client whose background tasks, such as the heartbeat, run on `spawner`
+    pub fn with_spawner(watchtower_urls: Vec<String>, username: &str, password: &str, spawner: Arc<dyn Spawner>) -> Self {
+        Self::builder(watchtower_urls, username, password).spawner(spawner).build().expect("Fails to build the client")
+    }
+
+    /// Returns a builder to configure the client
+    pub fn builder(watchtower_urls: Vec<String>, username: &str, password: &str) -> WatchtowerClientBuilder {
+        WatchtowerClientBuilder::new(watchtower_urls, username, password)
+    }
+
+    fn generate_new_instance(ip_addr: &str, port: u16, options: &RegistrationOptions) -> InstanceInfo {
         let instance_id = Uuid::new_v4().to_string();
         InstanceInfo {
             instance_id: instance_id.to_string(),
             ip_addr: ip_addr.to_string(),
-            port
+            port,
+            status: InstanceStatus::Up,
+            weight: options.weight,
+            metadata: options.metadata.clone()
         }
     }
 
-    async fn register_helper(&self, service_id: &str, new_instance_info: &InstanceInfo) -> Result<()> {
-        if let Some(_) = *self.instance_info.lock().await {
-            return Err(Error::InstanceAlreadyRegistered);
-        }
-        *self.instance_info.lock().await = Some((service_id.to_string(), new_instance_info.clone()));
-        self.http_client.register(&service_id, &new_instance_info).await
+    async fn register_helper(
+        &self,
+        service_id: &str,
+        ip_addr: &str,
+        port: u16,
+        options: &RegistrationOptions,
+        is_heartbeat_enabled: bool
+    ) -> Result<Registration> {
+        let new_instance_info = Self::generate_new_instance(ip_addr, port, options);
+        self.http_client.register(service_id, &new_instance_info).await?;
+        let registered_instance = RegisteredInstance::new(service_id, new_instance_info.clone(), is_heartbeat_enabled);
+        let state = registered_instance.watch_state();
+        self.registrations.lock().unwrap().insert(new_instance_info.instance_id.to_string(), registered_instance);
+        Ok(Registration::new(
+            service_id,
+            &new_instance_info.instance_id,
+            self.http_client.clone(),
+            self.registrations.clone(),
+            self.spawner.clone(),
+            state
+        ))
     }
 
     /// Register a new service
     /// 
-    /// This will spawn a child process to ping the service registry
-    /// Note: only one service can be registered at a time
-    pub async fn register(&self, service_id: &str, ip_addr: &str, port: u16) -> Result<()> {
-        let new_instance_info = Self::generate_new_instance(ip_addr, port);
-        self.register_helper(&service_id, &new_instance_info).await?;
-
-        let client = self.http_client.clone();
-        let instance_info = self.instance_info.clone();
-        let service_id = service_id.to_string();
-        actix::spawn(async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(HEARTBEAT_INTERVAL_SEC));
-            loop {
-                interval.tick().await;
-                if let Some((_, instance_info)) = &*instance_info.lock().await {
-                    if instance_info.instance_id != new_instance_info.instance_id {
-                        // the instance info does not match the current instance info
-                        return;
-                    }
-                } else {
-                    // the instance info is no longer there
-                    return;
-                }
-                client.renew(&service_id, &new_instance_info).await.unwrap();
+    /// The lease is renewed by a background heartbeat shared by every registration of this client.
+    /// Multiple instances, of the same or different services, can be registered from one client.
+    /// Returns `Error::NoRuntime` if the heartbeat cannot be spawned, in which case nothing is registered.
+    pub async fn register(&self, service_id: &str, ip_addr: &str, port: u16) -> Result<Registration> {
+        self.register_with_options(service_id, ip_addr, port, RegistrationOptions::default()).await
+    }
+
+    /// Register a new service with `options`, such as its weight
+    ///
+    /// The lease is renewed in the background, as with `register`.
+    pub async fn register_with_options(&self, service_id: &str, ip_addr: &str, port: u16, options: RegistrationOptions) -> Result<Registration> {
+        if !self.is_heartbeat_running.swap(true, Ordering::SeqCst) {
+            let http_client = self.http_client.clone();
+            let registrations = Arc::downgrade(&self.registrations);
+            let heartbeat = run_heartbeat(http_client, registrations, self.heartbeat_interval);
+            if let Err(err) = self.spawner.spawn(heartbeat.boxed()) {
+                self.is_heartbeat_running.store(false, Ordering::SeqCst);
+                return Err(err);
             }
-        });
-        Ok(())
+        }
+        self.register_helper(service_id, ip_addr, port, &options, true).await
+    }
+
+    /// Register a new service without renewing its lease in the background
+    ///
+    /// The lease has to be kept alive by calling `ping`.
+    pub async fn register_without_pinging(&self, service_id: &str, ip_addr: &str, port: u16) -> Result<Registration> {
+        self.register_helper(service_id, ip_addr, port, &RegistrationOptions::default(), false).await
     }
 
-    pub async fn register_without_pinging(&self, service_id: &str, ip_addr: &str, port: u16) -> Result<()> {
-        let new_instance_info = Self::generate_new_instance(ip_addr, port);
-        self.register_helper(&service_id, &new_instance_info).await
+    /// Returns every instance currently registered through this client
+    pub fn get_registrations(&self) -> Vec<RegisteredInstance> {
+        self.registrations.lock().unwrap().values().cloned().collect()
     }
     
+    /// Renew the leases of every instance registered through this client
     pub async fn ping(&self) -> Result<()> {
-        let my_instance_info;
-        let my_service_id;
-        if let Some((service_id, instance_info)) = &*self.instance_info.lock().await {
-            my_instance_info = instance_info.clone();
-            my_service_id = service_id.to_string();
-        } else {
-            // the instance info is no longer there
+        let registered_instances = self.get_registrations();
+        if registered_instances.is_empty() {
             return Err(Error::InvalidPing);
         }
-        self.http_client.renew(&my_service_id, &my_instance_info).await
+        let results = join_all(registered_instances.iter().map(|registered_instance| {
+            renew_or_register(&self.http_client, registered_instance)
+        })).await;
+        results.into_iter().collect()
     }
 
-    /// Cancel a lease for a service
+    /// Cancel the leases of every instance registered through this client
     pub async fn cancel(&self) -> Result<()> {
-        let (service_id, instance_info) = match &*self.instance_info.lock().await {
-            Some((service_id, instance_info)) => (service_id.to_string(), instance_info.clone()),
-            None => return Err(Error::NotFound)
-        };
-        self.http_client.cancel(&service_id, &instance_info).await?;
-        *self.instance_info.lock().await = None;
-        Ok(())
+        if self.registrations.lock().unwrap().is_empty() {
+            return Err(Error::NotFound);
+        }
+        cancel_all_registrations(&self.http_client, &self.registrations).await
+    }
+
+    /// Cancel the leases of every instance registered through this client, giving up after `timeout`
+    pub async fn deregister_all(&self, timeout: Duration) -> Result<()> {
+        deregister_all(&self.http_client, &self.registrations, timeout).await
     }
 
-    async fn refetch_service(&self, service_id: &str) -> Result<InstanceInfo> {
-        let instance_infos: Vec<InstanceInfo> = self.http_client.get_all_instances(service_id).await?;
+    /// Wait for SIGTERM or SIGINT, then cancel the leases of every instance registered through this client
+    ///
+    /// Deregistration gives up after `timeout`. Once a handler is installed, the signals no longer terminate the process,
+    /// so the caller is expected to exit once this returns.
+    pub async fn deregister_on_signal(&self, timeout: Duration) -> Result<()> {
+        wait_for_shutdown_signal().await?;
+        self.deregister_all(timeout).await
+    }
+
+    /// Spawn a task that waits for SIGTERM or SIGINT, cancels the leases of every instance registered through this
+    /// client and exits the process
+    ///
+    /// Deregistration gives up after `timeout`.
+    pub fn exit_on_signal(&self, timeout: Duration) -> Result<()> {
+        let http_client = self.http_client.clone();
+        let registrations = self.registrations.clone();
+        self.spawner.spawn(async move {
+            if let Err(err) = wait_for_shutdown_signal().await {
+                error!("Unable to listen for shutdown signals: {:?}", err);
+                return;
+            }
+            if let Err(err) = deregister_all(&http_client, &registrations, timeout).await {
+                error!("Unable to deregister before exiting: {:?}", err);
+            }
+            std::process::exit(0);
+        }.boxed())
+    }
 
-        let mut service = Service::new(instance_infos);
-        let instance_info = service.get_next_instance()?;
+    /// Select instances of a service with `load_balancer` from now on
+    pub async fn set_load_balancer(&self, service_id: &str, load_balancer: Arc<dyn LoadBalancer>) {
+        self.service_cache.set_load_balancer(service_id, load_balancer);
+    }
 
-        self.services.lock().await.insert(service_id.to_string(), service);
-        Ok(instance_info)
+    /// Spawns the background refresher of cached services, unless it is running
+    fn start_refresher(&self) {
+        if self.is_refresher_running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let running_guard = RunningGuard(self.is_refresher_running.clone());
+        let refresher = run_refresher(Arc::downgrade(&self.service_cache), self.refresh_interval, self.idle_timeout);
+        let result = self.spawner.spawn(async move {
+            let _running_guard = running_guard;
+            refresher.await
+        }.boxed());
+        if let Err(err) = result {
+            warn!("Unable to refresh services in the background: {:?}", err);
+        }
+    }
+
+    /// Selects an instance of `service` that is not ejected by the outlier detector nor excluded by `context`
+    ///
+    /// A half-open instance is only selected by one caller at a time, whose call probes it. If every instance is
+    /// ejected, one of them is selected anyway, but never one that is being probed, in which case
+    /// `Error::NoAvailableInstance` is returned.
+    fn select_available(&self, service_id: &str, service: &Service, context: &RequestContext) -> Result<InstanceInfo> {
+        let mut instance_infos = self.outlier_detector.filter_available(service_id, &service.instance_infos);
+        if instance_infos.iter().any(|instance_info| !context.is_excluded(&instance_info.instance_id)) {
+            instance_infos.retain(|instance_info| !context.is_excluded(&instance_info.instance_id));
+        }
+        let mut ejected_instance_info = None;
+        loop {
+            let instance_info = service.select_from(&instance_infos, context)?;
+            if self.outlier_detector.start_call(service_id, &instance_info.instance_id) {
+                return Ok(instance_info);
+            }
+            instance_infos.retain(|other| other.instance_id != instance_info.instance_id);
+            if ejected_instance_info.is_none() && !self.outlier_detector.is_probing(service_id, &instance_info.instance_id) {
+                ejected_instance_info = Some(instance_info);
+            }
+            if instance_infos.is_empty() {
+                return ejected_instance_info.ok_or(Error::NoAvailableInstance);
+            }
+        }
+    }
+
+    /// Reads a service with `read`, along with how long ago it expired if the registry is unreachable and the last
+    /// known instances are read instead
+    ///
+    /// The service is fetched first if it is not cached or has expired. Stale instances are read for up to the maximum
+    /// staleness, after which `Error::MaxRetryReached` is returned.
+    async fn lookup<T, F>(&self, service_id: &str, read: F) -> Result<(T, Option<Duration>)>
+    where
+        F: Fn(&Service) -> Result<T>
+    {
+        self.start_refresher();
+        if let Some(service) = self.service_cache.services().get(service_id) {
+            if !service.is_expired()? {
+                service.mark_used();
+                return Ok((read(&service)?, None));
+            }
+            if service.is_stale() {
+                // The service is being refreshed in the background
+                return self.read_stale(&service, read);
+            }
+        }
+
+        match self.service_cache.fetch(service_id).await {
+            Ok(()) => {
+                let service = self.service_cache.services().get(service_id).ok_or(Error::NoAvailableInstance)?;
+                service.mark_used();
+                Ok((read(&service)?, None))
+            },
+            Err(Error::MaxRetryReached) => {
+                let mut service = self.service_cache.services().get_mut(service_id).ok_or(Error::MaxRetryReached)?;
+                if service.mark_stale() {
+                    warn!("Unable to reach the registry, serving the last known instances of {}", service_id);
+                    let refresh = refresh_stale_service(
+                        self.http_client.clone(),
+                        Arc::downgrade(&self.service_cache),
+                        service_id.to_string(),
+                        self.max_staleness
+                    );
+                    if let Err(err) = self.spawner.spawn(refresh.boxed()) {
+                        error!("Unable to refresh {} in the background: {:?}", service_id, err);
+                    }
+                }
+                self.read_stale(&service, read)
+            },
+            Err(err) => Err(err)
+        }
+    }
+
+    fn read_stale<T, F>(&self, service: &Service, read: F) -> Result<(T, Option<Duration>)>
+    where
+        F: Fn(&Service) -> Result<T>
+    {
+        let staleness = service.staleness();
+        if staleness > self.max_staleness {
+            return Err(Error::MaxRetryReached);
+        }
+        service.mark_used();
+        Ok((read(service)?, Some(staleness)))
+    }
+
+    /// Selects an instance of a service, with how long ago the service expired if the last known instances are
+    /// served instead
+    async fn select(&self, service_id: &str, context: &RequestContext) -> Result<(InstanceInfo, Option<Duration>)> {
+        self.lookup(service_id, |service| self.select_available(service_id, service, context)).await
+    }
+
+    /// Returns how long ago the cached instances of a service expired, if they are served because the registry is
+    /// unreachable
+    pub async fn get_staleness(&self, service_id: &str) -> Option<Duration> {
+        self.service_cache.services().get(service_id)
+            .filter(|service| service.is_stale())
+            .map(|service| service.staleness())
     }
 
     /// Get the url of the service
     pub async fn get_service_url(&self, service_id: &str) -> Result<String> {
-        let maybe_instance_info = match self.services.lock().await.get_mut(service_id) {
-            Some(service) => {
-                if service.is_expired()? {
-                    None
-                } else {
-                    Some(service.get_next_instance()?)
-                }
-            }
-            None => None
-        };
+        self.get_service_url_with_context(service_id, &RequestContext::default()).await
+    }
+
+    /// Get the url of the service for a request with `context`, such as the hash key of a consistent hash load balancer
+    pub async fn get_service_url_with_context(&self, service_id: &str, context: &RequestContext) -> Result<String> {
+        let (instance_info, _) = self.select(service_id, context).await?;
+        Ok(instance_info.address())
+    }
+
+    /// Returns every instance of the service that is up, for example to send a request to all of them
+    ///
+    /// Unlike the selection of an instance, this includes the instances ejected by outlier detection.
+    pub async fn get_instances(&self, service_id: &str) -> Result<Vec<InstanceInfo>> {
+        let (instance_infos, _) = self.lookup(service_id, |service| Ok(service.instance_infos.clone())).await?;
+        Ok(instance_infos)
+    }
+
+    /// Choose an instance of the service with its load balancer
+    pub async fn choose_instance(&self, service_id: &str) -> Result<InstanceInfo> {
+        let (instance_info, _) = self.select(service_id, &RequestContext::default()).await?;
+        Ok(instance_info)
+    }
+
+    /// Build the url of `path` on an instance of the service with `scheme`, such as `http://127.0.0.1:8080/health`
+    pub async fn build_service_url(&self, service_id: &str, scheme: &str, path: &str) -> Result<String> {
+        Ok(self.choose_instance(service_id).await?.url(scheme, path))
+    }
+
+    /// Select an instance of the service for a request with `context`
+    ///
+    /// The load balancer counts the request as outstanding until the returned `SelectedInstance` is dropped, so it
+    /// should be kept for the duration of the request.
+    pub async fn select_instance(&self, service_id: &str, context: &RequestContext) -> Result<SelectedInstance> {
+        let (instance_info, staleness) = self.select(service_id, context).await?;
+        let selected_instance = SelectedInstance::new(service_id, instance_info, self.service_cache.load_balancer(service_id), self.outlier_detector.clone());
+        Ok(selected_instance.with_staleness(staleness))
+    }
 
-        let instance_info = match maybe_instance_info {
-            Some(service) => service,
-            None => self.refetch_service(service_id).await?
+    /// Report the outcome of a call to an instance of the service
+    ///
+    /// Instances with consecutive failures or a high failure rate are ejected from selection for a while, then put back
+    /// on probation: the next failure ejects them again, for longer.
+    pub fn report(&self, service_id: &str, instance_id: &str, outcome: CallOutcome) {
+        self.outlier_detector.report(service_id, instance_id, outcome);
+    }
+
+    /// Returns the health of every instance a call has been reported for, including whether it is ejected
+    pub fn get_instance_health(&self) -> Vec<InstanceHealthReport> {
+        self.outlier_detector.report_health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_mock_builder;
+
+    const INSTANCES: &str = r#"[{"instance_id": "instance", "ip_addr": "127.0.0.1", "port": 8080, "status": "UP"}]"#;
+
+    fn create_client(max_staleness: Duration) -> WatchtowerClient {
+        create_mock_builder()
+            .max_attempts(1)
+            .backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .cache_ttl(Duration::from_millis(10))
+            .max_staleness(max_staleness)
+            .build()
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_stale_service() {
+        let path = "/api/v1/services/test_serve_stale";
+        let instances = mockito::mock("GET", path).with_status(200).with_body(INSTANCES).create();
+        let client = create_client(Duration::from_secs(60));
+        assert_eq!(client.get_service_url("test_serve_stale").await.unwrap(), "127.0.0.1:8080");
+        drop(instances);
+
+        let _unavailable = mockito::mock("GET", path).with_status(503).create();
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+        let selected_instance = client.select_instance("test_serve_stale", &RequestContext::default()).await.unwrap();
+        assert_eq!(selected_instance.url(), "127.0.0.1:8080");
+        assert!(selected_instance.staleness().is_some());
+        assert!(client.get_staleness("test_serve_stale").await.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn test_url_of_ipv6_instance() {
+        let instances = r#"[{"instance_id": "instance", "ip_addr": "::1", "port": 8080, "status": "UP"}]"#;
+        let _instances = mockito::mock("GET", "/api/v1/services/test_ipv6").with_status(200).with_body(instances).create();
+        let client = create_client(Duration::from_secs(60));
+        assert_eq!(client.get_service_url("test_ipv6").await.unwrap(), "[::1]:8080");
+        let selected_instance = client.select_instance("test_ipv6", &RequestContext::default()).await.unwrap();
+        assert_eq!(selected_instance.url(), "[::1]:8080");
+    }
+
+    #[actix_rt::test]
+    async fn test_max_staleness() {
+        let path = "/api/v1/services/test_max_staleness";
+        let instances = mockito::mock("GET", path).with_status(200).with_body(INSTANCES).create();
+        let client = create_client(Duration::from_millis(50));
+        assert!(client.get_service_url("test_max_staleness").await.is_ok());
+        drop(instances);
+
+        let _unavailable = mockito::mock("GET", path).with_status(503).create();
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        assert_eq!(client.get_service_url("test_max_staleness").await, Err(Error::MaxRetryReached));
+    }
+
+    #[actix_rt::test]
+    async fn test_drop_service_stale_for_too_long() {
+        let path = "/api/v1/services/test_drop_stale";
+        let instances = mockito::mock("GET", path).with_status(200).with_body(INSTANCES).create();
+        let client = create_mock_builder()
+            .max_attempts(1)
+            .backoff(Duration::from_millis(20), Duration::from_millis(20))
+            .cache_ttl(Duration::from_millis(10))
+            .max_staleness(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        assert!(client.get_service_url("test_drop_stale").await.is_ok());
+        drop(instances);
+
+        let unavailable = mockito::mock("GET", path).with_status(503).create();
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+        assert!(client.get_service_url("test_drop_stale").await.is_ok());
+        tokio::time::delay_for(Duration::from_millis(300)).await;
+        assert!(!client.service_cache.services().contains_key("test_drop_stale"));
+        drop(unavailable);
+
+        // The dropped service is fetched again once the registry is back
+        let _instances = mockito::mock("GET", path).with_status(200).with_body(INSTANCES).create();
+        assert_eq!(client.get_service_url("test_drop_stale").await.unwrap(), "127.0.0.1:8080");
+    }
+
+    #[actix_rt::test]
+    async fn test_single_probe_of_half_open_instance() {
+        let _instances = mockito::mock("GET", "/api/v1/services/test_single_probe").with_status(200).with_body(INSTANCES).create();
+        let client = create_mock_builder()
+            .outlier_detection(OutlierDetectionConfig {
+                consecutive_failures: 1,
+                base_ejection_time: Duration::from_millis(50),
+                ..OutlierDetectionConfig::default()
+            })
+            .build()
+            .unwrap();
+        let context = RequestContext::default();
+        let selected_instance = client.select_instance("test_single_probe", &context).await.unwrap();
+        selected_instance.report(CallOutcome::failure(Duration::from_millis(10)));
+
+        // An ejected instance is still selected when it is the only one
+        assert!(client.select_instance("test_single_probe", &context).await.is_ok());
+
+        // Once the ejection is over, only one of two concurrent selections probes the instance
+        tokio::time::delay_for(Duration::from_millis(60)).await;
+        let (probe, other) = futures_util::future::join(
+            client.select_instance("test_single_probe", &context),
+            client.select_instance("test_single_probe", &context)
+        ).await;
+        assert!(probe.is_ok());
+        assert_eq!(other.err(), Some(Error::NoAvailableInstance));
+
+        probe.unwrap().report(CallOutcome::success(Duration::from_millis(10)));
+        assert!(client.select_instance("test_single_probe", &context).await.is_ok());
+        assert!(client.select_instance("test_single_probe", &context).await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn test_seed_from_cache_file() {
+        let path = "/api/v1/services/test_seed_from_cache_file";
+        let cache_file = std::env::temp_dir().join(format!("watchtower_test_seed_{}.json", std::process::id()));
+        let instances = mockito::mock("GET", path).with_status(200).with_body(INSTANCES).create();
+        let client = create_mock_builder()
+            .cache_file(&cache_file)
+            .build()
+            .unwrap();
+        assert!(client.get_service_url("test_seed_from_cache_file").await.is_ok());
+        drop(instances);
+
+        // A client started during a registry outage resolves the service from the cache file
+        let unavailable = mockito::mock("GET", path).with_status(503).expect(1).create();
+        let client = create_mock_builder()
+            .max_attempts(1)
+            .backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .cache_file(&cache_file)
+            .build()
+            .unwrap();
+        let selected_instance = client.select_instance("test_seed_from_cache_file", &RequestContext::default()).await.unwrap();
+        assert_eq!(selected_instance.url(), "127.0.0.1:8080");
+        assert!(selected_instance.staleness().is_some());
+        unavailable.assert();
+        std::fs::remove_file(&cache_file).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_staleness_of_cache_file() {
+        let path = "/api/v1/services/test_staleness_of_cache_file";
+        let cache_file = std::env::temp_dir().join(format!("watchtower_test_staleness_{}.json", std::process::id()));
+        let fetched_at_millis = |age: Duration| {
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64 - age.as_millis() as u64
+        };
+        let write_cache_file = |age: Duration| {
+            std::fs::write(&cache_file, format!(
+                r#"{{"version": 1, "services": {{"test_staleness_of_cache_file": {{"instance_infos": {}, "fetched_at_millis": {}}}}}}}"#,
+                INSTANCES, fetched_at_millis(age)
+            )).unwrap();
         };
-        
-        Ok(format!("{}:{}", instance_info.ip_addr, instance_info.port))
+        let _unavailable = mockito::mock("GET", path).with_status(503).create();
+        let create_seeded_client = || create_mock_builder()
+            .max_attempts(1)
+            .backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .cache_ttl(Duration::from_secs(30))
+            .max_staleness(Duration::from_secs(300))
+            .cache_file(&cache_file)
+            .build()
+            .unwrap();
+
+        // The staleness of seeded instances counts from when they were fetched
+        write_cache_file(Duration::from_secs(90));
+        let selected_instance = create_seeded_client().select_instance("test_staleness_of_cache_file", &RequestContext::default()).await.unwrap();
+        assert!(selected_instance.staleness().unwrap() >= Duration::from_secs(60));
+
+        // A week-old cache file is not served
+        write_cache_file(Duration::from_secs(7 * 24 * 3600));
+        let client = create_seeded_client();
+        assert_eq!(client.get_service_url("test_staleness_of_cache_file").await, Err(Error::MaxRetryReached));
+        std::fs::remove_file(&cache_file).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_concurrent_fetches_are_coalesced() {
+        let instances = mockito::mock("GET", "/api/v1/services/test_coalesced").with_status(200).with_body(INSTANCES).expect(1).create();
+        let client = create_client(Duration::from_secs(60));
+        let urls = join_all((0..10).map(|_| client.get_service_url("test_coalesced"))).await;
+        assert!(urls.into_iter().all(|url| url == Ok("127.0.0.1:8080".to_string())));
+        instances.assert();
+    }
+
+    #[actix_rt::test]
+    async fn test_refresh_in_background() {
+        let path = "/api/v1/services/test_refresh_in_background";
+        let instances = mockito::mock("GET", path).with_status(200).with_body(INSTANCES).create();
+        let client = create_mock_builder()
+            .cache_ttl(Duration::from_millis(500))
+            .refresh_interval(Duration::from_millis(400))
+            .build()
+            .unwrap();
+        assert!(client.get_service_url("test_refresh_in_background").await.is_ok());
+        drop(instances);
+
+        let new_instances = INSTANCES.replace("8080", "9090");
+        let _new_instances = mockito::mock("GET", path).with_status(200).with_body(&new_instances).create();
+        tokio::time::delay_for(Duration::from_millis(600)).await;
+        let service = client.service_cache.services().get("test_refresh_in_background").unwrap();
+        assert!(!service.is_expired().unwrap());
+        assert_eq!(service.instance_infos[0].port, 9090);
+    }
+
+    #[actix_rt::test]
+    async fn test_evict_idle_services() {
+        let _instances = mockito::mock("GET", "/api/v1/services/test_evict_idle").with_status(200).with_body(INSTANCES).create();
+        let client = create_mock_builder()
+            .refresh_interval(Duration::from_millis(50))
+            .idle_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        assert!(client.get_service_url("test_evict_idle").await.is_ok());
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        assert!(!client.service_cache.services().contains_key("test_evict_idle"));
     }
 }
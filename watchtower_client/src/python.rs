@@ -0,0 +1,644 @@
+//! The `watchtower_client` Python module
+//!
+//! The clients run on a `blocking::WatchtowerClient`, whose runtime runs requests and the heartbeat on a thread of its
+//! own. Errors are raised as subclasses of `WatchtowerError`, one per variant of `Error`.
+
+// The `#[pymethods]` of pyo3 0.16 expand to impl blocks nested in a static
+#![allow(non_local_definitions)]
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use futures_util::future::FutureExt;
+use log::warn;
+use pyo3::{create_exception, exceptions::{PyException, PyValueError}, prelude::*};
+use crate::{
+    blocking,
+    types::{InstanceInfo, Result, Error},
+    InstanceStatus,
+    Registration,
+    RegistrationOptions,
+    RetryPolicy,
+    WatchtowerClient
+};
+
+create_exception!(watchtower_client, WatchtowerError, PyException, "The base class of the errors of the client");
+create_exception!(watchtower_client, InternalError, WatchtowerError, "The registry or the connection to it failed");
+create_exception!(watchtower_client, NotFoundError, WatchtowerError, "The service or the instance is not on the registry");
+create_exception!(watchtower_client, UnauthorizedError, WatchtowerError, "The credentials were rejected by the registry");
+create_exception!(watchtower_client, InstanceAlreadyRegisteredError, WatchtowerError, "The instance is already registered");
+create_exception!(watchtower_client, MaxRetryReachedError, WatchtowerError, "Every attempt of the request failed");
+create_exception!(watchtower_client, InvalidPingError, WatchtowerError, "No instance is registered through the client");
+create_exception!(watchtower_client, NoAvailableInstanceError, WatchtowerError, "No instance of the service is available");
+create_exception!(watchtower_client, RequestTimeoutError, WatchtowerError, "The request timed out");
+create_exception!(watchtower_client, NoRuntimeError, WatchtowerError, "The runtime of the client could not be started");
+create_exception!(watchtower_client, InvalidConfigError, WatchtowerError, "The configuration of the client is invalid");
+create_exception!(watchtower_client, InvalidUrlError, WatchtowerError, "A url is invalid");
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        let message = err.to_string();
+        match err {
+            Error::InternalError => InternalError::new_err(message),
+            Error::NotFound => NotFoundError::new_err(message),
+            Error::Unauthorized => UnauthorizedError::new_err(message),
+            Error::InstanceAlreadyRegistered => InstanceAlreadyRegisteredError::new_err(message),
+            Error::MaxRetryReached => MaxRetryReachedError::new_err(message),
+            Error::InvalidPing => InvalidPingError::new_err(message),
+            Error::NoAvailableInstance => NoAvailableInstanceError::new_err(message),
+            Error::Timeout => RequestTimeoutError::new_err(message),
+            Error::NoRuntime => NoRuntimeError::new_err(message),
+            Error::InvalidConfig => InvalidConfigError::new_err(message),
+            Error::InvalidUrl => InvalidUrlError::new_err(message)
+        }
+    }
+}
+
+/// An instance of a service, as returned by `get_instances` and `choose_instance`
+#[pyclass]
+#[derive(Clone)]
+pub struct PyInstanceInfo {
+    #[pyo3(get)]
+    instance_id: String,
+    #[pyo3(get)]
+    ip_addr: String,
+    #[pyo3(get)]
+    port: u16,
+    /// The status as sent by the registry, such as `UP`
+    #[pyo3(get)]
+    status: String,
+    #[pyo3(get)]
+    weight: u32,
+    #[pyo3(get)]
+    metadata: HashMap<String, String>
+}
+
+impl From<InstanceInfo> for PyInstanceInfo {
+    fn from(instance_info: InstanceInfo) -> Self {
+        let status = serde_json::to_value(instance_info.status).ok()
+            .and_then(|status| status.as_str().map(|status| status.to_string()))
+            .unwrap_or_default();
+        PyInstanceInfo {
+            instance_id: instance_info.instance_id,
+            ip_addr: instance_info.ip_addr,
+            port: instance_info.port,
+            status,
+            weight: instance_info.weight,
+            metadata: instance_info.metadata
+        }
+    }
+}
+
+#[pymethods]
+impl PyInstanceInfo {
+    /// Builds the url of `path` on the instance with `scheme`, such as `http://127.0.0.1:8080/health`
+    #[args(scheme = "\"http\"", path = "\"\"")]
+    pub fn url(&self, scheme: &str, path: &str) -> String {
+        let instance_info = InstanceInfo {
+            instance_id: self.instance_id.clone(),
+            ip_addr: self.ip_addr.clone(),
+            port: self.port,
+            status: InstanceStatus::default(),
+            weight: self.weight,
+            metadata: self.metadata.clone()
+        };
+        instance_info.url(scheme, path)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PyInstanceInfo(instance_id='{}', ip_addr='{}', port={}, status='{}', weight={}, metadata={:?})",
+            self.instance_id, self.ip_addr, self.port, self.status, self.weight, self.metadata
+        )
+    }
+}
+
+/// The options of the Python clients, where durations are given in seconds
+///
+/// Unset options keep the defaults of `WatchtowerClientBuilder`.
+#[derive(Clone, Default)]
+struct ClientOptions {
+    connect_timeout: Option<f64>,
+    request_timeout: Option<f64>,
+    max_attempts: Option<u16>,
+    initial_backoff: Option<f64>,
+    max_backoff: Option<f64>,
+    heartbeat_interval: Option<f64>,
+    cache_ttl: Option<f64>,
+    refresh_interval: Option<f64>,
+    idle_timeout: Option<f64>,
+    max_staleness: Option<f64>,
+    cache_file: Option<String>,
+    user_agent: Option<String>
+}
+
+/// Converts a duration option given in seconds, raising `ValueError` if it is negative, NaN or infinite
+fn to_duration(name: &str, seconds: f64) -> PyResult<Duration> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(PyValueError::new_err(format!("{} must be a finite, non-negative number of seconds, got {}", name, seconds)));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+impl ClientOptions {
+    /// Builds the client behind the Python clients
+    fn build(&self, watchtower_urls: Vec<String>, username: &str, password: &str) -> PyResult<blocking::WatchtowerClient> {
+        let mut builder = WatchtowerClient::builder(watchtower_urls, username, password);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(to_duration("connect_timeout", timeout)?);
+        }
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.request_timeout(to_duration("request_timeout", timeout)?);
+        }
+        if let Some(max_attempts) = self.max_attempts {
+            builder = builder.max_attempts(max_attempts);
+        }
+        if self.initial_backoff.is_some() || self.max_backoff.is_some() {
+            let default_retry_policy = RetryPolicy::default();
+            let initial_backoff = match self.initial_backoff {
+                Some(backoff) => to_duration("initial_backoff", backoff)?,
+                None => default_retry_policy.initial_backoff
+            };
+            let max_backoff = match self.max_backoff {
+                Some(backoff) => to_duration("max_backoff", backoff)?,
+                None => default_retry_policy.max_backoff
+            };
+            builder = builder.backoff(initial_backoff, max_backoff);
+        }
+        if let Some(interval) = self.heartbeat_interval {
+            builder = builder.heartbeat_interval(to_duration("heartbeat_interval", interval)?);
+        }
+        if let Some(ttl) = self.cache_ttl {
+            builder = builder.cache_ttl(to_duration("cache_ttl", ttl)?);
+        }
+        if let Some(interval) = self.refresh_interval {
+            builder = builder.refresh_interval(to_duration("refresh_interval", interval)?);
+        }
+        if let Some(timeout) = self.idle_timeout {
+            builder = builder.idle_timeout(to_duration("idle_timeout", timeout)?);
+        }
+        if let Some(max_staleness) = self.max_staleness {
+            builder = builder.max_staleness(to_duration("max_staleness", max_staleness)?);
+        }
+        if let Some(path) = &self.cache_file {
+            builder = builder.cache_file(path);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        Ok(blocking::WatchtowerClient::with_builder(builder)?)
+    }
+}
+
+/// Builds the options of a registration from the keyword arguments of `register`
+fn create_registration_options(weight: Option<u32>, metadata: Option<HashMap<String, String>>) -> RegistrationOptions {
+    let default_options = RegistrationOptions::default();
+    RegistrationOptions {
+        weight: weight.unwrap_or(default_options.weight),
+        metadata: metadata.unwrap_or(default_options.metadata)
+    }
+}
+
+/// Configures a `PyWatchtowerClient` or a `PyAsyncWatchtowerClient` one option at a time
+///
+/// Every setter returns the builder, so calls can be chained.
+#[pyclass]
+#[derive(Clone)]
+pub struct PyWatchtowerClientBuilder {
+    watchtower_urls: Vec<String>,
+    username: String,
+    password: String,
+    options: ClientOptions
+}
+
+#[pymethods]
+impl PyWatchtowerClientBuilder {
+    #[new]
+    pub fn new(watchtower_urls: Vec<String>, username: &str, password: &str) -> Self {
+        PyWatchtowerClientBuilder {
+            watchtower_urls,
+            username: username.to_string(),
+            password: password.to_string(),
+            options: ClientOptions::default()
+        }
+    }
+
+    pub fn connect_timeout(mut self_: PyRefMut<Self>, timeout: f64) -> PyRefMut<Self> {
+        self_.options.connect_timeout = Some(timeout);
+        self_
+    }
+
+    pub fn request_timeout(mut self_: PyRefMut<Self>, timeout: f64) -> PyRefMut<Self> {
+        self_.options.request_timeout = Some(timeout);
+        self_
+    }
+
+    pub fn max_attempts(mut self_: PyRefMut<Self>, max_attempts: u16) -> PyRefMut<Self> {
+        self_.options.max_attempts = Some(max_attempts);
+        self_
+    }
+
+    pub fn backoff(mut self_: PyRefMut<Self>, initial_backoff: f64, max_backoff: f64) -> PyRefMut<Self> {
+        self_.options.initial_backoff = Some(initial_backoff);
+        self_.options.max_backoff = Some(max_backoff);
+        self_
+    }
+
+    pub fn heartbeat_interval(mut self_: PyRefMut<Self>, interval: f64) -> PyRefMut<Self> {
+        self_.options.heartbeat_interval = Some(interval);
+        self_
+    }
+
+    pub fn cache_ttl(mut self_: PyRefMut<Self>, ttl: f64) -> PyRefMut<Self> {
+        self_.options.cache_ttl = Some(ttl);
+        self_
+    }
+
+    pub fn refresh_interval(mut self_: PyRefMut<Self>, interval: f64) -> PyRefMut<Self> {
+        self_.options.refresh_interval = Some(interval);
+        self_
+    }
+
+    pub fn idle_timeout(mut self_: PyRefMut<Self>, timeout: f64) -> PyRefMut<Self> {
+        self_.options.idle_timeout = Some(timeout);
+        self_
+    }
+
+    pub fn max_staleness(mut self_: PyRefMut<Self>, max_staleness: f64) -> PyRefMut<Self> {
+        self_.options.max_staleness = Some(max_staleness);
+        self_
+    }
+
+    pub fn cache_file(mut self_: PyRefMut<Self>, path: String) -> PyRefMut<Self> {
+        self_.options.cache_file = Some(path);
+        self_
+    }
+
+    pub fn user_agent(mut self_: PyRefMut<Self>, user_agent: String) -> PyRefMut<Self> {
+        self_.options.user_agent = Some(user_agent);
+        self_
+    }
+
+    pub fn build(&self) -> PyResult<PyWatchtowerClient> {
+        Ok(PyWatchtowerClient {
+            client: self.options.build(self.watchtower_urls.clone(), &self.username, &self.password)?
+        })
+    }
+
+    pub fn build_async(&self) -> PyResult<PyAsyncWatchtowerClient> {
+        Ok(PyAsyncWatchtowerClient {
+            client: self.options.build(self.watchtower_urls.clone(), &self.username, &self.password)?
+        })
+    }
+}
+
+#[pyclass]
+pub struct PyWatchtowerClient {
+    client: blocking::WatchtowerClient,
+}
+
+#[pymethods]
+impl PyWatchtowerClient {
+    /// Durations are given in seconds, and unset options keep the defaults of `WatchtowerClientBuilder`
+    #[new]
+    #[args(
+        "*",
+        connect_timeout = "None",
+        request_timeout = "None",
+        max_attempts = "None",
+        initial_backoff = "None",
+        max_backoff = "None",
+        heartbeat_interval = "None",
+        cache_ttl = "None",
+        refresh_interval = "None",
+        idle_timeout = "None",
+        max_staleness = "None",
+        cache_file = "None",
+        user_agent = "None"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        watchtower_urls: Vec<String>,
+        username: &str,
+        password: &str,
+        connect_timeout: Option<f64>,
+        request_timeout: Option<f64>,
+        max_attempts: Option<u16>,
+        initial_backoff: Option<f64>,
+        max_backoff: Option<f64>,
+        heartbeat_interval: Option<f64>,
+        cache_ttl: Option<f64>,
+        refresh_interval: Option<f64>,
+        idle_timeout: Option<f64>,
+        max_staleness: Option<f64>,
+        cache_file: Option<String>,
+        user_agent: Option<String>
+    ) -> PyResult<Self> {
+        let options = ClientOptions {
+            connect_timeout, request_timeout, max_attempts, initial_backoff, max_backoff, heartbeat_interval, cache_ttl,
+            refresh_interval, idle_timeout, max_staleness, cache_file, user_agent
+        };
+        Ok(PyWatchtowerClient {
+            client: options.build(watchtower_urls, username, password)?
+        })
+    }
+
+    /// Registers an instance of a service, whose lease is renewed in the background until `cancel` is called
+    ///
+    /// Returns the instance id.
+    #[args("*", weight = "None", metadata = "None")]
+    pub fn register(
+        &self,
+        py: Python,
+        service_id: &str,
+        ip_addr: &str,
+        port: u16,
+        weight: Option<u32>,
+        metadata: Option<HashMap<String, String>>
+    ) -> PyResult<String> {
+        let options = create_registration_options(weight, metadata);
+        let registration = py.allow_threads(|| self.client.register_with_options(service_id, ip_addr, port, options))?;
+        let instance_id = registration.instance_id().to_string();
+        registration.detach();
+        Ok(instance_id)
+    }
+
+    /// Returns the instances registered through this client
+    pub fn get_registrations(&self) -> Vec<PyInstanceInfo> {
+        get_registrations(self.client.client())
+    }
+
+    // Methods without arguments take `PyRef`, as pyo3 only uses the no-argument calling convention without `Python`
+    pub fn ping(self_: PyRef<Self>) -> PyResult<()> {
+        let client = &self_.client;
+        Ok(self_.py().allow_threads(|| client.ping())?)
+    }
+
+    pub fn cancel(self_: PyRef<Self>) -> PyResult<()> {
+        let client = &self_.client;
+        Ok(self_.py().allow_threads(|| client.cancel())?)
+    }
+
+    pub fn get_service_url(&self, py: Python, service_id: &str) -> PyResult<String> {
+        Ok(py.allow_threads(|| self.client.get_service_url(service_id))?)
+    }
+
+    pub fn get_instances(&self, py: Python, service_id: &str) -> PyResult<Vec<PyInstanceInfo>> {
+        let instance_infos = py.allow_threads(|| self.client.get_instances(service_id))?;
+        Ok(instance_infos.into_iter().map(PyInstanceInfo::from).collect())
+    }
+
+    pub fn choose_instance(&self, py: Python, service_id: &str) -> PyResult<PyInstanceInfo> {
+        Ok(py.allow_threads(|| self.client.choose_instance(service_id))?.into())
+    }
+
+    #[args(scheme = "\"http\"", path = "\"\"")]
+    pub fn build_service_url(&self, py: Python, service_id: &str, scheme: &str, path: &str) -> PyResult<String> {
+        Ok(py.allow_threads(|| self.client.build_service_url(service_id, scheme, path))?)
+    }
+}
+
+/// Returns the instances registered through `client`
+fn get_registrations(client: &WatchtowerClient) -> Vec<PyInstanceInfo> {
+    client.get_registrations().into_iter()
+        .map(|registered_instance| registered_instance.instance_info.into())
+        .collect()
+}
+
+/// A client whose methods return awaitables, for asyncio programs
+///
+/// Requests run on the runtime of the client, so they do not block the event loop.
+#[pyclass]
+pub struct PyAsyncWatchtowerClient {
+    client: blocking::WatchtowerClient,
+}
+
+/// An instance to register, as returned by `PyAsyncWatchtowerClient.register`
+///
+/// Awaiting it registers the instance until `cancel` is called on the client. Used with `async with`, the instance is
+/// registered on entry and its lease cancelled on exit.
+#[pyclass]
+pub struct PyAsyncRegistration {
+    client: Arc<WatchtowerClient>,
+    service_id: String,
+    ip_addr: String,
+    port: u16,
+    options: RegistrationOptions,
+    registration: Arc<std::sync::Mutex<Option<Registration>>>
+}
+
+/// Runs `future` on the runtime of `client`, returning an asyncio future of its output on the running event loop
+///
+/// Cancelling the asyncio future does not cancel `future`, whose output is then dropped.
+fn into_awaitable<T, F>(py: Python, client: &WatchtowerClient, future: F) -> PyResult<PyObject>
+where
+    F: std::future::Future<Output = Result<T>> + Send + 'static,
+    T: IntoPy<PyObject> + Send + 'static
+{
+    let event_loop: PyObject = py.import("asyncio")?.call_method0("get_running_loop")?.into();
+    let py_future = event_loop.call_method0(py, "create_future")?;
+    let resolve: PyObject = wrap_pyfunction!(resolve_future, py)?.into();
+    let pending_future = py_future.clone_ref(py);
+    client.spawner.spawn(async move {
+        let result = future.await;
+        Python::with_gil(|py| {
+            let (value, is_error) = match result {
+                Ok(value) => (value.into_py(py), false),
+                Err(err) => (PyErr::from(err).into_py(py), true)
+            };
+            let args = (resolve, pending_future, value, is_error);
+            if let Err(err) = event_loop.call_method1(py, "call_soon_threadsafe", args) {
+                warn!("Unable to hand a result back to the event loop: {}", err);
+            }
+        });
+    }.boxed())?;
+    Ok(py_future)
+}
+
+/// Sets the result of an asyncio future, unless it was cancelled in the meantime
+#[pyfunction]
+fn resolve_future(future: &PyAny, value: PyObject, is_error: bool) -> PyResult<()> {
+    if future.call_method0("done")?.is_true()? {
+        return Ok(());
+    }
+    future.call_method1(if is_error { "set_exception" } else { "set_result" }, (value,))?;
+    Ok(())
+}
+
+#[pymethods]
+impl PyAsyncWatchtowerClient {
+    /// Durations are given in seconds, and unset options keep the defaults of `WatchtowerClientBuilder`
+    #[new]
+    #[args(
+        "*",
+        connect_timeout = "None",
+        request_timeout = "None",
+        max_attempts = "None",
+        initial_backoff = "None",
+        max_backoff = "None",
+        heartbeat_interval = "None",
+        cache_ttl = "None",
+        refresh_interval = "None",
+        idle_timeout = "None",
+        max_staleness = "None",
+        cache_file = "None",
+        user_agent = "None"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        watchtower_urls: Vec<String>,
+        username: &str,
+        password: &str,
+        connect_timeout: Option<f64>,
+        request_timeout: Option<f64>,
+        max_attempts: Option<u16>,
+        initial_backoff: Option<f64>,
+        max_backoff: Option<f64>,
+        heartbeat_interval: Option<f64>,
+        cache_ttl: Option<f64>,
+        refresh_interval: Option<f64>,
+        idle_timeout: Option<f64>,
+        max_staleness: Option<f64>,
+        cache_file: Option<String>,
+        user_agent: Option<String>
+    ) -> PyResult<Self> {
+        let options = ClientOptions {
+            connect_timeout, request_timeout, max_attempts, initial_backoff, max_backoff, heartbeat_interval, cache_ttl,
+            refresh_interval, idle_timeout, max_staleness, cache_file, user_agent
+        };
+        Ok(PyAsyncWatchtowerClient {
+            client: options.build(watchtower_urls, username, password)?
+        })
+    }
+
+    /// Returns the instance to register, to be awaited or used with `async with`
+    #[args("*", weight = "None", metadata = "None")]
+    pub fn register(
+        &self,
+        service_id: &str,
+        ip_addr: &str,
+        port: u16,
+        weight: Option<u32>,
+        metadata: Option<HashMap<String, String>>
+    ) -> PyAsyncRegistration {
+        PyAsyncRegistration {
+            client: self.client.client().clone(),
+            service_id: service_id.to_string(),
+            ip_addr: ip_addr.to_string(),
+            port,
+            options: create_registration_options(weight, metadata),
+            registration: Arc::new(std::sync::Mutex::new(None))
+        }
+    }
+
+    /// Returns the instances registered through this client
+    pub fn get_registrations(&self) -> Vec<PyInstanceInfo> {
+        get_registrations(self.client.client())
+    }
+
+    pub fn ping(self_: PyRef<Self>) -> PyResult<PyObject> {
+        let client = self_.client.client().clone();
+        into_awaitable(self_.py(), self_.client.client(), async move { client.ping().await })
+    }
+
+    pub fn cancel(self_: PyRef<Self>) -> PyResult<PyObject> {
+        let client = self_.client.client().clone();
+        into_awaitable(self_.py(), self_.client.client(), async move { client.cancel().await })
+    }
+
+    pub fn get_service_url(&self, py: Python, service_id: &str) -> PyResult<PyObject> {
+        let client = self.client.client().clone();
+        let service_id = service_id.to_string();
+        into_awaitable(py, self.client.client(), async move { client.get_service_url(&service_id).await })
+    }
+
+    pub fn get_instances(&self, py: Python, service_id: &str) -> PyResult<PyObject> {
+        let client = self.client.client().clone();
+        let service_id = service_id.to_string();
+        into_awaitable(py, self.client.client(), async move {
+            let instance_infos = client.get_instances(&service_id).await?;
+            Ok(instance_infos.into_iter().map(PyInstanceInfo::from).collect::<Vec<_>>())
+        })
+    }
+
+    pub fn choose_instance(&self, py: Python, service_id: &str) -> PyResult<PyObject> {
+        let client = self.client.client().clone();
+        let service_id = service_id.to_string();
+        into_awaitable(py, self.client.client(), async move {
+            Ok(PyInstanceInfo::from(client.choose_instance(&service_id).await?))
+        })
+    }
+
+    #[args(scheme = "\"http\"", path = "\"\"")]
+    pub fn build_service_url(&self, py: Python, service_id: &str, scheme: &str, path: &str) -> PyResult<PyObject> {
+        let client = self.client.client().clone();
+        let (service_id, scheme, path) = (service_id.to_string(), scheme.to_string(), path.to_string());
+        into_awaitable(py, self.client.client(), async move { client.build_service_url(&service_id, &scheme, &path).await })
+    }
+}
+
+impl PyAsyncRegistration {
+    /// Registers the instance, keeping its handle if `is_scoped`, or detaching it otherwise
+    fn start(&self, py: Python, is_scoped: bool) -> PyResult<PyObject> {
+        let client = self.client.clone();
+        let (service_id, ip_addr, port) = (self.service_id.clone(), self.ip_addr.clone(), self.port);
+        let options = self.options.clone();
+        let registration = self.registration.clone();
+        into_awaitable(py, &self.client, async move {
+            let new_registration = client.register_with_options(&service_id, &ip_addr, port, options).await?;
+            let instance_id = new_registration.instance_id().to_string();
+            if is_scoped {
+                *registration.lock().unwrap() = Some(new_registration);
+            } else {
+                new_registration.detach();
+            }
+            Ok(instance_id)
+        })
+    }
+}
+
+#[pymethods]
+impl PyAsyncRegistration {
+    /// Registers the instance until `cancel` is called on the client, returning its instance id
+    fn __await__(self_: PyRef<Self>) -> PyResult<PyObject> {
+        let py = self_.py();
+        self_.start(py, false)?.call_method0(py, "__await__")
+    }
+
+    /// Registers the instance, returning its instance id
+    fn __aenter__(self_: PyRef<Self>) -> PyResult<PyObject> {
+        self_.start(self_.py(), true)
+    }
+
+    /// Cancels the lease of the instance
+    fn __aexit__(&self, py: Python, _exc_type: &PyAny, _exc_value: &PyAny, _traceback: &PyAny) -> PyResult<PyObject> {
+        let registration = self.registration.lock().unwrap().take();
+        into_awaitable(py, &self.client, async move {
+            match registration {
+                Some(registration) => registration.cancel().await,
+                None => Ok(())
+            }
+        })
+    }
+}
+
+#[pymodule]
+fn watchtower_client(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyWatchtowerClient>()?;
+    m.add_class::<PyWatchtowerClientBuilder>()?;
+    m.add_class::<PyInstanceInfo>()?;
+    m.add_class::<PyAsyncWatchtowerClient>()?;
+    m.add_class::<PyAsyncRegistration>()?;
+
+    m.add("WatchtowerError", py.get_type::<WatchtowerError>())?;
+    m.add("InternalError", py.get_type::<InternalError>())?;
+    m.add("NotFoundError", py.get_type::<NotFoundError>())?;
+    m.add("UnauthorizedError", py.get_type::<UnauthorizedError>())?;
+    m.add("InstanceAlreadyRegisteredError", py.get_type::<InstanceAlreadyRegisteredError>())?;
+    m.add("MaxRetryReachedError", py.get_type::<MaxRetryReachedError>())?;
+    m.add("InvalidPingError", py.get_type::<InvalidPingError>())?;
+    m.add("NoAvailableInstanceError", py.get_type::<NoAvailableInstanceError>())?;
+    m.add("RequestTimeoutError", py.get_type::<RequestTimeoutError>())?;
+    m.add("NoRuntimeError", py.get_type::<NoRuntimeError>())?;
+    m.add("InvalidConfigError", py.get_type::<InvalidConfigError>())?;
+    m.add("InvalidUrlError", py.get_type::<InvalidUrlError>())?;
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use log::warn;
+use serde::{Serialize, Deserialize};
+use crate::types::{InstanceInfo, Result};
+
+/// The version of the cache file format, increased on every incompatible change
+///
+/// Files of any other version are ignored, and overwritten on the next fetch.
+pub const CACHE_FILE_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+struct CacheFileHeader {
+    version: u32
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CacheFile {
+    version: u32,
+    services: HashMap<String, CachedService>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedService {
+    instance_infos: Vec<InstanceInfo>,
+    /// When the instances were fetched, in milliseconds since the Unix epoch
+    fetched_at_millis: u64
+}
+
+/// The instances of a service read from the cache file
+pub struct SeededService {
+    pub instance_infos: Vec<InstanceInfo>,
+    /// How long ago the instances were fetched, by the wall clock
+    pub age: Duration
+}
+
+/// A local file holding the last fetched instances of every service, to resolve services on startup while the
+/// registry is unreachable
+pub struct DiskCache {
+    path: PathBuf,
+    services: Mutex<HashMap<String, CachedService>>,
+    /// Held while writing the cache file, so that writes never interleave
+    write_lock: Mutex<()>
+}
+
+impl DiskCache {
+    /// Opens the cache file at `path`, starting empty if it does not exist or cannot be read
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let services = match read_cache_file(&path) {
+            Ok(cache_file) => cache_file.services,
+            Err(_) => {
+                warn!("Ignoring the unreadable cache file {}", path.display());
+                HashMap::new()
+            }
+        };
+        DiskCache {
+            path,
+            services: Mutex::new(services),
+            write_lock: Mutex::new(())
+        }
+    }
+
+    /// Returns the instances of every cached service, with their age
+    pub fn services(&self) -> HashMap<String, SeededService> {
+        let now_millis = now_millis();
+        self.services.lock().unwrap().iter()
+            .map(|(service_id, cached_service)| (service_id.clone(), SeededService {
+                instance_infos: cached_service.instance_infos.clone(),
+                age: Duration::from_millis(now_millis.saturating_sub(cached_service.fetched_at_millis))
+            }))
+            .collect()
+    }
+
+    /// Stores the freshly fetched instances of a service, then writes the cache file
+    pub async fn store(self: &Arc<Self>, service_id: &str, instance_infos: &[InstanceInfo]) -> Result<()> {
+        let cached_service = CachedService {
+            instance_infos: instance_infos.to_vec(),
+            fetched_at_millis: now_millis()
+        };
+        self.services.lock().unwrap().insert(service_id.to_string(), cached_service);
+        self.write().await
+    }
+
+    /// Removes a service that no longer exists, then writes the cache file
+    pub async fn remove(self: &Arc<Self>, service_id: &str) -> Result<()> {
+        if self.services.lock().unwrap().remove(service_id).is_none() {
+            return Ok(());
+        }
+        self.write().await
+    }
+
+    /// Writes the cache file on a blocking thread
+    ///
+    /// Every write holds the latest services, so writes finishing out of order never leave an older state behind.
+    async fn write(self: &Arc<Self>) -> Result<()> {
+        let disk_cache = self.clone();
+        tokio::task::spawn_blocking(move || disk_cache.write_now()).await?
+    }
+
+    /// Writes the cache file atomically, by renaming a temporary file over it, so that a crash never leaves it torn
+    ///
+    /// The temporary file is named after the process, so that processes sharing a cache file never write to the same
+    /// temporary file.
+    fn write_now(&self) -> Result<()> {
+        let _write_guard = self.write_lock.lock().unwrap();
+        let cache_file = CacheFile {
+            version: CACHE_FILE_VERSION,
+            services: self.services.lock().unwrap().clone()
+        };
+        let mut temp_file_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_file_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = self.path.with_file_name(temp_file_name);
+        fs::write(&temp_path, serde_json::to_vec(&cache_file)?)?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Returns the wall-clock time in milliseconds since the Unix epoch, or 0 if the system time is before it
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_millis() as u64).unwrap_or(0)
+}
+
+fn read_cache_file(path: &Path) -> Result<CacheFile> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(CacheFile::default()),
+        Err(err) => return Err(err.into())
+    };
+    let header: CacheFileHeader = serde_json::from_slice(&contents)?;
+    if header.version != CACHE_FILE_VERSION {
+        warn!("Ignoring the cache file {} of version {}, expected {}", path.display(), header.version, CACHE_FILE_VERSION);
+        return Ok(CacheFile::default());
+    }
+    Ok(serde_json::from_slice(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_instance;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("watchtower_{}_{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[actix_rt::test]
+    async fn test_store_and_open() {
+        let path = temp_path("test_store_and_open");
+        let disk_cache = Arc::new(DiskCache::open(&path));
+        assert!(disk_cache.services().is_empty());
+        disk_cache.store("a", &[create_instance("1")]).await.unwrap();
+        disk_cache.store("b", &[create_instance("2")]).await.unwrap();
+        disk_cache.remove("b").await.unwrap();
+
+        let services = DiskCache::open(&path).services();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services["a"].instance_infos, vec![create_instance("1")]);
+        assert!(services["a"].age < Duration::from_secs(60));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_age_of_old_file() {
+        let path = temp_path("test_age_of_old_file");
+        let fetched_at_millis = now_millis() - 7 * 24 * 3600 * 1000;
+        let cache_file = format!(r#"{{"version": 1, "services": {{"a": {{"instance_infos": [], "fetched_at_millis": {}}}}}}}"#, fetched_at_millis);
+        fs::write(&path, cache_file).unwrap();
+        let services = DiskCache::open(&path).services();
+        assert!(services["a"].age >= Duration::from_secs(7 * 24 * 3600));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ignore_other_versions() {
+        let path = temp_path("test_ignore_other_versions");
+        fs::write(&path, r#"{"version": 0, "services": {"a": []}}"#).unwrap();
+        assert!(DiskCache::open(&path).services().is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(DiskCache::open(&path).services().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,8 +1,11 @@
-use tokio::sync::Mutex;
+use std::collections::HashMap;
 use log::error;
+use reqwest::{RequestBuilder, Response, StatusCode};
 use crate::{
-    types::{InstanceInfo, Result, Error},
-    load_balancer::{LoadBalancer, RoundRobinLoadBalancer}
+    resources::{LeaseInfo, RegistryHealth},
+    types::{InstanceInfo, InstanceUpdate, Result, Error},
+    load_balancer::RoundRobinLoadBalancer,
+    utils::retry::RetryPolicy
 };
 
 pub struct HttpClient {
@@ -10,159 +13,226 @@ pub struct HttpClient {
     urls: Vec<String>,
     username: String,
     password: String,
-    load_balancer: Mutex<RoundRobinLoadBalancer>
+    retry_policy: RetryPolicy,
+    load_balancer: RoundRobinLoadBalancer
 }
 
-const MAX_ATTEMPT: u16 = 3;
-
 impl HttpClient {
     pub fn new(urls: Vec<String>, username: String, password: String) -> Self {
+        HttpClient::with_client(reqwest::Client::new(), urls, username, password, RetryPolicy::default())
+    }
+
+    /// Creates an `HttpClient` that sends requests through `client` and retries them according to `retry_policy`
+    pub fn with_client(client: reqwest::Client, urls: Vec<String>, username: String, password: String, retry_policy: RetryPolicy) -> Self {
         HttpClient {
-            client: reqwest::Client::new(),
-            load_balancer: Mutex::new(RoundRobinLoadBalancer::new(urls.len())),
+            client,
+            load_balancer: RoundRobinLoadBalancer::new(),
             urls,
             username,
-            password
+            password,
+            retry_policy
         }
     }
 
-    async fn get_new_url(&self) -> &str {
-        let current_index = self.load_balancer.lock().await.get_next_index();
-        &self.urls[current_index]
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
     }
 
-    pub async fn register(&self, service_id: &str, instance_info: &InstanceInfo) -> Result<()> {
-        let mut base_url = self.get_new_url().await;
-        let mut attempt = 0;
+    fn get_new_url(&self) -> &str {
+        &self.urls[self.load_balancer.get_next_index(self.urls.len())]
+    }
 
-        let instance_info = serde_json::to_string(&instance_info).expect("Fails to serialize instance_info");
-        while attempt < MAX_ATTEMPT {
-            let url = format!("{}/api/v1/services/{}", base_url, service_id);
-            match self.client.post(&url).body(instance_info.clone())
-                .basic_auth(&self.username, Some(&self.password))
-                .header("content-type", "application/json")
-                .send().await {
-                Ok(res) => {
-                    if res.status() == reqwest::StatusCode::NO_CONTENT {
-                        return Ok(())
-                    } else if res.status() == reqwest::StatusCode::UNAUTHORIZED {
-                        return Err(Error::Unauthorized);
-                    } else {
-                        error!("Unexpected status code {}", res.status());
-                    }
+    /// Sends the request built by `build` from the base url of a registry node
+    ///
+    /// Connection errors and server errors are retried on the next node with a backoff, up to the maximum number of
+    /// attempts of the retry policy. Any other response is returned to the caller, except for 401 which is mapped to
+    /// `Error::Unauthorized`.
+    async fn send<F>(&self, operation: &str, build: F) -> Result<Response>
+    where
+        F: Fn(&str) -> RequestBuilder
+    {
+        let mut attempt = 0;
+        loop {
+            let base_url = self.get_new_url();
+            match build(base_url).basic_auth(&self.username, Some(&self.password)).send().await {
+                Ok(res) if res.status() == StatusCode::UNAUTHORIZED => return Err(Error::Unauthorized),
+                Ok(res) if res.status().is_server_error() => {
+                    error!("{} request to {} failed with status code {}", operation, base_url, res.status());
                 }
+                Ok(res) => return Ok(res),
                 Err(err) => {
-                    error!("Register request error: {}", err);
-                    base_url = self.get_new_url().await;
-                    attempt += 1;
+                    error!("{} request error: {}", operation, err);
                 }
             }
+            attempt += 1;
+            if attempt >= self.retry_policy.max_attempts {
+                return Err(Error::MaxRetryReached);
+            }
+            tokio::time::delay_for(self.retry_policy.backoff(attempt as u32)).await;
+        }
+    }
+
+    pub async fn register(&self, service_id: &str, instance_info: &InstanceInfo) -> Result<()> {
+        let res = self.send("Register", |base_url| {
+            self.client.post(&format!("{}/api/v1/services/{}", base_url, service_id)).json(instance_info)
+        }).await?;
+        match res.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            status => Err(unexpected_status(status))
         }
-        Err(Error::MaxRetryReached)
     }
 
+    /// Renews the lease of an instance, returning `Error::NotFound` if the registry does not hold it
     pub async fn renew(&self, service_id: &str, instance_info: &InstanceInfo) -> Result<()> {
-        let mut base_url = self.get_new_url().await;
-        let mut attempt = 0;
+        let res = self.send("Renew", |base_url| {
+            self.client.put(&format!("{}/api/v1/services/{}/{}", base_url, service_id, instance_info.instance_id))
+        }).await?;
+        match res.status() {
+            StatusCode::OK => Ok(()),
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status => Err(unexpected_status(status))
+        }
+    }
 
-        while attempt < MAX_ATTEMPT {
-            let url = format!("{}/api/v1/services/{}/{}", base_url, service_id, instance_info.instance_id);
-            match self.client.put(&url)
-                .basic_auth(&self.username, Some(&self.password))
-                .send().await {
-                Ok(res) => {
-                    if res.status() == reqwest::StatusCode::OK {
-                        return Ok(());
-                    } else if res.status() == reqwest::StatusCode::NOT_FOUND {
-                        // If the instance does not exist, register the instance instead
-                        return self.register(service_id, &instance_info).await;
-                    } else if res.status() == reqwest::StatusCode::UNAUTHORIZED {
-                        return Err(Error::Unauthorized);
-                    } else {
-                        error!("Unexpected status code: {}", res.status());
-                    }
-                }
-                Err(err) => {
-                    error!("Renew request error: {}", err);
-                    base_url = self.get_new_url().await;
-                    attempt += 1;
-                }
-            }
+    pub async fn update_instance(&self, service_id: &str, instance_id: &str, update: &InstanceUpdate) -> Result<()> {
+        let res = self.send("Update", |base_url| {
+            self.client.patch(&format!("{}/api/v1/services/{}/{}", base_url, service_id, instance_id)).json(update)
+        }).await?;
+        match res.status() {
+            StatusCode::OK => Ok(()),
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status => Err(unexpected_status(status))
         }
-        Err(Error::MaxRetryReached)
     }
 
+    /// Cancels the lease of an instance, which succeeds if the registry no longer holds it
     pub async fn cancel(&self, service_id: &str, instance_info: &InstanceInfo) -> Result<()> {
-        let mut base_url = self.get_new_url().await;
-        let mut attempt = 0;
-
-        while attempt < MAX_ATTEMPT {
-            let url = format!("{}/api/v1/services/{}/{}", base_url, service_id, instance_info.instance_id);
-            match self.client.delete(&url)
-                .basic_auth(&self.username, Some(&self.password))
-                .send().await {
-                Ok(res) => {
-                    if res.status() == reqwest::StatusCode::OK {
-                        return Ok(());
-                    } else {
-                        error!("Unexpected status code: {}", res.status());
-                    }
-                }
-                Err(err) => {
-                    error!("Renew request error: {}", err);
-                    base_url = self.get_new_url().await;
-                    attempt += 1;
-                }
-            }
+        let res = self.send("Cancel", |base_url| {
+            self.client.delete(&format!("{}/api/v1/services/{}/{}", base_url, service_id, instance_info.instance_id))
+        }).await?;
+        match res.status() {
+            StatusCode::OK | StatusCode::NOT_FOUND => Ok(()),
+            status => Err(unexpected_status(status))
         }
-        Err(Error::MaxRetryReached)    
     }
 
     pub async fn get_all_instances(&self, service_id: &str) -> Result<Vec<InstanceInfo>> {
-        let mut base_url = self.get_new_url().await;
-        let mut attempt = 0;
+        let res = self.send("Get all instances", |base_url| {
+            self.client.get(&format!("{}/api/v1/services/{}", base_url, service_id))
+        }).await?;
+        match res.status() {
+            StatusCode::OK => Ok(res.json().await?),
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status => Err(unexpected_status(status))
+        }
+    }
 
-        while attempt < MAX_ATTEMPT {
-            let url = format!("{}/api/v1/services/{}", base_url, service_id);
-            match self.client.get(&url)
-                .basic_auth(&self.username, Some(&self.password))
-                .send().await {
-                Ok(res) => {
-                    if res.status() == reqwest::StatusCode::OK {
-                        return Ok(res.json().await?);
-                    } else if res.status() == reqwest::StatusCode::NOT_FOUND {
-                        return Err(Error::NotFound);
-                    } else if res.status() == reqwest::StatusCode::UNAUTHORIZED {
-                        return Err(Error::Unauthorized);
-                    } else {
-                        error!("Unexpected status code: {}", res.status());
-                    }
-                }
-                Err(err) => {
-                    error!("Get all instances request error: {}", err);
-                    base_url = self.get_new_url().await;
-                    attempt += 1;
-                }
-            }
+    /// Returns the instances of every service on the registry, keyed by service id
+    pub async fn get_all_services(&self) -> Result<HashMap<String, Vec<InstanceInfo>>> {
+        let res = self.send("Get all services", |base_url| {
+            self.client.get(&format!("{}/api/v1/services", base_url))
+        }).await?;
+        match res.status() {
+            StatusCode::OK => Ok(res.json().await?),
+            status => Err(unexpected_status(status))
+        }
+    }
+
+    /// Returns the leases of the instances of a service, with their age
+    pub async fn get_all_leases(&self, service_id: &str) -> Result<Vec<LeaseInfo>> {
+        let res = self.send("Get all leases", |base_url| {
+            self.client.get(&format!("{}/api/v1/leases/{}", base_url, service_id))
+        }).await?;
+        match res.status() {
+            StatusCode::OK => Ok(res.json().await?),
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status => Err(unexpected_status(status))
+        }
+    }
+
+    /// Returns the health of the next registry node
+    pub async fn get_health(&self) -> Result<RegistryHealth> {
+        let res = self.send("Get health", |base_url| {
+            self.client.get(&format!("{}/api/v1/healthcheck", base_url))
+        }).await?;
+        match res.status() {
+            StatusCode::OK => Ok(res.json().await?),
+            status => Err(unexpected_status(status))
         }
-        Err(Error::MaxRetryReached)
     }
 }
 
+fn unexpected_status(status: StatusCode) -> Error {
+    error!("Unexpected status code: {}", status);
+    Error::InternalError
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn create_http_client() -> HttpClient {
+        let retry_policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: std::time::Duration::from_millis(1),
+            max_backoff: std::time::Duration::from_millis(1)
+        };
+        HttpClient::with_client(
+            reqwest::Client::new(),
+            vec![mockito::SERVER_URL.to_string()],
+            "admin".to_string(),
+            "password".to_string(),
+            retry_policy
+        )
+    }
+
+    #[actix_rt::test]
+    async fn test_server_errors_are_retried() {
+        let mock = mockito::mock("GET", "/api/v1/services/test_server_errors").with_status(503).expect(3).create();
+        let http_client = create_http_client();
+        assert_eq!(http_client.get_all_instances("test_server_errors").await, Err(Error::MaxRetryReached));
+        mock.assert();
+    }
+
+    #[actix_rt::test]
+    async fn test_unexpected_status_is_not_retried() {
+        let mock = mockito::mock("PATCH", "/api/v1/services/test_unexpected_status/instance").with_status(400).expect(1).create();
+        let http_client = create_http_client();
+        let update = InstanceUpdate::default();
+        assert_eq!(http_client.update_instance("test_unexpected_status", "instance", &update).await, Err(Error::InternalError));
+        mock.assert();
+    }
+
     #[actix_rt::test]
-    async fn test_get_new_url() {
+    async fn test_get_all_leases() {
+        let _leases = mockito::mock("GET", "/api/v1/leases/test_get_all_leases")
+            .with_status(200)
+            .with_body(r#"[{
+                "service_id": "test_get_all_leases",
+                "instance_info": {"instance_id": "a", "ip_addr": "127.0.0.1", "port": 8080, "status": "UP"},
+                "lease_age_ms": 1500,
+                "last_renewed_timestamp_ms": 1700000000000
+            }]"#)
+            .create();
+        let _not_found = mockito::mock("GET", "/api/v1/leases/test_get_all_leases_not_found").with_status(404).create();
+        let http_client = create_http_client();
+        let leases = http_client.get_all_leases("test_get_all_leases").await.unwrap();
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].instance_info.instance_id, "a");
+        assert_eq!(leases[0].lease_age_ms, 1500);
+        assert_eq!(http_client.get_all_leases("test_get_all_leases_not_found").await.err(), Some(Error::NotFound));
+    }
+
+    #[test]
+    fn test_get_new_url() {
         let http_client = HttpClient::new(vec!["a".to_string(), "b".to_string()], "admin".to_string(), "password".to_string());
-        let url = http_client.get_new_url().await;
+        let url = http_client.get_new_url();
         match url {
             "a" => {
-                assert_eq!(http_client.get_new_url().await, "b");
+                assert_eq!(http_client.get_new_url(), "b");
             }
             "b" => {
-                assert_eq!(http_client.get_new_url().await, "a"); 
+                assert_eq!(http_client.get_new_url(), "a"); 
             }
             _ => {
                 panic!("Unexpected url value");
@@ -1,11 +1,62 @@
-use std::cmp::{Ord, PartialOrd, PartialEq, Ordering};
+use std::{
+    cmp::{Ord, PartialOrd, PartialEq, Ordering},
+    collections::HashMap
+};
 use serde::{Serialize, Deserialize};
 
+/// The status of an instance.
+///
+/// Only `Up` instances are handed out by the client.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InstanceStatus {
+    #[default]
+    Up,
+    Down,
+    Starting,
+    OutOfService
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Eq)]
 pub struct InstanceInfo {
     pub instance_id: String,
     pub ip_addr: String,
-    pub port: u16
+    pub port: u16,
+    #[serde(default)]
+    pub status: InstanceStatus,
+    /// The share of traffic the instance receives relative to the other instances of its service. 0 drains it.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Free-form attributes of the instance, such as the port of a secondary protocol
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>
+}
+
+pub const DEFAULT_WEIGHT: u32 = 1;
+
+fn default_weight() -> u32 {
+    DEFAULT_WEIGHT
+}
+
+impl InstanceInfo {
+    /// Returns the address of the instance, such as `127.0.0.1:8080`
+    ///
+    /// IPv6 addresses are enclosed in brackets.
+    pub fn address(&self) -> String {
+        if self.ip_addr.contains(':') && !self.ip_addr.starts_with('[') {
+            format!("[{}]:{}", self.ip_addr, self.port)
+        } else {
+            format!("{}:{}", self.ip_addr, self.port)
+        }
+    }
+
+    /// Builds the url of `path` on the instance with `scheme`, such as `http://127.0.0.1:8080/health`
+    ///
+    /// IPv6 addresses are enclosed in brackets, and a leading slash is added to `path` if it is missing.
+    pub fn url(&self, scheme: &str, path: &str) -> String {
+        let separator = if path.is_empty() || path.starts_with('/') { "" } else { "/" };
+        format!("{}://{}{}{}", scheme, self.address(), separator, path)
+    }
 }
 
 impl Ord for InstanceInfo {
@@ -25,3 +76,71 @@ impl PartialEq for InstanceInfo {
         self.instance_id == other.instance_id
     }
 }
+
+/// A partial update of a registered instance. Fields left as `None` are unchanged.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct InstanceUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<InstanceStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>
+}
+
+impl InstanceUpdate {
+    /// Applies the set fields to `instance_info`
+    pub fn apply(&self, instance_info: &mut InstanceInfo) {
+        if let Some(status) = self.status {
+            instance_info.status = status;
+        }
+        if let Some(weight) = self.weight {
+            instance_info.weight = weight;
+        }
+    }
+}
+
+/// The lease of a registered instance, as reported to operators by the registry
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct LeaseInfo {
+    pub service_id: String,
+    pub instance_info: InstanceInfo,
+    /// Milliseconds elapsed since the lease was last renewed
+    pub lease_age_ms: u64,
+    /// Wall-clock time of the last renewal in milliseconds since the Unix epoch
+    pub last_renewed_timestamp_ms: u64
+}
+
+/// The health of a registry node, as reported by `/api/v1/healthcheck`
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RegistryHealth {
+    pub is_ready: bool,
+    pub is_synced: bool,
+    pub is_eviction_stalled: bool,
+    pub seconds_since_last_eviction: u64,
+    pub services: i64,
+    pub instances: i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::create_instance;
+
+    fn create_instance_info(ip_addr: &str) -> InstanceInfo {
+        InstanceInfo {
+            ip_addr: ip_addr.to_string(),
+            ..create_instance("instance")
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        let instance_info = create_instance_info("127.0.0.1");
+        assert_eq!(instance_info.url("http", "/health"), "http://127.0.0.1:8080/health");
+        assert_eq!(instance_info.url("https", "health"), "https://127.0.0.1:8080/health");
+        assert_eq!(instance_info.url("http", ""), "http://127.0.0.1:8080");
+        assert_eq!(create_instance_info("::1").url("http", "/"), "http://[::1]:8080/");
+        assert_eq!(instance_info.address(), "127.0.0.1:8080");
+        assert_eq!(create_instance_info("::1").address(), "[::1]:8080");
+    }
+}
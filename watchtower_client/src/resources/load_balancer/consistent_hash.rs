@@ -0,0 +1,116 @@
+use std::sync::Mutex;
+use rand::seq::SliceRandom;
+use crate::resources::{
+    InstanceInfo,
+    load_balancer::{LoadBalancer, RequestContext}
+};
+
+const VIRTUAL_NODES_PER_INSTANCE: usize = 64;
+
+/// A hash ring built for a list of instances.
+struct Ring {
+    instance_ids: Vec<String>,
+    /// The points of the ring with the index of the instance they belong to, sorted by point
+    points: Vec<(u64, usize)>
+}
+
+impl Ring {
+    fn new(instances: &[InstanceInfo]) -> Self {
+        let mut points: Vec<(u64, usize)> = instances.iter().enumerate()
+            .flat_map(|(index, instance_info)| (0..VIRTUAL_NODES_PER_INSTANCE).map(move |virtual_node| {
+                (hash(format!("{}#{}", instance_info.instance_id, virtual_node).as_bytes()), index)
+            }))
+            .collect();
+        points.sort_unstable();
+        Ring {
+            instance_ids: instances.iter().map(|instance_info| instance_info.instance_id.to_string()).collect(),
+            points
+        }
+    }
+
+    fn is_built_for(&self, instances: &[InstanceInfo]) -> bool {
+        self.instance_ids.len() == instances.len()
+            && self.instance_ids.iter().zip(instances).all(|(instance_id, instance_info)| *instance_id == instance_info.instance_id)
+    }
+
+    fn get_index(&self, key: &str) -> usize {
+        let point = hash(key.as_bytes());
+        let position = self.points.partition_point(|(ring_point, _)| *ring_point < point);
+        self.points[position % self.points.len()].1
+    }
+}
+
+/// Hashes `bytes` with 64-bit FNV-1a, which is stable across processes and platforms.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Selects instances on a hash ring by the hash key of the request, so that requests with the same key go to the same
+/// instance while it is available.
+///
+/// When an instance leaves or joins, only the keys of its share of the ring move. Requests without a hash key are sent
+/// to a random instance.
+#[derive(Default)]
+pub struct ConsistentHashLoadBalancer {
+    ring: Mutex<Option<Ring>>
+}
+
+impl ConsistentHashLoadBalancer {
+    pub fn new() -> Self {
+        ConsistentHashLoadBalancer::default()
+    }
+}
+
+impl LoadBalancer for ConsistentHashLoadBalancer {
+    fn select<'a>(&self, instances: &'a [InstanceInfo], context: &RequestContext) -> Option<&'a InstanceInfo> {
+        if instances.is_empty() {
+            return None;
+        }
+        let hash_key = match &context.hash_key {
+            Some(hash_key) => hash_key,
+            None => return instances.choose(&mut rand::thread_rng())
+        };
+
+        let mut ring = self.ring.lock().unwrap();
+        if !ring.as_ref().is_some_and(|ring| ring.is_built_for(instances)) {
+            *ring = Some(Ring::new(instances));
+        }
+        ring.as_ref().map(|ring| &instances[ring.get_index(hash_key)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_instance;
+
+    fn create_instances(count: usize) -> Vec<InstanceInfo> {
+        (0..count).map(|index| create_instance(&format!("instance-{}", index))).collect()
+    }
+
+    #[test]
+    fn test_select_by_hash_key() {
+        let load_balancer = ConsistentHashLoadBalancer::new();
+        let instances = create_instances(5);
+        let keys: Vec<String> = (0..100).map(|key| format!("user-{}", key)).collect();
+
+        let selected: Vec<String> = keys.iter()
+            .map(|key| load_balancer.select(&instances, &RequestContext::with_hash_key(key)).unwrap().instance_id.to_string())
+            .collect();
+        for (key, instance_id) in keys.iter().zip(&selected) {
+            assert_eq!(&load_balancer.select(&instances, &RequestContext::with_hash_key(key)).unwrap().instance_id, instance_id);
+        }
+
+        // Only the keys of the removed instance move
+        let remaining_instances: Vec<InstanceInfo> = instances.iter()
+            .filter(|instance_info| instance_info.instance_id != "instance-0")
+            .cloned()
+            .collect();
+        for (key, instance_id) in keys.iter().zip(&selected) {
+            let new_instance_id = &load_balancer.select(&remaining_instances, &RequestContext::with_hash_key(key)).unwrap().instance_id;
+            if instance_id != "instance-0" {
+                assert_eq!(new_instance_id, instance_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex
+};
+use rand::Rng;
+use crate::resources::{
+    InstanceInfo,
+    load_balancer::{LoadBalancer, RequestContext}
+};
+
+/// Picks two instances at random and selects the one with fewer outstanding requests.
+///
+/// Outstanding requests are only counted for instances obtained through `WatchtowerClient::select_instance`.
+#[derive(Default)]
+pub struct LeastOutstandingLoadBalancer {
+    outstanding_requests: Mutex<HashMap<String, usize>>
+}
+
+impl LeastOutstandingLoadBalancer {
+    pub fn new() -> Self {
+        LeastOutstandingLoadBalancer::default()
+    }
+
+    /// Returns the number of outstanding requests to an instance
+    pub fn outstanding_requests(&self, instance_id: &str) -> usize {
+        self.outstanding_requests.lock().unwrap().get(instance_id).copied().unwrap_or(0)
+    }
+}
+
+impl LoadBalancer for LeastOutstandingLoadBalancer {
+    fn select<'a>(&self, instances: &'a [InstanceInfo], _context: &RequestContext) -> Option<&'a InstanceInfo> {
+        match instances.len() {
+            0 => None,
+            1 => instances.first(),
+            len => {
+                let mut rng = rand::thread_rng();
+                let first = rng.gen_range(0..len);
+                // Picks the second instance among the others, so that both choices are distinct
+                let second = (first + rng.gen_range(1..len)) % len;
+                let (first, second) = (&instances[first], &instances[second]);
+                if self.outstanding_requests(&second.instance_id) < self.outstanding_requests(&first.instance_id) {
+                    Some(second)
+                } else {
+                    Some(first)
+                }
+            }
+        }
+    }
+
+    fn on_request_start(&self, instance_info: &InstanceInfo) {
+        *self.outstanding_requests.lock().unwrap().entry(instance_info.instance_id.to_string()).or_insert(0) += 1;
+    }
+
+    fn on_request_end(&self, instance_info: &InstanceInfo) {
+        let mut outstanding_requests = self.outstanding_requests.lock().unwrap();
+        if let Some(count) = outstanding_requests.get_mut(&instance_info.instance_id) {
+            *count -= 1;
+            if *count == 0 {
+                outstanding_requests.remove(&instance_info.instance_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_instance;
+
+    #[test]
+    fn test_select_least_outstanding() {
+        let load_balancer = LeastOutstandingLoadBalancer::new();
+        let instances = vec![create_instance("busy"), create_instance("idle")];
+        load_balancer.on_request_start(&instances[0]);
+
+        for _ in 0..10 {
+            let instance_info = load_balancer.select(&instances, &RequestContext::default()).unwrap();
+            assert_eq!(instance_info.instance_id, "idle");
+        }
+
+        load_balancer.on_request_end(&instances[0]);
+        assert_eq!(load_balancer.outstanding_requests("busy"), 0);
+    }
+}
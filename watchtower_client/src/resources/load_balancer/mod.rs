@@ -1,7 +1,130 @@
 mod round_robin;
+mod weighted_round_robin;
+mod random;
+mod weighted_random;
+mod least_outstanding;
+mod consistent_hash;
 
-pub trait LoadBalancer {
-    fn get_next_index(&mut self) -> usize;
+use std::{sync::Arc, time::Duration};
+use crate::resources::{CallOutcome, InstanceInfo, OutlierDetector};
+
+/// What a load balancer may know about the request an instance is selected for.
+#[derive(Clone, Debug, Default)]
+pub struct RequestContext {
+    /// The key that requests are routed by, for strategies that keep requests with the same key on the same instance
+    pub hash_key: Option<String>,
+    /// The instances not to select, such as the ones a retried request already failed on, unless no other is left
+    pub excluded_instance_ids: Vec<String>
+}
+
+impl RequestContext {
+    /// Creates a context for a request routed by `hash_key`
+    pub fn with_hash_key(hash_key: &str) -> Self {
+        RequestContext {
+            hash_key: Some(hash_key.to_string()),
+            ..RequestContext::default()
+        }
+    }
+
+    /// Avoids selecting the instance for the request, unless no other is left
+    pub fn exclude(&mut self, instance_id: &str) {
+        self.excluded_instance_ids.push(instance_id.to_string());
+    }
+
+    pub fn is_excluded(&self, instance_id: &str) -> bool {
+        self.excluded_instance_ids.iter().any(|excluded_instance_id| excluded_instance_id == instance_id)
+    }
+}
+
+/// A strategy that selects the instance of a service to send a request to.
+///
+/// A load balancer is kept per service for the lifetime of the client, so it can keep state across refreshes of the
+/// service's instances.
+pub trait LoadBalancer: Send + Sync {
+    /// Selects an instance from `instances`, or returns `None` if none can be selected
+    fn select<'a>(&self, instances: &'a [InstanceInfo], context: &RequestContext) -> Option<&'a InstanceInfo>;
+
+    /// Called with every instance of the service whenever they are fetched, so that the state kept for the instances
+    /// that are gone can be dropped
+    ///
+    /// `select` may be given only some of these instances, such as the ones that are not ejected, so state should be
+    /// kept for the others.
+    fn on_refresh(&self, _instances: &[InstanceInfo]) {}
+
+    /// Called when a request is sent to an instance selected by this load balancer
+    fn on_request_start(&self, _instance_info: &InstanceInfo) {}
+
+    /// Called when a request sent to an instance selected by this load balancer completes
+    fn on_request_end(&self, _instance_info: &InstanceInfo) {}
+}
+
+/// Creates the load balancer of a service that has none set.
+pub type LoadBalancerFactory = Arc<dyn Fn() -> Arc<dyn LoadBalancer> + Send + Sync>;
+
+/// An instance selected for a request.
+///
+/// The load balancer that selected the instance is notified when the request starts, and when this is dropped.
+pub struct SelectedInstance {
+    service_id: String,
+    instance_info: InstanceInfo,
+    load_balancer: Arc<dyn LoadBalancer>,
+    outlier_detector: Arc<OutlierDetector>,
+    staleness: Option<Duration>
+}
+
+impl SelectedInstance {
+    pub fn new(
+        service_id: &str,
+        instance_info: InstanceInfo,
+        load_balancer: Arc<dyn LoadBalancer>,
+        outlier_detector: Arc<OutlierDetector>
+    ) -> Self {
+        load_balancer.on_request_start(&instance_info);
+        SelectedInstance {
+            service_id: service_id.to_string(),
+            instance_info,
+            load_balancer,
+            outlier_detector,
+            staleness: None
+        }
+    }
+
+    /// Marks the instance as selected from the last known instances of the service, which expired `staleness` ago
+    pub fn with_staleness(mut self, staleness: Option<Duration>) -> Self {
+        self.staleness = staleness;
+        self
+    }
+
+    /// Returns how long ago the instances this was selected from expired, if the registry was unreachable and the
+    /// last known instances were served instead
+    pub fn staleness(&self) -> Option<Duration> {
+        self.staleness
+    }
+
+    /// Reports the outcome of the request, so that the instance is ejected from selection if it keeps failing
+    pub fn report(&self, outcome: CallOutcome) {
+        self.outlier_detector.report(&self.service_id, &self.instance_info.instance_id, outcome);
+    }
+
+    pub fn instance_info(&self) -> &InstanceInfo {
+        &self.instance_info
+    }
+
+    /// Returns the url of the instance, as `ip_addr:port` with IPv6 addresses enclosed in brackets
+    pub fn url(&self) -> String {
+        self.instance_info.address()
+    }
+}
+
+impl Drop for SelectedInstance {
+    fn drop(&mut self) {
+        self.load_balancer.on_request_end(&self.instance_info);
+    }
 }
 
-pub use round_robin::RoundRobinLoadBalancer;
\ No newline at end of file
+pub use round_robin::RoundRobinLoadBalancer;
+pub use weighted_round_robin::WeightedRoundRobinLoadBalancer;
+pub use random::RandomLoadBalancer;
+pub use weighted_random::WeightedRandomLoadBalancer;
+pub use least_outstanding::LeastOutstandingLoadBalancer;
+pub use consistent_hash::ConsistentHashLoadBalancer;
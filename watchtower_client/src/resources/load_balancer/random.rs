@@ -0,0 +1,21 @@
+use rand::seq::SliceRandom;
+use crate::resources::{
+    InstanceInfo,
+    load_balancer::{LoadBalancer, RequestContext}
+};
+
+/// Selects instances uniformly at random.
+#[derive(Default)]
+pub struct RandomLoadBalancer;
+
+impl RandomLoadBalancer {
+    pub fn new() -> Self {
+        RandomLoadBalancer
+    }
+}
+
+impl LoadBalancer for RandomLoadBalancer {
+    fn select<'a>(&self, instances: &'a [InstanceInfo], _context: &RequestContext) -> Option<&'a InstanceInfo> {
+        instances.choose(&mut rand::thread_rng())
+    }
+}
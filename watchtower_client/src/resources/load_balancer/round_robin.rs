@@ -1,43 +1,57 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use rand::Rng;
-use crate::resources::load_balancer::LoadBalancer;
+use crate::resources::{
+    InstanceInfo,
+    load_balancer::{LoadBalancer, RequestContext}
+};
 
+/// Selects instances in turn, starting from a random one.
 pub struct RoundRobinLoadBalancer {
-    current_index: usize,
-    array_size: usize
+    current_index: AtomicUsize
 }
 
 impl RoundRobinLoadBalancer {
-    pub fn new(array_size: usize) -> Self {
+    pub fn new() -> Self {
         let mut rng = rand::thread_rng();
-        let current_index = rng.gen_range(0..array_size);
         RoundRobinLoadBalancer {
-            current_index,
-            array_size
+            current_index: AtomicUsize::new(rng.gen())
         }
     }
+
+    /// Returns the next index of an array of `array_size` elements
+    pub fn get_next_index(&self, array_size: usize) -> usize {
+        self.current_index.fetch_add(1, Ordering::Relaxed).wrapping_add(1) % array_size
+    }
+}
+
+impl Default for RoundRobinLoadBalancer {
+    fn default() -> Self {
+        RoundRobinLoadBalancer::new()
+    }
 }
 
 impl LoadBalancer for RoundRobinLoadBalancer {
-    fn get_next_index(&mut self) -> usize {
-        self.current_index = (self.current_index + 1) % self.array_size;
-        self.current_index        
+    fn select<'a>(&self, instances: &'a [InstanceInfo], _context: &RequestContext) -> Option<&'a InstanceInfo> {
+        if instances.is_empty() {
+            return None;
+        }
+        instances.get(self.get_next_index(instances.len()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::resources::load_balancer::LoadBalancer;
 
     #[test]
     fn test_get_next_index() {
         let size = 3;
 
-        let mut load_balancer = RoundRobinLoadBalancer::new(size);
-        let start_index = load_balancer.get_next_index();
+        let load_balancer = RoundRobinLoadBalancer::new();
+        let start_index = load_balancer.get_next_index(size);
 
-        assert_eq!((start_index + 1) % size, load_balancer.get_next_index());
-        assert_eq!((start_index + 2) % size, load_balancer.get_next_index());
-        assert_eq!((start_index + 3) % size, load_balancer.get_next_index());
+        assert_eq!((start_index + 1) % size, load_balancer.get_next_index(size));
+        assert_eq!((start_index + 2) % size, load_balancer.get_next_index(size));
+        assert_eq!((start_index + 3) % size, load_balancer.get_next_index(size));
     }
-}
\ No newline at end of file
+}
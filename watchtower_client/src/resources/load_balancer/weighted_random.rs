@@ -0,0 +1,84 @@
+use rand::Rng;
+use crate::resources::{
+    InstanceInfo,
+    load_balancer::{LoadBalancer, RequestContext}
+};
+
+type WeightFn = Box<dyn Fn(&InstanceInfo) -> u32 + Send + Sync>;
+
+/// Selects instances at random, in proportion to their weight.
+///
+/// Instances with a weight of 0 are never selected.
+pub struct WeightedRandomLoadBalancer {
+    weight: WeightFn
+}
+
+impl WeightedRandomLoadBalancer {
+    /// Creates a load balancer that weighs instances by their registered weight
+    pub fn new() -> Self {
+        WeightedRandomLoadBalancer::with_weight(|instance_info| instance_info.weight)
+    }
+
+    /// Creates a load balancer that weighs instances with `weight`
+    pub fn with_weight<F>(weight: F) -> Self
+    where
+        F: Fn(&InstanceInfo) -> u32 + Send + Sync + 'static
+    {
+        WeightedRandomLoadBalancer {
+            weight: Box::new(weight)
+        }
+    }
+}
+
+impl Default for WeightedRandomLoadBalancer {
+    fn default() -> Self {
+        WeightedRandomLoadBalancer::new()
+    }
+}
+
+impl LoadBalancer for WeightedRandomLoadBalancer {
+    fn select<'a>(&self, instances: &'a [InstanceInfo], _context: &RequestContext) -> Option<&'a InstanceInfo> {
+        let weights: Vec<u64> = instances.iter().map(|instance_info| (self.weight)(instance_info) as u64).collect();
+        let total_weight: u64 = weights.iter().sum();
+        if total_weight == 0 {
+            return None;
+        }
+
+        let mut point = rand::thread_rng().gen_range(0..total_weight);
+        for (instance_info, weight) in instances.iter().zip(weights) {
+            if point < weight {
+                return Some(instance_info);
+            }
+            point -= weight;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_instance;
+
+    #[test]
+    fn test_select_by_weight() {
+        let load_balancer = WeightedRandomLoadBalancer::with_weight(|instance_info| match instance_info.instance_id.as_str() {
+            "heavy" => 3,
+            "light" => 1,
+            _ => 0
+        });
+        let instances = vec![create_instance("heavy"), create_instance("light"), create_instance("drained")];
+
+        let mut heavy_count = 0;
+        for _ in 0..4000 {
+            let instance_info = load_balancer.select(&instances, &RequestContext::default()).unwrap();
+            assert_ne!(instance_info.instance_id, "drained");
+            if instance_info.instance_id == "heavy" {
+                heavy_count += 1;
+            }
+        }
+        assert!((2700..3300).contains(&heavy_count));
+
+        assert!(load_balancer.select(&instances[2..], &RequestContext::default()).is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex
+};
+use crate::resources::{
+    InstanceInfo,
+    load_balancer::{LoadBalancer, RequestContext}
+};
+
+/// Selects instances in turn, in proportion to their weight, with the smooth weighted round-robin of nginx.
+///
+/// The turns of an instance are spread evenly rather than taken in a row, so an instance of weight 5 among instances
+/// of weight 1 does not receive bursts of 5 requests. Instances with a weight of 0 are never selected.
+///
+/// The current weight of every instance of the service is kept while it is left out of a selection, such as while it
+/// is ejected, and only dropped once the instance is gone from the service.
+#[derive(Default)]
+pub struct WeightedRoundRobinLoadBalancer {
+    current_weights: Mutex<HashMap<String, i64>>
+}
+
+impl WeightedRoundRobinLoadBalancer {
+    pub fn new() -> Self {
+        WeightedRoundRobinLoadBalancer::default()
+    }
+}
+
+impl LoadBalancer for WeightedRoundRobinLoadBalancer {
+    fn select<'a>(&self, instances: &'a [InstanceInfo], _context: &RequestContext) -> Option<&'a InstanceInfo> {
+        let mut current_weights = self.current_weights.lock().unwrap();
+
+        // Every available instance gains its weight, and the one with the highest current weight is selected and set
+        // back by the total weight. The others keep their current weight until they are available again.
+        let mut total_weight = 0;
+        let mut selected: Option<(&InstanceInfo, i64)> = None;
+        for instance_info in instances.iter().filter(|instance_info| instance_info.weight > 0) {
+            let weight = instance_info.weight as i64;
+            total_weight += weight;
+            let current_weight = current_weights.entry(instance_info.instance_id.to_string()).or_insert(0);
+            *current_weight += weight;
+            if selected.is_none_or(|(_, highest_weight)| *current_weight > highest_weight) {
+                selected = Some((instance_info, *current_weight));
+            }
+        }
+
+        let (instance_info, _) = selected?;
+        if let Some(current_weight) = current_weights.get_mut(&instance_info.instance_id) {
+            *current_weight -= total_weight;
+        }
+        Some(instance_info)
+    }
+
+    fn on_refresh(&self, instances: &[InstanceInfo]) {
+        let instance_ids: HashSet<&str> = instances.iter().map(|instance_info| instance_info.instance_id.as_str()).collect();
+        self.current_weights.lock().unwrap().retain(|instance_id, _| instance_ids.contains(instance_id.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_instance;
+
+    fn create_weighted_instance(instance_id: &str, weight: u32) -> InstanceInfo {
+        InstanceInfo {
+            weight,
+            ..create_instance(instance_id)
+        }
+    }
+
+    fn select_ids(load_balancer: &WeightedRoundRobinLoadBalancer, instances: &[InstanceInfo], count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| load_balancer.select(instances, &RequestContext::default()).unwrap().instance_id.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_select_smoothly_by_weight() {
+        let load_balancer = WeightedRoundRobinLoadBalancer::new();
+        let instances = vec![create_weighted_instance("a", 5), create_weighted_instance("b", 1), create_weighted_instance("c", 1)];
+        assert_eq!(select_ids(&load_balancer, &instances, 7), vec!["a", "a", "b", "a", "c", "a", "a"]);
+    }
+
+    #[test]
+    fn test_zero_weight_drains_instance() {
+        let load_balancer = WeightedRoundRobinLoadBalancer::new();
+        let mut instances = vec![create_weighted_instance("a", 1), create_weighted_instance("b", 1)];
+        assert_eq!(select_ids(&load_balancer, &instances, 4), vec!["a", "b", "a", "b"]);
+
+        instances[1].weight = 0;
+        assert_eq!(select_ids(&load_balancer, &instances, 3), vec!["a", "a", "a"]);
+
+        instances[0].weight = 0;
+        assert!(load_balancer.select(&instances, &RequestContext::default()).is_none());
+    }
+
+    #[test]
+    fn test_keep_state_of_unavailable_instances() {
+        let load_balancer = WeightedRoundRobinLoadBalancer::new();
+        let instances = vec![create_weighted_instance("a", 1), create_weighted_instance("b", 1)];
+        assert_eq!(select_ids(&load_balancer, &instances, 1), vec!["a"]);
+
+        // b is next in turn, and stays so while it is unavailable
+        assert_eq!(select_ids(&load_balancer, &instances[..1], 2), vec!["a", "a"]);
+        assert_eq!(load_balancer.current_weights.lock().unwrap().get("b"), Some(&1));
+        assert_eq!(select_ids(&load_balancer, &instances, 2), vec!["b", "a"]);
+
+        load_balancer.on_refresh(&instances[..1]);
+        assert_eq!(load_balancer.current_weights.lock().unwrap().get("b"), None);
+    }
+}
@@ -1,9 +1,19 @@
 mod instance_info;
 mod service;
 mod http_client;
+mod registration;
+mod outlier_detection;
+mod disk_cache;
+mod service_cache;
 
 pub mod load_balancer;
 
-pub use instance_info::InstanceInfo;
-pub use service::Service;
-pub use http_client::HttpClient;
\ No newline at end of file
+pub use instance_info::{InstanceInfo, InstanceStatus, InstanceUpdate, LeaseInfo, RegistryHealth, DEFAULT_WEIGHT};
+pub use registration::{
+    Registration, RegisteredInstance, RegistrationOptions, RegistrationState, Registrations, cancel_all_registrations, renew_or_register, run_heartbeat
+};
+pub use service::{Service, UPDATE_INTERVAL_MS};
+pub use outlier_detection::{CallOutcome, CircuitState, InstanceHealthReport, OutlierDetectionConfig, OutlierDetector};
+pub use http_client::HttpClient;
+pub use disk_cache::DiskCache;
+pub use service_cache::{ServiceCache, refresh_stale_service, run_refresher};
\ No newline at end of file
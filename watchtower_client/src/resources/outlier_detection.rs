@@ -0,0 +1,423 @@
+use std::{
+    cmp::min,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration
+};
+use serde::Serialize;
+use log::warn;
+use crate::{
+    resources::InstanceInfo,
+    utils::time::{Clock, MonotonicClock}
+};
+
+/// The weight of the last call in the moving average of call latencies.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// How long a half-open instance waits for the outcome of its probe before letting another caller probe it.
+const PROBE_TIMEOUT_MS: u64 = 10_000;
+
+/// When instances are ejected from selection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutlierDetectionConfig {
+    /// The number of consecutive failed calls that ejects an instance
+    pub consecutive_failures: u32,
+    /// The share of failed calls in a window that ejects an instance, between 0 and 1
+    pub failure_rate_threshold: f64,
+    /// The minimum number of calls in a window for the failure rate to be considered
+    pub failure_rate_minimum_calls: u32,
+    /// The duration of the windows the failure rate is computed over
+    pub failure_rate_window: Duration,
+    /// Calls slower than this are counted as failures, if set
+    pub slow_call_threshold: Option<Duration>,
+    /// How long an instance is ejected the first time, multiplied by the number of times it has been ejected
+    pub base_ejection_time: Duration,
+    /// The upper bound of how long an instance is ejected
+    pub max_ejection_time: Duration
+}
+
+impl Default for OutlierDetectionConfig {
+    fn default() -> Self {
+        OutlierDetectionConfig {
+            consecutive_failures: 5,
+            failure_rate_threshold: 0.5,
+            failure_rate_minimum_calls: 20,
+            failure_rate_window: Duration::from_secs(10),
+            slow_call_threshold: None,
+            base_ejection_time: Duration::from_secs(30),
+            max_ejection_time: Duration::from_secs(300)
+        }
+    }
+}
+
+/// The outcome of a call to an instance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CallOutcome {
+    pub is_success: bool,
+    pub latency: Duration
+}
+
+impl CallOutcome {
+    pub fn success(latency: Duration) -> Self {
+        CallOutcome { is_success: true, latency }
+    }
+
+    pub fn failure(latency: Duration) -> Self {
+        CallOutcome { is_success: false, latency }
+    }
+}
+
+/// The state of the circuit of an instance.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CircuitState {
+    /// The instance is selected normally.
+    Closed,
+    /// The instance is ejected from selection.
+    Open,
+    /// The ejection is over and a single call probes the instance. A failure ejects the instance again, and a success
+    /// closes the circuit.
+    HalfOpen
+}
+
+/// The health of an instance, as reported by callers.
+#[derive(Clone, Debug, Serialize)]
+pub struct InstanceHealthReport {
+    pub service_id: String,
+    pub instance_id: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// The share of failed calls in the current window
+    pub failure_rate: f64,
+    /// The number of times the instance has been ejected without recovering since
+    pub ejection_count: u32,
+    /// The time left before the ejection is over, in milliseconds, while the circuit is open
+    pub ejection_remaining_ms: Option<u64>,
+    /// A moving average of the latency of calls
+    pub average_latency_ms: Option<f64>
+}
+
+#[derive(Default)]
+struct InstanceHealth {
+    consecutive_failures: u32,
+    window_start_millis: u64,
+    window_calls: u32,
+    window_failures: u32,
+    ejection_count: u32,
+    ejected_until_millis: Option<u64>,
+    is_half_open: bool,
+    /// When the probe of a half-open instance was selected, until its outcome is reported
+    probe_started_millis: Option<u64>,
+    average_latency_ms: Option<f64>
+}
+
+impl InstanceHealth {
+    fn failure_rate(&self) -> f64 {
+        if self.window_calls == 0 {
+            0.0
+        } else {
+            self.window_failures as f64 / self.window_calls as f64
+        }
+    }
+
+    /// Puts the instance on probation if its ejection is over, returning false if it is still ejected
+    fn end_ejection(&mut self, now: u64) -> bool {
+        match self.ejected_until_millis {
+            Some(ejected_until_millis) if now < ejected_until_millis => false,
+            Some(_) => {
+                self.ejected_until_millis = None;
+                self.is_half_open = true;
+                self.probe_started_millis = None;
+                self.window_start_millis = now;
+                true
+            },
+            None => true
+        }
+    }
+
+    /// Returns true if a probe of the half-open instance is awaiting its outcome
+    fn is_probing(&self, now: u64) -> bool {
+        self.is_half_open && self.probe_started_millis.is_some_and(|started_millis| now < started_millis + PROBE_TIMEOUT_MS)
+    }
+
+    fn state(&self) -> CircuitState {
+        if self.ejected_until_millis.is_some() {
+            CircuitState::Open
+        } else if self.is_half_open {
+            CircuitState::HalfOpen
+        } else {
+            CircuitState::Closed
+        }
+    }
+}
+
+/// Tracks the outcome of calls to instances and ejects outliers from selection, like the outlier detection of Envoy.
+pub struct OutlierDetector {
+    config: OutlierDetectionConfig,
+    instances: Mutex<HashMap<(String, String), InstanceHealth>>,
+    clock: Arc<dyn Clock>
+}
+
+impl OutlierDetector {
+    pub fn new(config: OutlierDetectionConfig) -> Self {
+        OutlierDetector::with_clock(config, Arc::new(MonotonicClock::new()))
+    }
+
+    /// Creates an `OutlierDetector` that measures ejections with `clock`
+    pub(crate) fn with_clock(config: OutlierDetectionConfig, clock: Arc<dyn Clock>) -> Self {
+        OutlierDetector {
+            config,
+            instances: Mutex::new(HashMap::new()),
+            clock
+        }
+    }
+
+    /// Records the outcome of a call to an instance, ejecting it if it is an outlier
+    pub fn report(&self, service_id: &str, instance_id: &str, outcome: CallOutcome) {
+        let now = self.clock.now_millis();
+        let mut instances = self.instances.lock().unwrap();
+        let health = instances.entry((service_id.to_string(), instance_id.to_string())).or_default();
+        if health.ejected_until_millis.is_some() {
+            // The call was sent before the instance was ejected
+            return;
+        }
+        health.probe_started_millis = None;
+
+        let latency_ms = outcome.latency.as_secs_f64() * 1000.0;
+        health.average_latency_ms = Some(match health.average_latency_ms {
+            Some(average_latency_ms) => LATENCY_SMOOTHING * latency_ms + (1.0 - LATENCY_SMOOTHING) * average_latency_ms,
+            None => latency_ms
+        });
+
+        if now >= health.window_start_millis + self.config.failure_rate_window.as_millis() as u64 {
+            health.window_start_millis = now;
+            health.window_calls = 0;
+            health.window_failures = 0;
+        }
+        health.window_calls += 1;
+
+        let is_slow = self.config.slow_call_threshold.is_some_and(|threshold| outcome.latency > threshold);
+        if outcome.is_success && !is_slow {
+            health.consecutive_failures = 0;
+            if health.is_half_open {
+                health.is_half_open = false;
+                health.ejection_count = health.ejection_count.saturating_sub(1);
+            }
+            return;
+        }
+
+        health.consecutive_failures += 1;
+        health.window_failures += 1;
+        let is_outlier = health.is_half_open
+            || health.consecutive_failures >= self.config.consecutive_failures
+            || (health.window_calls >= self.config.failure_rate_minimum_calls
+                && health.failure_rate() >= self.config.failure_rate_threshold);
+        if is_outlier {
+            health.ejection_count += 1;
+            let ejection_time = min(self.config.base_ejection_time * health.ejection_count, self.config.max_ejection_time);
+            warn!("Ejecting instance {} of service {} for {:?}", instance_id, service_id, ejection_time);
+            health.ejected_until_millis = Some(now + ejection_time.as_millis() as u64);
+            health.is_half_open = false;
+            health.consecutive_failures = 0;
+            health.window_calls = 0;
+            health.window_failures = 0;
+        }
+    }
+
+    /// Returns whether an instance can be selected, ending its ejection if it is over
+    ///
+    /// A half-open instance is not available while another caller probes it.
+    pub fn is_available(&self, service_id: &str, instance_id: &str) -> bool {
+        let now = self.clock.now_millis();
+        let mut instances = self.instances.lock().unwrap();
+        match instances.get_mut(&(service_id.to_string(), instance_id.to_string())) {
+            Some(health) => health.end_ejection(now) && !health.is_probing(now),
+            None => true
+        }
+    }
+
+    /// Returns true if an instance is half-open and another caller probes it
+    pub fn is_probing(&self, service_id: &str, instance_id: &str) -> bool {
+        let now = self.clock.now_millis();
+        self.instances.lock().unwrap()
+            .get(&(service_id.to_string(), instance_id.to_string()))
+            .is_some_and(|health| health.is_probing(now))
+    }
+
+    /// Records that an instance was selected, returning false if it is half-open and already being probed
+    ///
+    /// The selection of a half-open instance is its probe, until its outcome is reported or `PROBE_TIMEOUT_MS` passes.
+    pub fn start_call(&self, service_id: &str, instance_id: &str) -> bool {
+        let now = self.clock.now_millis();
+        let mut instances = self.instances.lock().unwrap();
+        let health = match instances.get_mut(&(service_id.to_string(), instance_id.to_string())) {
+            Some(health) => health,
+            None => return true
+        };
+        if !health.end_ejection(now) || health.is_probing(now) {
+            return false;
+        }
+        if health.is_half_open {
+            health.probe_started_millis = Some(now);
+        }
+        true
+    }
+
+    /// Returns the instances of a service that can be selected
+    ///
+    /// If every instance is ejected, they are all returned, since sending requests to ejected instances is better
+    /// than sending none.
+    pub fn filter_available(&self, service_id: &str, instance_infos: &[InstanceInfo]) -> Vec<InstanceInfo> {
+        let available: Vec<InstanceInfo> = instance_infos.iter()
+            .filter(|instance_info| self.is_available(service_id, &instance_info.instance_id))
+            .cloned()
+            .collect();
+        if available.is_empty() {
+            instance_infos.to_vec()
+        } else {
+            available
+        }
+    }
+
+    /// Returns the health of every instance a call has been reported for
+    pub fn report_health(&self) -> Vec<InstanceHealthReport> {
+        let now = self.clock.now_millis();
+        self.instances.lock().unwrap().iter()
+            .map(|((service_id, instance_id), health)| InstanceHealthReport {
+                service_id: service_id.to_string(),
+                instance_id: instance_id.to_string(),
+                state: health.state(),
+                consecutive_failures: health.consecutive_failures,
+                failure_rate: health.failure_rate(),
+                ejection_count: health.ejection_count,
+                ejection_remaining_ms: health.ejected_until_millis
+                    .map(|ejected_until_millis| ejected_until_millis.saturating_sub(now)),
+                average_latency_ms: health.average_latency_ms
+            })
+            .collect()
+    }
+
+    /// Forgets the instances of a service that are not in `instance_infos`
+    pub fn retain(&self, service_id: &str, instance_infos: &[InstanceInfo]) {
+        self.instances.lock().unwrap().retain(|(health_service_id, instance_id), _| {
+            health_service_id != service_id
+                || instance_infos.iter().any(|instance_info| &instance_info.instance_id == instance_id)
+        });
+    }
+}
+
+impl Default for OutlierDetector {
+    fn default() -> Self {
+        OutlierDetector::new(OutlierDetectionConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::create_instance, utils::time::ManualClock};
+
+    fn create_detector(clock: Arc<ManualClock>) -> OutlierDetector {
+        let config = OutlierDetectionConfig {
+            consecutive_failures: 3,
+            failure_rate_threshold: 0.5,
+            failure_rate_minimum_calls: 10,
+            failure_rate_window: Duration::from_secs(10),
+            slow_call_threshold: Some(Duration::from_secs(1)),
+            base_ejection_time: Duration::from_secs(30),
+            max_ejection_time: Duration::from_secs(60)
+        };
+        OutlierDetector::with_clock(config, clock)
+    }
+
+    fn get_state(detector: &OutlierDetector) -> CircuitState {
+        detector.report_health()[0].state
+    }
+
+    #[test]
+    fn test_eject_on_consecutive_failures() {
+        let clock = Arc::new(ManualClock::new());
+        let detector = create_detector(clock.clone());
+        let failure = CallOutcome::failure(Duration::from_millis(10));
+
+        detector.report("service", "instance", failure);
+        detector.report("service", "instance", CallOutcome::success(Duration::from_millis(10)));
+        detector.report("service", "instance", failure);
+        detector.report("service", "instance", failure);
+        assert!(detector.is_available("service", "instance"));
+        detector.report("service", "instance", CallOutcome::success(Duration::from_secs(2)));
+        assert!(!detector.is_available("service", "instance"));
+        assert_eq!(get_state(&detector), CircuitState::Open);
+
+        // The instance is probed once the ejection is over, and ejected for longer if it fails again
+        clock.advance(Duration::from_secs(30));
+        assert!(detector.is_available("service", "instance"));
+        assert_eq!(get_state(&detector), CircuitState::HalfOpen);
+        assert!(detector.start_call("service", "instance"));
+        detector.report("service", "instance", failure);
+        clock.advance(Duration::from_secs(59));
+        assert!(!detector.is_available("service", "instance"));
+        assert_eq!(detector.report_health()[0].ejection_remaining_ms, Some(1000));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(detector.is_available("service", "instance"));
+        assert!(detector.start_call("service", "instance"));
+        detector.report("service", "instance", CallOutcome::success(Duration::from_millis(10)));
+        assert_eq!(get_state(&detector), CircuitState::Closed);
+        assert_eq!(detector.report_health()[0].ejection_count, 1);
+    }
+
+    #[test]
+    fn test_single_probe_while_half_open() {
+        let clock = Arc::new(ManualClock::new());
+        let detector = create_detector(clock.clone());
+        for _ in 0..3 {
+            detector.report("service", "instance", CallOutcome::failure(Duration::from_millis(10)));
+        }
+        clock.advance(Duration::from_secs(30));
+
+        // Concurrent callers are filtered out while the probe is in flight
+        assert!(detector.start_call("service", "instance"));
+        assert!(!detector.is_available("service", "instance"));
+        assert!(!detector.start_call("service", "instance"));
+        assert_eq!(get_state(&detector), CircuitState::HalfOpen);
+
+        // A probe whose outcome is never reported is given up
+        clock.advance(Duration::from_millis(PROBE_TIMEOUT_MS));
+        assert!(detector.is_available("service", "instance"));
+        assert!(detector.start_call("service", "instance"));
+        detector.report("service", "instance", CallOutcome::success(Duration::from_millis(10)));
+        assert_eq!(get_state(&detector), CircuitState::Closed);
+        assert!(detector.start_call("service", "instance"));
+        assert!(detector.start_call("service", "instance"));
+    }
+
+    #[test]
+    fn test_eject_on_failure_rate() {
+        let clock = Arc::new(ManualClock::new());
+        let detector = create_detector(clock);
+
+        for _ in 0..5 {
+            detector.report("service", "instance", CallOutcome::success(Duration::from_millis(10)));
+            detector.report("service", "instance", CallOutcome::failure(Duration::from_millis(10)));
+        }
+        assert!(!detector.is_available("service", "instance"));
+    }
+
+    #[test]
+    fn test_filter_available() {
+        let clock = Arc::new(ManualClock::new());
+        let detector = create_detector(clock);
+        let instance_infos: Vec<InstanceInfo> = ["a", "b"].iter().map(|instance_id| create_instance(instance_id)).collect();
+
+        for _ in 0..3 {
+            detector.report("service", "a", CallOutcome::failure(Duration::from_millis(10)));
+        }
+        assert_eq!(detector.filter_available("service", &instance_infos), vec![instance_infos[1].clone()]);
+
+        for _ in 0..3 {
+            detector.report("service", "b", CallOutcome::failure(Duration::from_millis(10)));
+        }
+        assert_eq!(detector.filter_available("service", &instance_infos), instance_infos);
+    }
+}
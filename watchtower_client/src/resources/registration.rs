@@ -0,0 +1,362 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    time::Duration
+};
+use futures_util::future::join_all;
+use futures_util::future::FutureExt;
+use log::{error, info, warn};
+use tokio::{sync::watch, time::Instant};
+use crate::{
+    resources::{HttpClient, InstanceStatus, DEFAULT_WEIGHT},
+    types::{InstanceInfo, InstanceUpdate, Result, Error},
+    utils::spawner::Spawner
+};
+
+/// Options of a new registration.
+#[derive(Clone, Debug)]
+pub struct RegistrationOptions {
+    /// The share of traffic the instance receives relative to the other instances of its service. 0 drains it.
+    pub weight: u32,
+    /// Free-form attributes of the instance, such as `grpc_port`
+    pub metadata: HashMap<String, String>
+}
+
+impl Default for RegistrationOptions {
+    fn default() -> Self {
+        RegistrationOptions {
+            weight: DEFAULT_WEIGHT,
+            metadata: HashMap::new()
+        }
+    }
+}
+
+/// The state of the lease of a registered instance, as seen by the heartbeat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegistrationState {
+    /// The lease is held and renewed.
+    Registered,
+    /// Renewing the lease failed and is being retried.
+    Renewing,
+    /// The lease could not be renewed or registered again, and may have expired.
+    Lost,
+    /// The registry no longer held the lease, so the instance was registered again.
+    ReRegistered
+}
+
+/// An instance registered through a `WatchtowerClient`.
+#[derive(Clone, Debug)]
+pub struct RegisteredInstance {
+    pub service_id: String,
+    pub instance_info: InstanceInfo,
+    /// Whether the lease is renewed by the client's heartbeat.
+    pub is_heartbeat_enabled: bool,
+    state_sender: Arc<watch::Sender<RegistrationState>>,
+    state_receiver: watch::Receiver<RegistrationState>
+}
+
+impl RegisteredInstance {
+    pub fn new(service_id: &str, instance_info: InstanceInfo, is_heartbeat_enabled: bool) -> Self {
+        let (state_sender, state_receiver) = watch::channel(RegistrationState::Registered);
+        RegisteredInstance {
+            service_id: service_id.to_string(),
+            instance_info,
+            is_heartbeat_enabled,
+            state_sender: Arc::new(state_sender),
+            state_receiver
+        }
+    }
+
+    /// Returns the last known state of the lease
+    pub fn state(&self) -> RegistrationState {
+        *self.state_receiver.borrow()
+    }
+
+    /// Returns a channel that receives every change to the state of the lease
+    pub fn watch_state(&self) -> watch::Receiver<RegistrationState> {
+        self.state_receiver.clone()
+    }
+
+    fn set_state(&self, state: RegistrationState) {
+        // The instance holds a receiver, so this cannot fail
+        let _ = self.state_sender.broadcast(state);
+    }
+}
+
+/// The instances registered through a `WatchtowerClient`, keyed by instance id.
+pub type Registrations = Arc<Mutex<HashMap<String, RegisteredInstance>>>;
+
+/// A handle to a registered instance.
+///
+/// The lease is cancelled in the background when the handle is dropped, unless it has been detached.
+#[must_use = "dropping the handle cancels the lease; call detach() to keep it"]
+pub struct Registration {
+    service_id: String,
+    instance_id: String,
+    http_client: Arc<HttpClient>,
+    registrations: Registrations,
+    spawner: Arc<dyn Spawner>,
+    state: watch::Receiver<RegistrationState>,
+    is_detached: bool
+}
+
+impl Registration {
+    pub fn new(
+        service_id: &str,
+        instance_id: &str,
+        http_client: Arc<HttpClient>,
+        registrations: Registrations,
+        spawner: Arc<dyn Spawner>,
+        state: watch::Receiver<RegistrationState>
+    ) -> Self {
+        Registration {
+            service_id: service_id.to_string(),
+            instance_id: instance_id.to_string(),
+            http_client,
+            registrations,
+            spawner,
+            state,
+            is_detached: false
+        }
+    }
+
+    /// Releases the handle without cancelling the lease
+    ///
+    /// The instance stays registered through the client, which can still renew or cancel it.
+    pub fn detach(mut self) {
+        self.is_detached = true;
+    }
+
+    pub fn service_id(&self) -> &str {
+        &self.service_id
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Returns the current instance info, or `None` once the registration is cancelled
+    pub fn instance_info(&self) -> Option<InstanceInfo> {
+        self.registrations.lock().unwrap().get(&self.instance_id)
+            .map(|registered_instance| registered_instance.instance_info.clone())
+    }
+
+    /// Returns the last known state of the lease
+    pub fn state(&self) -> RegistrationState {
+        *self.state.borrow()
+    }
+
+    /// Returns a channel that receives every change to the state of the lease
+    ///
+    /// The channel is closed once the registration is cancelled.
+    pub fn watch_state(&self) -> watch::Receiver<RegistrationState> {
+        self.state.clone()
+    }
+
+    /// Renews the lease of the instance, registering it again if the registry no longer holds it
+    pub async fn ping(&self) -> Result<()> {
+        let registered_instance = self.registrations.lock().unwrap().get(&self.instance_id).cloned()
+            .ok_or(Error::InvalidPing)?;
+        renew_or_register(&self.http_client, &registered_instance).await
+    }
+
+    /// Updates the status of the instance on the registry
+    ///
+    /// If the registry no longer holds the lease, the instance is registered again with the new status.
+    pub async fn set_status(&self, status: InstanceStatus) -> Result<()> {
+        self.update(InstanceUpdate { status: Some(status), ..InstanceUpdate::default() }).await
+    }
+
+    /// Updates the weight of the instance on the registry, where 0 drains it
+    ///
+    /// If the registry no longer holds the lease, the instance is registered again with the new weight.
+    pub async fn set_weight(&self, weight: u32) -> Result<()> {
+        self.update(InstanceUpdate { weight: Some(weight), ..InstanceUpdate::default() }).await
+    }
+
+    /// Applies `update` to the instance on the registry
+    ///
+    /// If the registry no longer holds the lease, the instance is registered again with the update applied.
+    pub async fn update(&self, update: InstanceUpdate) -> Result<()> {
+        let mut instance_info = self.instance_info().ok_or(Error::NotFound)?;
+        update.apply(&mut instance_info);
+
+        match self.http_client.update_instance(&self.service_id, &self.instance_id, &update).await {
+            Ok(()) => {},
+            Err(Error::NotFound) => self.http_client.register(&self.service_id, &instance_info).await?,
+            Err(err) => return Err(err)
+        }
+
+        if let Some(registered_instance) = self.registrations.lock().unwrap().get_mut(&self.instance_id) {
+            update.apply(&mut registered_instance.instance_info);
+        }
+        Ok(())
+    }
+
+    /// Cancels the lease of the instance
+    pub async fn cancel(&self) -> Result<()> {
+        cancel_registration(&self.http_client, &self.registrations, &self.instance_id).await
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if self.is_detached || !self.registrations.lock().unwrap().contains_key(&self.instance_id) {
+            return;
+        }
+
+        let http_client = self.http_client.clone();
+        let registrations = self.registrations.clone();
+        let instance_id = self.instance_id.to_string();
+        let cancellation = async move {
+            if let Err(err) = cancel_registration(&http_client, &registrations, &instance_id).await {
+                error!("Unable to cancel dropped instance {}: {:?}", instance_id, err);
+            }
+        };
+        if self.spawner.spawn(cancellation.boxed()).is_err() {
+            warn!("Instance {} was dropped outside of a runtime, its lease will expire on its own", self.instance_id);
+        }
+    }
+}
+
+/// Cancels the lease of a registered instance and forgets about it
+pub async fn cancel_registration(http_client: &HttpClient, registrations: &Registrations, instance_id: &str) -> Result<()> {
+    // The instance is removed first so that the heartbeat does not register it again
+    let registered_instance = registrations.lock().unwrap().remove(instance_id).ok_or(Error::NotFound)?;
+    if let Err(err) = http_client.cancel(&registered_instance.service_id, &registered_instance.instance_info).await {
+        registrations.lock().unwrap().insert(instance_id.to_string(), registered_instance);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Cancels the leases of every registered instance
+pub async fn cancel_all_registrations(http_client: &HttpClient, registrations: &Registrations) -> Result<()> {
+    let instance_ids: Vec<String> = registrations.lock().unwrap().keys().cloned().collect();
+    let results = join_all(instance_ids.iter().map(|instance_id| {
+        cancel_registration(http_client, registrations, instance_id)
+    })).await;
+    results.into_iter().collect()
+}
+
+/// Renews the lease of a registered instance, registering it again if the registry no longer holds it
+pub async fn renew_or_register(http_client: &HttpClient, registered_instance: &RegisteredInstance) -> Result<()> {
+    let service_id = &registered_instance.service_id;
+    let instance_info = &registered_instance.instance_info;
+    match http_client.renew(service_id, instance_info).await {
+        Err(Error::NotFound) => {
+            http_client.register(service_id, instance_info).await?;
+            info!("Instance {} was registered again", instance_info.instance_id);
+            registered_instance.set_state(RegistrationState::ReRegistered);
+            Ok(())
+        },
+        result => result
+    }
+}
+
+/// Renews the lease of a registered instance for up to `retry_window`, backing off between retries as set by the retry
+/// policy of `http_client`
+///
+/// Every attempt is cut short at the end of the window, so that the lease is reported lost within it.
+async fn renew_with_backoff(http_client: &HttpClient, registered_instance: &RegisteredInstance, retry_window: Duration) {
+    let deadline = Instant::now() + retry_window;
+    let mut retry = 1;
+    loop {
+        let time_left = deadline.saturating_duration_since(Instant::now());
+        let attempt = tokio::time::timeout(time_left, renew_or_register(http_client, registered_instance)).await;
+        match attempt.unwrap_or(Err(Error::Timeout)) {
+            Ok(()) => {
+                let state = registered_instance.state();
+                if state == RegistrationState::Renewing || state == RegistrationState::Lost {
+                    registered_instance.set_state(RegistrationState::Registered);
+                }
+                return;
+            },
+            Err(err) => {
+                let instance_id = &registered_instance.instance_info.instance_id;
+                let backoff = http_client.retry_policy().backoff(retry);
+                if Instant::now() + backoff >= deadline {
+                    error!("Lost the lease of instance {}: {:?}", instance_id, err);
+                    registered_instance.set_state(RegistrationState::Lost);
+                    return;
+                }
+                warn!("Unable to renew instance {}, retrying in {:?}: {:?}", instance_id, backoff, err);
+                registered_instance.set_state(RegistrationState::Renewing);
+                tokio::time::delay_for(backoff).await;
+                retry += 1;
+            }
+        }
+    }
+}
+
+/// Renews every registration with heartbeat enabled every `interval`
+///
+/// Failed renewals are retried with a backoff until the next beat, and the outcome is published to the registration's
+/// state channel. The loop stops once the registrations are dropped, that is once the client and all its
+/// `Registration` handles are gone.
+pub async fn run_heartbeat(http_client: Arc<HttpClient>, registrations: Weak<Mutex<HashMap<String, RegisteredInstance>>>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let registered_instances: Vec<RegisteredInstance> = match registrations.upgrade() {
+            Some(registrations) => registrations.lock().unwrap().values()
+                .filter(|registered_instance| registered_instance.is_heartbeat_enabled)
+                .cloned()
+                .collect(),
+            None => return
+        };
+
+        join_all(registered_instances.iter().map(|registered_instance| {
+            renew_with_backoff(&http_client, registered_instance, interval)
+        })).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_instance;
+
+    fn create_registered_instance(service_id: &str) -> RegisteredInstance {
+        RegisteredInstance::new(service_id, create_instance("instance"), true)
+    }
+
+    fn create_http_client(url: &str) -> HttpClient {
+        HttpClient::new(vec![url.to_string()], "admin".to_string(), "password".to_string())
+    }
+
+    #[actix_rt::test]
+    async fn test_lost_lease_is_registered_again() {
+        let _renew = mockito::mock("PUT", "/api/v1/services/test_lost_lease/instance").with_status(404).create();
+        let _register = mockito::mock("POST", "/api/v1/services/test_lost_lease").with_status(204).create();
+        let http_client = create_http_client(mockito::SERVER_URL);
+        let registered_instance = create_registered_instance("test_lost_lease");
+
+        renew_with_backoff(&http_client, &registered_instance, Duration::from_secs(1)).await;
+        assert_eq!(registered_instance.state(), RegistrationState::ReRegistered);
+    }
+
+    #[actix_rt::test]
+    async fn test_unreachable_registry_loses_lease() {
+        let http_client = create_http_client("http://127.0.0.1:1");
+        let registered_instance = create_registered_instance("test_unreachable_registry");
+        let mut state = registered_instance.watch_state();
+        assert_eq!(state.recv().await, Some(RegistrationState::Registered));
+
+        renew_with_backoff(&http_client, &registered_instance, Duration::from_secs(1)).await;
+        assert_eq!(registered_instance.state(), RegistrationState::Lost);
+    }
+
+    #[actix_rt::test]
+    async fn test_unresponsive_registry_loses_lease_within_window() {
+        // The listener accepts connections but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let http_client = create_http_client(&format!("http://{}", listener.local_addr().unwrap()));
+        let registered_instance = create_registered_instance("test_unresponsive_registry");
+
+        let start = Instant::now();
+        renew_with_backoff(&http_client, &registered_instance, Duration::from_millis(500)).await;
+        assert_eq!(registered_instance.state(), RegistrationState::Lost);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -1,77 +1,180 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc
+    },
+    time::Duration
+};
 use crate::{
     resources::{InstanceInfo, 
         load_balancer::{
-            RoundRobinLoadBalancer,
-            LoadBalancer
+            WeightedRoundRobinLoadBalancer,
+            LoadBalancer,
+            RequestContext
         }
     },
-    utils::time::get_time_since_epoch,
+    utils::time::{Clock, MonotonicClock},
     types::{Result, Error}
 };
 
-pub const UPDATE_INTERVAL: u64 = 30;
+/// The default time after which a cached service is fetched again
+pub const UPDATE_INTERVAL_MS: u64 = 30_000;
 
 pub struct Service {
     pub instance_infos: Vec<InstanceInfo>,
-    pub load_balancer: RoundRobinLoadBalancer,
-    pub last_updated_timestamp: u64
+    pub load_balancer: Arc<dyn LoadBalancer>,
+    pub last_updated_millis: u64,
+    ttl_millis: u64,
+    expires_at_millis: u64,
+    /// How long the service had already been expired when it was created, for services read from the cache file
+    seeded_staleness_millis: u64,
+    last_used_millis: AtomicU64,
+    is_stale: bool,
+    clock: Arc<dyn Clock>
 }
 
 impl Service {
     pub fn new(instance_infos: Vec<InstanceInfo>) -> Self {
+        Service::with_load_balancer(instance_infos, Duration::from_millis(UPDATE_INTERVAL_MS), Arc::new(WeightedRoundRobinLoadBalancer::new()))
+    }
+
+    /// Creates a `Service` that expires `ttl` after its creation and selects instances with `load_balancer`
+    pub fn with_load_balancer(instance_infos: Vec<InstanceInfo>, ttl: Duration, load_balancer: Arc<dyn LoadBalancer>) -> Self {
+        Service::with_clock(instance_infos, ttl, load_balancer, Arc::new(MonotonicClock::new()))
+    }
+
+    /// Creates a `Service` that expires `ttl` after its creation, as measured with `clock`
+    pub(crate) fn with_clock(instance_infos: Vec<InstanceInfo>, ttl: Duration, load_balancer: Arc<dyn LoadBalancer>, clock: Arc<dyn Clock>) -> Self {
+        let now_millis = clock.now_millis();
+        load_balancer.on_refresh(&instance_infos);
         Service {
-            load_balancer: RoundRobinLoadBalancer::new(instance_infos.len()),
+            load_balancer,
             instance_infos,
-            last_updated_timestamp: get_time_since_epoch().unwrap()
+            last_updated_millis: now_millis,
+            ttl_millis: ttl.as_millis() as u64,
+            expires_at_millis: now_millis + ttl.as_millis() as u64,
+            seeded_staleness_millis: 0,
+            last_used_millis: AtomicU64::new(now_millis),
+            is_stale: false,
+            clock
         }
     }
 
     /// Returns true if the service is expired
     pub fn is_expired(&self) -> Result<bool> {
-        Ok((self.last_updated_timestamp + UPDATE_INTERVAL) <= get_time_since_epoch()?)
+        Ok(self.expires_at_millis <= self.clock.now_millis())
     }
 
-    /// Gets the next instance for the given service
-    pub fn get_next_instance(&mut self) -> Result<InstanceInfo> {
-        let index = self.load_balancer.get_next_index();
-        match self.instance_infos.get(index) {
-            Some(instance_info) => Ok(instance_info.clone()),
-            None => Err(Error::InternalError)
+    /// Returns how long until the service expires, or zero if it has
+    pub fn expires_in(&self) -> Duration {
+        Duration::from_millis(self.expires_at_millis.saturating_sub(self.clock.now_millis()))
+    }
+
+    /// Records that the service was looked up, which keeps it from being idle
+    pub fn mark_used(&self) {
+        self.last_used_millis.store(self.clock.now_millis(), Ordering::Relaxed);
+    }
+
+    /// Returns how long ago the service was last used, or created if it never was
+    pub fn idle_time(&self) -> Duration {
+        Duration::from_millis(self.clock.now_millis().saturating_sub(self.last_used_millis.load(Ordering::Relaxed)))
+    }
+
+    /// Expires the service now, so that it is fetched again before its instances are served
+    pub fn expire(&mut self) {
+        self.expire_with_staleness(Duration::ZERO);
+    }
+
+    /// Expires the service as if it had expired `staleness` ago, such as instances read from the cache file
+    pub fn expire_with_staleness(&mut self, staleness: Duration) {
+        self.expires_at_millis = self.clock.now_millis();
+        self.seeded_staleness_millis = staleness.as_millis() as u64;
+    }
+
+    /// Returns true if the service could not be fetched again once expired
+    pub fn is_stale(&self) -> bool {
+        self.is_stale
+    }
+
+    /// Marks the service as stale, returning false if it already was
+    pub fn mark_stale(&mut self) -> bool {
+        !std::mem::replace(&mut self.is_stale, true)
+    }
+
+    /// Returns how long ago the service expired, or zero if it has not
+    pub fn staleness(&self) -> Duration {
+        match self.clock.now_millis().checked_sub(self.expires_at_millis) {
+            Some(staleness_millis) => Duration::from_millis(staleness_millis + self.seeded_staleness_millis),
+            None => Duration::ZERO
         }
     }
+
+    /// Replaces the instances of the service with freshly fetched ones
+    pub fn refresh(&mut self, instance_infos: Vec<InstanceInfo>) {
+        self.load_balancer.on_refresh(&instance_infos);
+        self.instance_infos = instance_infos;
+        self.last_updated_millis = self.clock.now_millis();
+        self.expires_at_millis = self.last_updated_millis + self.ttl_millis;
+        self.seeded_staleness_millis = 0;
+        self.is_stale = false;
+    }
+
+    /// Gets the next instance for the given service
+    pub fn get_next_instance(&self) -> Result<InstanceInfo> {
+        self.select(&RequestContext::default())
+    }
+
+    /// Selects an instance for a request with `context`
+    ///
+    /// Returns `Error::NoAvailableInstance` if the load balancer selects none.
+    pub fn select(&self, context: &RequestContext) -> Result<InstanceInfo> {
+        self.select_from(&self.instance_infos, context)
+    }
+
+    /// Selects an instance among `instance_infos`, a subset of the instances of the service, for a request with `context`
+    pub fn select_from(&self, instance_infos: &[InstanceInfo], context: &RequestContext) -> Result<InstanceInfo> {
+        self.load_balancer.select(instance_infos, context).cloned().ok_or(Error::NoAvailableInstance)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::resources::instance_info::InstanceInfo;
+    use crate::{
+        test_utils::create_instance,
+        utils::time::ManualClock
+    };
 
     #[test]
     fn test_is_expired() {
-        let service = Service::new(vec![InstanceInfo {
-            instance_id: "test".to_string(),
-            ip_addr: "0.0.0.0".to_string(),
-            port: 8888
-        }]);
-        assert_eq!(service.is_expired().unwrap(), false);
-        let sleep_time = std::time::Duration::from_millis(UPDATE_INTERVAL * 1000);
-        std::thread::sleep(sleep_time);
-        assert_eq!(service.is_expired().unwrap(), true);
+        let clock = Arc::new(ManualClock::new());
+        let mut service = Service::with_clock(vec![create_instance("test")], Duration::from_millis(UPDATE_INTERVAL_MS), Arc::new(WeightedRoundRobinLoadBalancer::new()), clock.clone());
+        assert!(!service.is_expired().unwrap());
+        clock.advance(Duration::from_millis(UPDATE_INTERVAL_MS - 1));
+        assert!(!service.is_expired().unwrap());
+        assert_eq!(service.expires_in(), Duration::from_millis(1));
+        clock.advance(Duration::from_millis(1));
+        assert!(service.is_expired().unwrap());
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(service.staleness(), Duration::from_millis(500));
+
+        service.refresh(service.instance_infos.clone());
+        assert!(!service.is_expired().unwrap());
+        service.expire();
+        assert!(service.is_expired().unwrap());
+
+        service.expire_with_staleness(Duration::from_secs(60));
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(service.staleness(), Duration::from_millis(60_500));
+        service.refresh(service.instance_infos.clone());
+        assert_eq!(service.staleness(), Duration::ZERO);
     }
 
     #[test]
     fn test_get_next_instance() {
-        let instance_info1 = InstanceInfo {
-            instance_id: "test1".to_string(),
-            ip_addr: "0.0.0.0".to_string(),
-            port: 8888
-        };
-        let instance_info2 = InstanceInfo {
-            instance_id: "test2".to_string(),
-            ip_addr: "0.0.0.0".to_string(),
-            port: 8888
-        };
-        let mut service = Service::new(vec![instance_info1.clone(), instance_info2.clone()]);
+        let instance_info1 = create_instance("test1");
+        let instance_info2 = create_instance("test2");
+        let service = Service::new(vec![instance_info1.clone(), instance_info2.clone()]);
         
         let ret_instance = service.get_next_instance().unwrap();
         assert!(ret_instance == instance_info1 || ret_instance == instance_info2);
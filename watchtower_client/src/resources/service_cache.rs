@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak
+    },
+    time::Duration
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures_util::future::{join_all, BoxFuture, FutureExt, Shared};
+use log::{info, warn};
+use tokio::time::Instant;
+use crate::{
+    resources::{
+        load_balancer::{LoadBalancer, LoadBalancerFactory},
+        DiskCache, HttpClient, InstanceInfo, InstanceStatus, OutlierDetector, Service
+    },
+    types::{Result, Error}
+};
+
+type SharedFetch = Shared<BoxFuture<'static, Result<()>>>;
+
+/// The services fetched by a client, shared with the background tasks that keep them fresh
+///
+/// Services are stored in a sharded concurrent map, whose locks are never held across an await, so that fetching a
+/// service does not hold up lookups of the others.
+pub struct ServiceCache {
+    http_client: Arc<HttpClient>,
+    services: DashMap<String, Service>,
+    /// The fetch in flight for each service, tagged with an id so that only the callers of that fetch remove it
+    ///
+    /// A fetch only holds a weak reference to the cache, so that a fetch left behind by its callers never keeps the
+    /// cache alive.
+    in_flight_fetches: std::sync::Mutex<HashMap<String, (u64, SharedFetch)>>,
+    next_fetch_id: AtomicU64,
+    load_balancers: std::sync::Mutex<HashMap<String, Arc<dyn LoadBalancer>>>,
+    load_balancer_factory: LoadBalancerFactory,
+    outlier_detector: Arc<OutlierDetector>,
+    disk_cache: Option<Arc<DiskCache>>,
+    ttl: Duration
+}
+
+impl ServiceCache {
+    /// Creates a cache whose services expire `ttl` after being fetched, seeded with the services of `disk_cache`
+    ///
+    /// Seeded services are expired since `ttl` after they were fetched, so they are fetched again before being served
+    /// unless the registry is unreachable. Services that have been expired for longer than `max_staleness` are skipped.
+    pub fn new(
+        http_client: Arc<HttpClient>,
+        ttl: Duration,
+        max_staleness: Duration,
+        load_balancer_factory: LoadBalancerFactory,
+        outlier_detector: Arc<OutlierDetector>,
+        disk_cache: Option<DiskCache>
+    ) -> Self {
+        let services = DashMap::new();
+        let mut load_balancers = HashMap::new();
+        for (service_id, seeded_service) in disk_cache.iter().flat_map(|disk_cache| disk_cache.services()) {
+            let staleness = seeded_service.age.saturating_sub(ttl);
+            if staleness > max_staleness {
+                info!("Skipping {} from the cache file, fetched {:?} ago", service_id, seeded_service.age);
+                continue;
+            }
+            let load_balancer = load_balancer_factory();
+            let mut service = Service::with_load_balancer(seeded_service.instance_infos, ttl, load_balancer.clone());
+            service.expire_with_staleness(staleness);
+            services.insert(service_id.clone(), service);
+            load_balancers.insert(service_id, load_balancer);
+        }
+        ServiceCache {
+            http_client,
+            services,
+            in_flight_fetches: std::sync::Mutex::new(HashMap::new()),
+            next_fetch_id: AtomicU64::new(0),
+            load_balancers: std::sync::Mutex::new(load_balancers),
+            load_balancer_factory,
+            outlier_detector,
+            disk_cache: disk_cache.map(Arc::new),
+            ttl
+        }
+    }
+
+    /// Returns the cached services
+    ///
+    /// References into the map lock a shard of it, so they must not be held across an await.
+    pub fn services(&self) -> &DashMap<String, Service> {
+        &self.services
+    }
+
+    /// Returns the load balancer of a service, creating the default one if none is set
+    pub fn load_balancer(&self, service_id: &str) -> Arc<dyn LoadBalancer> {
+        self.load_balancers.lock().unwrap()
+            .entry(service_id.to_string())
+            .or_insert_with(|| (self.load_balancer_factory)())
+            .clone()
+    }
+
+    /// Select instances of a service with `load_balancer` from now on
+    pub fn set_load_balancer(&self, service_id: &str, load_balancer: Arc<dyn LoadBalancer>) {
+        self.load_balancers.lock().unwrap().insert(service_id.to_string(), load_balancer.clone());
+        if let Some(mut service) = self.services.get_mut(service_id) {
+            service.load_balancer = load_balancer;
+        }
+    }
+
+    /// Fetches a service and caches it, sharing the fetch with every concurrent caller for the same service
+    pub async fn fetch(self: &Arc<Self>, service_id: &str) -> Result<()> {
+        let (fetch_id, fetch) = {
+            let mut in_flight_fetches = self.in_flight_fetches.lock().unwrap();
+            in_flight_fetches.entry(service_id.to_string()).or_insert_with(|| {
+                let fetch = fetch_service(self.http_client.clone(), Arc::downgrade(self), service_id.to_string())
+                    .boxed()
+                    .shared();
+                (self.next_fetch_id.fetch_add(1, Ordering::Relaxed), fetch)
+            }).clone()
+        };
+        let result = fetch.await;
+
+        let mut in_flight_fetches = self.in_flight_fetches.lock().unwrap();
+        if in_flight_fetches.get(service_id).is_some_and(|(id, _)| *id == fetch_id) {
+            in_flight_fetches.remove(service_id);
+        }
+        result
+    }
+
+    /// Caches the freshly fetched instances of a service
+    fn store(&self, service_id: &str, instance_infos: Vec<InstanceInfo>) {
+        self.outlier_detector.retain(service_id, &instance_infos);
+        match self.services.entry(service_id.to_string()) {
+            Entry::Occupied(mut entry) => entry.get_mut().refresh(instance_infos),
+            Entry::Vacant(entry) => {
+                entry.insert(Service::with_load_balancer(instance_infos, self.ttl, self.load_balancer(service_id)));
+            }
+        }
+    }
+
+    /// Removes a service that no longer exists, from the cache file as well
+    pub async fn remove(&self, service_id: &str) {
+        self.services.remove(service_id);
+        if let Some(disk_cache) = &self.disk_cache {
+            if let Err(err) = disk_cache.remove(service_id).await {
+                warn!("Unable to remove {} from the cache file: {:?}", service_id, err);
+            }
+        }
+    }
+
+    /// Evicts the services that were not used for `idle_timeout`
+    ///
+    /// Evicted services are kept in the cache file, which only seeds the cache on startup.
+    fn evict_idle(&self, idle_timeout: Duration) {
+        self.services.retain(|service_id, service| {
+            let is_idle = service.idle_time() >= idle_timeout;
+            if is_idle {
+                info!("Evicting idle service {}", service_id);
+            }
+            !is_idle
+        });
+    }
+
+    /// Returns the services that expire within `duration`, except stale ones which are refreshed separately
+    fn get_expiring_within(&self, duration: Duration) -> Vec<String> {
+        self.services.iter()
+            .filter(|service| !service.is_stale() && service.expires_in() <= duration)
+            .map(|service| service.key().clone())
+            .collect()
+    }
+}
+
+/// Fetches a service and stores it in the cache, unless the cache was dropped in the meantime
+///
+/// The cache is only upgraded once the instances are fetched, and released before the cache file is written.
+async fn fetch_service(http_client: Arc<HttpClient>, service_cache: Weak<ServiceCache>, service_id: String) -> Result<()> {
+    let instance_infos = fetch_available_instances(&http_client, &service_id).await?;
+    let disk_cache = match service_cache.upgrade() {
+        Some(service_cache) => {
+            service_cache.store(&service_id, instance_infos.clone());
+            service_cache.disk_cache.clone()
+        },
+        None => return Ok(())
+    };
+    if let Some(disk_cache) = disk_cache {
+        if let Err(err) = disk_cache.store(&service_id, &instance_infos).await {
+            warn!("Unable to write {} to the cache file: {:?}", service_id, err);
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the instances of a service that are up
+async fn fetch_available_instances(http_client: &HttpClient, service_id: &str) -> Result<Vec<InstanceInfo>> {
+    let mut instance_infos: Vec<InstanceInfo> = http_client.get_all_instances(service_id).await?;
+    instance_infos.retain(|instance_info| instance_info.status == InstanceStatus::Up);
+    if instance_infos.is_empty() {
+        return Err(Error::NoAvailableInstance);
+    }
+    Ok(instance_infos)
+}
+
+/// Every `refresh_interval`, evicts idle services and fetches the services that would expire before the next tick, so
+/// that callers are rarely the ones to fetch them
+///
+/// Stops once the cache is dropped.
+pub async fn run_refresher(service_cache: Weak<ServiceCache>, refresh_interval: Duration, idle_timeout: Duration) {
+    let mut ticks = tokio::time::interval_at(Instant::now() + refresh_interval, refresh_interval);
+    loop {
+        ticks.tick().await;
+        let service_cache = match service_cache.upgrade() {
+            Some(service_cache) => service_cache,
+            None => return
+        };
+        service_cache.evict_idle(idle_timeout);
+        let service_ids = service_cache.get_expiring_within(refresh_interval);
+        let results = join_all(service_ids.iter().map(|service_id| service_cache.fetch(service_id))).await;
+        for (service_id, result) in service_ids.iter().zip(results) {
+            if let Err(err) = result {
+                warn!("Unable to refresh {}: {:?}", service_id, err);
+            }
+        }
+    }
+}
+
+/// Fetches a stale service until the registry is reachable again, backing off between attempts as set by the retry
+/// policy of `http_client`
+///
+/// Stops once the service is no longer cached, such as after being evicted as idle, and drops it once it has been stale
+/// for longer than `max_staleness`, so that it is fetched again on its next use.
+pub async fn refresh_stale_service(http_client: Arc<HttpClient>, service_cache: Weak<ServiceCache>, service_id: String, max_staleness: Duration) {
+    let mut retry = 1;
+    loop {
+        tokio::time::delay_for(http_client.retry_policy().backoff(retry)).await;
+        let service_cache = match service_cache.upgrade() {
+            Some(service_cache) => service_cache,
+            None => return
+        };
+        let staleness = service_cache.services().get(&service_id).map(|service| service.staleness());
+        match staleness {
+            None => return,
+            Some(staleness) if staleness > max_staleness => {
+                warn!("Dropping {}, which has been stale for {:?}", service_id, staleness);
+                service_cache.services().remove(&service_id);
+                return;
+            },
+            Some(_) => ()
+        }
+        match service_cache.fetch(&service_id).await {
+            Ok(()) => {
+                info!("The registry is reachable again, refreshed {}", service_id);
+                return;
+            },
+            Err(Error::MaxRetryReached) => retry += 1,
+            Err(err) => {
+                info!("Dropping stale service {}: {:?}", service_id, err);
+                service_cache.remove(&service_id).await;
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::load_balancer::WeightedRoundRobinLoadBalancer;
+
+    #[actix_rt::test]
+    async fn test_abandoned_fetch_does_not_keep_cache_alive() {
+        // The listener accepts connections but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let http_client = Arc::new(HttpClient::new(vec![url], "admin".to_string(), "password".to_string()));
+        let service_cache = Arc::new(ServiceCache::new(
+            http_client,
+            Duration::from_secs(30),
+            Duration::from_secs(300),
+            Arc::new(|| Arc::new(WeightedRoundRobinLoadBalancer::new())),
+            Arc::new(OutlierDetector::default()),
+            None
+        ));
+
+        assert!(tokio::time::timeout(Duration::from_millis(50), service_cache.fetch("test")).await.is_err());
+        assert_eq!(service_cache.in_flight_fetches.lock().unwrap().len(), 1);
+        let weak_service_cache = Arc::downgrade(&service_cache);
+        drop(service_cache);
+        assert!(weak_service_cache.upgrade().is_none());
+    }
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+use crate::{
+    resources::{InstanceInfo, InstanceStatus, DEFAULT_WEIGHT},
+    WatchtowerClient, WatchtowerClientBuilder
+};
+
+/// Creates an HTTP client for the mock server
+///
+/// The mock server serves one connection at a time, so a pooled idle connection would block it.
+pub fn create_mock_http_client() -> reqwest::Client {
+    reqwest::Client::builder().pool_max_idle_per_host(0).build().unwrap()
+}
+
+/// Creates the builder of a client of the mock server
+pub fn create_mock_builder() -> WatchtowerClientBuilder {
+    WatchtowerClient::builder(vec![mockito::SERVER_URL.to_string()], "admin", "password")
+        .http_client(create_mock_http_client())
+}
+
+/// Creates an instance at `127.0.0.1:8080` that is up, with the default weight and no metadata
+pub fn create_instance(instance_id: &str) -> InstanceInfo {
+    InstanceInfo {
+        instance_id: instance_id.to_string(),
+        ip_addr: "127.0.0.1".to_string(),
+        port: 8080,
+        status: InstanceStatus::Up,
+        weight: DEFAULT_WEIGHT,
+        metadata: HashMap::new()
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    time::Duration
+};
+use futures_util::future::{poll_fn, FutureExt};
+use log::warn;
+use tokio::sync::mpsc::Sender;
+use tonic::transport::{Channel, Endpoint};
+use tower::discover::{Change, Discover};
+use crate::{
+    types::{InstanceInfo, Result, Error},
+    WatchtowerClient,
+    WatchtowerDiscover
+};
+
+/// The metadata key of the port that serves gRPC, for instances that serve it on another port than the registered one
+pub const GRPC_PORT_METADATA_KEY: &str = "grpc_port";
+
+const DEFAULT_POLL_INTERVAL_SEC: u64 = 5;
+const CHANGE_BUFFER_SIZE: usize = 16;
+const PROBE_TIMEOUT_SEC: u64 = 5;
+
+type ConfigureEndpoint = Arc<dyn Fn(Endpoint) -> Endpoint + Send + Sync>;
+
+/// Builds a tonic `Channel` that balances requests over the instances of a service
+///
+/// Instances are added to and removed from the channel as they come and go on the registry. Only instances that are up
+/// are added, on the port set by their `grpc_port` metadata, or on their registered port otherwise.
+pub struct WatchtowerChannel {
+    watchtower_client: Arc<WatchtowerClient>,
+    service_id: String,
+    poll_interval: Duration,
+    configure_endpoint: ConfigureEndpoint
+}
+
+impl WatchtowerChannel {
+    pub fn new(watchtower_client: Arc<WatchtowerClient>, service_id: &str) -> Self {
+        WatchtowerChannel {
+            watchtower_client,
+            service_id: service_id.to_string(),
+            poll_interval: Duration::from_secs(DEFAULT_POLL_INTERVAL_SEC),
+            configure_endpoint: Arc::new(|endpoint| endpoint)
+        }
+    }
+
+    /// Sets how often the instances of the service are looked up, 5 seconds by default
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Applies `configure` to the endpoint of every instance, for example to set a timeout or TLS
+    pub fn with_endpoint<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(Endpoint) -> Endpoint + Send + Sync + 'static
+    {
+        self.configure_endpoint = Arc::new(configure);
+        self
+    }
+
+    /// Creates the channel, and keeps its endpoints up to date in the background until it is dropped
+    ///
+    /// Returns `Error::NoRuntime` if there is no runtime to run the channel on.
+    pub fn build(self) -> Result<Channel> {
+        // tonic spawns the worker of the channel on the current runtime, and panics if there is none
+        tokio::runtime::Handle::try_current().map_err(|_| Error::NoRuntime)?;
+        let (channel, sender) = Channel::balance_channel(CHANGE_BUFFER_SIZE);
+
+        let configure_endpoint = self.configure_endpoint;
+        let discover = WatchtowerDiscover::new(self.watchtower_client.clone(), &self.service_id, move |instance_info| {
+            create_endpoint(instance_info).map(|endpoint| configure_endpoint(endpoint))
+        }).with_poll_interval(self.poll_interval);
+        self.watchtower_client.spawner.spawn(forward_changes(discover, sender, self.poll_interval).boxed())?;
+        Ok(channel)
+    }
+}
+
+/// Creates the endpoint of the gRPC server of an instance
+fn create_endpoint(instance_info: &InstanceInfo) -> Result<Endpoint> {
+    let mut instance_info = instance_info.clone();
+    if let Some(grpc_port) = instance_info.metadata.get(GRPC_PORT_METADATA_KEY) {
+        instance_info.port = grpc_port.parse().map_err(|_| Error::InvalidUrl)?;
+    }
+    Endpoint::from_shared(instance_info.url("http", "")).map_err(|_| Error::InvalidUrl)
+}
+
+/// Returns whether a connection can be made to `endpoint`
+async fn is_reachable(endpoint: &Endpoint) -> bool {
+    let connection = tokio::time::timeout(Duration::from_secs(PROBE_TIMEOUT_SEC), endpoint.connect());
+    matches!(connection.await, Ok(Ok(_)))
+}
+
+/// Forwards the changes of `discover` to a balanced channel, until the channel is dropped
+///
+/// tonic fails the whole channel when it cannot connect to an inserted endpoint, so instances are only inserted once a
+/// connection can be made to them. Unreachable instances are probed again every `poll_interval`.
+async fn forward_changes<F>(
+    mut discover: WatchtowerDiscover<F, Result<Endpoint>>,
+    mut sender: Sender<Change<String, Endpoint>>,
+    poll_interval: Duration
+)
+where
+    F: Fn(&InstanceInfo) -> Result<Endpoint>
+{
+    let mut unreachable_endpoints: HashMap<String, Endpoint> = HashMap::new();
+    loop {
+        // Reserving a slot fails once the channel is dropped
+        if poll_fn(|cx| sender.poll_ready(cx)).await.is_err() {
+            return;
+        }
+
+        let next_change = poll_fn(|cx| Pin::new(&mut discover).poll_discover(cx));
+        let change = match tokio::time::timeout(poll_interval, next_change).await {
+            Ok(Ok(change)) => change,
+            Ok(Err(_)) => return,
+            Err(_) => {
+                // A reserved slot is always ready, so it is released for the next reservation to see a dropped channel
+                sender.disarm();
+                for (instance_id, endpoint) in std::mem::take(&mut unreachable_endpoints) {
+                    if !is_reachable(&endpoint).await {
+                        unreachable_endpoints.insert(instance_id, endpoint);
+                    } else if sender.send(Change::Insert(instance_id, endpoint)).await.is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+        };
+
+        let change = match change {
+            Change::Insert(instance_id, Ok(endpoint)) => {
+                if !is_reachable(&endpoint).await {
+                    warn!("Unable to connect to instance {}, it is added once it is reachable", instance_id);
+                    unreachable_endpoints.insert(instance_id, endpoint);
+                    continue;
+                }
+                unreachable_endpoints.remove(&instance_id);
+                Change::Insert(instance_id, endpoint)
+            },
+            Change::Insert(instance_id, Err(err)) => {
+                warn!("Unable to create the endpoint of instance {}: {:?}", instance_id, err);
+                continue;
+            },
+            Change::Remove(instance_id) => {
+                unreachable_endpoints.remove(&instance_id);
+                Change::Remove(instance_id)
+            }
+        };
+        if sender.send(change).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use crate::test_utils::{create_instance, create_mock_builder};
+
+    fn create_instance_info(port: u16, metadata: &[(&str, &str)]) -> InstanceInfo {
+        InstanceInfo {
+            port,
+            metadata: metadata.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            ..create_instance(&port.to_string())
+        }
+    }
+
+    #[test]
+    fn test_create_endpoint() {
+        let endpoint = create_endpoint(&create_instance_info(8080, &[])).unwrap();
+        assert_eq!(endpoint.uri().port_u16(), Some(8080));
+        let endpoint = create_endpoint(&create_instance_info(8080, &[(GRPC_PORT_METADATA_KEY, "9090")])).unwrap();
+        assert_eq!(endpoint.uri().port_u16(), Some(9090));
+        assert!(create_endpoint(&create_instance_info(8080, &[(GRPC_PORT_METADATA_KEY, "grpc")])).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_forward_reachable_instances() {
+        // Connections to the listener are accepted by the OS without ever being read
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let grpc_port = listener.local_addr().unwrap().port().to_string();
+        let instances = format!(r#"[
+            {{"instance_id": "reachable", "ip_addr": "127.0.0.1", "port": 8080, "status": "UP", "metadata": {{"grpc_port": "{}"}}}},
+            {{"instance_id": "unreachable", "ip_addr": "127.0.0.1", "port": 1, "status": "UP"}}
+        ]"#, grpc_port);
+        let _instances = mockito::mock("GET", "/api/v1/services/test_forward_reachable_instances").with_status(200).with_body(&instances).create();
+
+        let watchtower_client = create_mock_builder().build().unwrap();
+        let poll_interval = Duration::from_millis(50);
+        let discover = WatchtowerDiscover::new(Arc::new(watchtower_client), "test_forward_reachable_instances", create_endpoint)
+            .with_poll_interval(poll_interval);
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(CHANGE_BUFFER_SIZE);
+        let forwarder = tokio::spawn(forward_changes(discover, sender, poll_interval));
+
+        match receiver.recv().await.unwrap() {
+            Change::Insert(instance_id, endpoint) => {
+                assert_eq!(instance_id, "reachable");
+                assert_eq!(endpoint.uri().port_u16().map(|port| port.to_string()), Some(grpc_port));
+            },
+            Change::Remove(instance_id) => panic!("Unexpected removal of {}", instance_id)
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(500), receiver.recv()).await.is_err());
+
+        drop(receiver);
+        tokio::time::timeout(Duration::from_secs(5), forwarder).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant}
+};
+use futures_util::future::{BoxFuture, FutureExt};
+use http::{Request, Response, Uri};
+use log::warn;
+use tower::{
+    discover::{Change, Discover},
+    layer::Layer,
+    Service
+};
+use crate::{
+    resources::load_balancer::RequestContext,
+    types::{InstanceInfo, Result, Error},
+    CallOutcome,
+    WatchtowerClient,
+    WATCHTOWER_SCHEME
+};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_POLL_INTERVAL_SEC: u64 = 5;
+
+/// Streams the instances of a service as they are added to and removed from the registry, for example to feed
+/// `tower-balance`
+///
+/// Instances are keyed by their instance id, and turned into services by `make_service`. An instance whose address
+/// changes is inserted again. While the registry is unreachable, the instances last seen are kept.
+pub struct WatchtowerDiscover<F, S> {
+    watchtower_client: Arc<WatchtowerClient>,
+    service_id: String,
+    make_service: F,
+    poll_interval: Duration,
+    ticks: Option<tokio::time::Interval>,
+    fetch: Option<BoxFuture<'static, Result<Vec<InstanceInfo>>>>,
+    instance_infos: HashMap<String, InstanceInfo>,
+    changes: VecDeque<Change<String, S>>
+}
+
+impl<F, S> WatchtowerDiscover<F, S>
+where
+    F: Fn(&InstanceInfo) -> S
+{
+    pub fn new(watchtower_client: Arc<WatchtowerClient>, service_id: &str, make_service: F) -> Self {
+        WatchtowerDiscover {
+            watchtower_client,
+            service_id: service_id.to_string(),
+            make_service,
+            poll_interval: Duration::from_secs(DEFAULT_POLL_INTERVAL_SEC),
+            ticks: None,
+            fetch: None,
+            instance_infos: HashMap::new(),
+            changes: VecDeque::new()
+        }
+    }
+
+    /// Sets how often the instances of the service are looked up, 5 seconds by default
+    ///
+    /// Lookups go through the cache of the `WatchtowerClient`, so changes are seen once the service is refreshed.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Queues the changes from the instances last seen to `instance_infos`
+    fn update(&mut self, instance_infos: Vec<InstanceInfo>) {
+        let mut removed_instance_infos = std::mem::take(&mut self.instance_infos);
+        for instance_info in instance_infos {
+            let known_instance_info = removed_instance_infos.remove(&instance_info.instance_id);
+            let is_changed = known_instance_info.is_none_or(|known_instance_info| {
+                known_instance_info.ip_addr != instance_info.ip_addr || known_instance_info.port != instance_info.port
+            });
+            if is_changed {
+                let service = (self.make_service)(&instance_info);
+                self.changes.push_back(Change::Insert(instance_info.instance_id.clone(), service));
+            }
+            self.instance_infos.insert(instance_info.instance_id.clone(), instance_info);
+        }
+        for instance_id in removed_instance_infos.into_keys() {
+            self.changes.push_back(Change::Remove(instance_id));
+        }
+    }
+}
+
+// No field is ever pinned, so the services and the closure do not need to be `Unpin`
+impl<F, S> Unpin for WatchtowerDiscover<F, S> {}
+
+impl<F, S> Discover for WatchtowerDiscover<F, S>
+where
+    F: Fn(&InstanceInfo) -> S
+{
+    type Key = String;
+    type Service = S;
+    type Error = Error;
+
+    fn poll_discover(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Change<String, S>>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(change) = this.changes.pop_front() {
+                return Poll::Ready(Ok(change));
+            }
+
+            if let Some(fetch) = this.fetch.as_mut() {
+                let result = match fetch.poll_unpin(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending
+                };
+                this.fetch = None;
+                match result {
+                    Ok(instance_infos) => this.update(instance_infos),
+                    Err(Error::NotFound) | Err(Error::NoAvailableInstance) => this.update(Vec::new()),
+                    Err(err) => warn!("Unable to look up the instances of {}: {:?}", this.service_id, err)
+                }
+                continue;
+            }
+
+            let poll_interval = this.poll_interval;
+            let ticks = this.ticks.get_or_insert_with(|| tokio::time::interval(poll_interval));
+            if ticks.poll_tick(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let watchtower_client = this.watchtower_client.clone();
+            let service_id = this.service_id.clone();
+            this.fetch = Some(async move { watchtower_client.get_instances(&service_id).await }.boxed());
+        }
+    }
+}
+
+/// Applies `WatchtowerService` to services
+#[derive(Clone)]
+pub struct WatchtowerLayer {
+    watchtower_client: Arc<WatchtowerClient>
+}
+
+impl WatchtowerLayer {
+    pub fn new(watchtower_client: Arc<WatchtowerClient>) -> Self {
+        WatchtowerLayer {
+            watchtower_client
+        }
+    }
+}
+
+impl<S> Layer<S> for WatchtowerLayer {
+    type Service = WatchtowerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WatchtowerService {
+            inner,
+            watchtower_client: self.watchtower_client.clone()
+        }
+    }
+}
+
+/// Rewrites the uri of requests to an instance of the service named by its host, such as `http://my-service/path`
+///
+/// `watchtower://` uris are rewritten to `http://`. The outcome of every request is reported for outlier detection,
+/// where errors and server errors are failures.
+#[derive(Clone)]
+pub struct WatchtowerService<S> {
+    inner: S,
+    watchtower_client: Arc<WatchtowerClient>
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for WatchtowerService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static
+{
+    type Response = Response<ResBody>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, std::result::Result<Response<ResBody>, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // The service polled ready is the one to call, so it is taken and replaced with a clone
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let watchtower_client = self.watchtower_client.clone();
+        async move {
+            let (mut parts, body) = request.into_parts();
+            let service_id = parts.uri.host().ok_or(Error::InvalidUrl)?.to_string();
+            let selected_instance = watchtower_client.select_instance(&service_id, &RequestContext::default()).await?;
+            parts.uri = resolve(&parts.uri, selected_instance.instance_info())?;
+
+            let start = Instant::now();
+            let result = inner.call(Request::from_parts(parts, body)).await;
+            let is_success = result.as_ref().is_ok_and(|response| !response.status().is_server_error());
+            selected_instance.report(if is_success {
+                CallOutcome::success(start.elapsed())
+            } else {
+                CallOutcome::failure(start.elapsed())
+            });
+            result.map_err(Into::into)
+        }.boxed()
+    }
+}
+
+/// Replaces the host of a logical uri with the address of `instance_info`
+fn resolve(uri: &Uri, instance_info: &InstanceInfo) -> Result<Uri> {
+    let scheme = match uri.scheme_str() {
+        Some(WATCHTOWER_SCHEME) | None => "http",
+        Some(scheme) => scheme
+    };
+    let path = uri.path_and_query().map_or("/", |path_and_query| path_and_query.as_str());
+    instance_info.url(scheme, path).parse().map_err(|_| Error::InvalidUrl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use futures_util::future::poll_fn;
+    use tower::{service_fn, ServiceExt};
+    use crate::test_utils::create_mock_builder;
+
+    const INSTANCES: &str = r#"[
+        {"instance_id": "a", "ip_addr": "127.0.0.1", "port": 8080, "status": "UP"},
+        {"instance_id": "b", "ip_addr": "127.0.0.1", "port": 8081, "status": "UP"}
+    ]"#;
+
+    fn create_watchtower_client() -> Arc<WatchtowerClient> {
+        let watchtower_client = create_mock_builder()
+            .cache_ttl(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        Arc::new(watchtower_client)
+    }
+
+    async fn next_change<D: Discover + Unpin>(discover: &mut D) -> Change<D::Key, D::Service> {
+        poll_fn(|cx| Pin::new(&mut *discover).poll_discover(cx)).await.ok().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_discover_instances() {
+        let path = "/api/v1/services/test_discover_instances";
+        let instances = mockito::mock("GET", path).with_status(200).with_body(INSTANCES).create();
+        let mut discover = WatchtowerDiscover::new(create_watchtower_client(), "test_discover_instances", |instance_info| instance_info.port)
+            .with_poll_interval(Duration::from_millis(20));
+
+        let mut ports = Vec::new();
+        for _ in 0..2 {
+            match next_change(&mut discover).await {
+                Change::Insert(_, port) => ports.push(port),
+                Change::Remove(instance_id) => panic!("Unexpected removal of {}", instance_id)
+            }
+        }
+        ports.sort_unstable();
+        assert_eq!(ports, vec![8080, 8081]);
+        drop(instances);
+
+        let _instances = mockito::mock("GET", path).with_status(200).with_body(&INSTANCES.replace("8081", "9091")).create();
+        match next_change(&mut discover).await {
+            Change::Insert(instance_id, port) => assert_eq!((instance_id.as_str(), port), ("b", 9091)),
+            Change::Remove(instance_id) => panic!("Unexpected removal of {}", instance_id)
+        }
+
+        let _not_found = mockito::mock("GET", path).with_status(404).create();
+        let mut removed_instance_ids = Vec::new();
+        for _ in 0..2 {
+            match next_change(&mut discover).await {
+                Change::Insert(instance_id, _) => panic!("Unexpected insertion of {}", instance_id),
+                Change::Remove(instance_id) => removed_instance_ids.push(instance_id)
+            }
+        }
+        removed_instance_ids.sort_unstable();
+        assert_eq!(removed_instance_ids, vec!["a", "b"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_rewrite_uri() {
+        let _instances = mockito::mock("GET", "/api/v1/services/test_rewrite_uri").with_status(200).with_body(INSTANCES).create();
+        let service = WatchtowerLayer::new(create_watchtower_client()).layer(service_fn(|request: Request<()>| async move {
+            Ok::<_, Infallible>(Response::new(request.uri().to_string()))
+        }));
+
+        let request = Request::get("watchtower://test_rewrite_uri/path?query=1").body(()).unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert!(response.body().starts_with("http://127.0.0.1:808"));
+        assert!(response.body().ends_with("/path?query=1"));
+
+        let request = Request::get("/path").body(()).unwrap();
+        assert!(service.oneshot(request).await.is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::error::WatchtowerError;
-pub use crate::resources::InstanceInfo;
+pub use crate::resources::{InstanceInfo, InstanceUpdate};
 
 pub type Error = WatchtowerError;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1 +1,4 @@
-pub mod time;
\ No newline at end of file
+pub mod time;
+pub mod signal;
+pub mod spawner;
+pub mod retry;
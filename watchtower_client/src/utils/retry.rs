@@ -0,0 +1,49 @@
+use std::{cmp::min, time::Duration};
+
+/// How requests to the registry, and renewals by the heartbeat, are retried.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of attempts made for a request, across registry nodes, before giving up
+    pub max_attempts: u16,
+    /// The delay before the first retry, doubled on every following one
+    pub initial_backoff: Duration,
+    /// The upper bound of the delay between retries
+    pub max_backoff: Duration
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retry number `retry`, starting from 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = min(retry.saturating_sub(1), 16);
+        min(self.initial_backoff * 2u32.pow(exponent), self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let retry_policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500)
+        };
+        assert_eq!(retry_policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry_policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(retry_policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(retry_policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(retry_policy.backoff(100), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,18 @@
+use futures_util::future::select;
+
+/// Waits until the process receives SIGTERM or SIGINT.
+#[cfg(unix)]
+pub async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    select(Box::pin(terminate.recv()), Box::pin(interrupt.recv())).await;
+    Ok(())
+}
+
+/// Waits until the process receives Ctrl-C.
+#[cfg(not(unix))]
+pub async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,39 @@
+use futures_util::future::BoxFuture;
+
+use crate::types::{Result, Error};
+
+/// Runs the background tasks of a `WatchtowerClient`, such as the heartbeat.
+pub trait Spawner: Send + Sync {
+    /// Spawns `task` to run to completion in the background
+    ///
+    /// Returns `Error::NoRuntime` if there is no runtime to run the task on.
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> Result<()>;
+}
+
+/// A `Spawner` that runs tasks on the current tokio runtime.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioSpawner;
+
+impl Spawner for TokioSpawner {
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> Result<()> {
+        let handle = tokio::runtime::Handle::try_current().map_err(|_| Error::NoRuntime)?;
+        handle.spawn(task);
+        Ok(())
+    }
+}
+
+/// A `Spawner` that runs tasks on the current actix system.
+#[cfg(feature = "actix")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ActixSpawner;
+
+#[cfg(feature = "actix")]
+impl Spawner for ActixSpawner {
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> Result<()> {
+        if !actix::System::is_set() {
+            return Err(Error::NoRuntime);
+        }
+        actix::spawn(task);
+        Ok(())
+    }
+}
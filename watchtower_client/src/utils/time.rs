@@ -1,8 +1,63 @@
-use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+use std::time::Instant;
+#[cfg(test)]
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration
+};
 
-pub fn get_time_since_epoch() -> Result<u64, SystemTimeError> {
-    match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(now) => Ok(now.as_secs() as u64),
-        Err(error) => Err(error)
+/// A source of monotonic time.
+pub(crate) trait Clock: Send + Sync {
+    /// Returns the number of milliseconds elapsed since an arbitrary, fixed origin.
+    fn now_millis(&self) -> u64;
+}
+
+/// A `Clock` backed by `Instant`, which is unaffected by changes to the system time.
+pub(crate) struct MonotonicClock {
+    origin: Instant
+}
+
+impl MonotonicClock {
+    pub fn new() -> MonotonicClock {
+        MonotonicClock {
+            origin: Instant::now()
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        MonotonicClock::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_millis(&self) -> u64 {
+        self.origin.elapsed().as_millis() as u64
+    }
+}
+
+/// A `Clock` that only moves when advanced.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct ManualClock {
+    millis: AtomicU64
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.millis.fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.millis.load(Ordering::SeqCst)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,78 @@
+/*
+ * Registers and resolves a service through the C API, against a local watchtower at WATCHTOWER_URL or
+ * http://localhost:8088 by default.
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "watchtower_client.h"
+
+#define CHECK_STATUS(call, expected)                                                                     \
+    do {                                                                                                 \
+        WatchtowerStatus status = (call);                                                                \
+        if (status != (expected)) {                                                                      \
+            fprintf(stderr, "%s:%d: %s returned '%s'\n", __FILE__, __LINE__, #call,                      \
+                    watchtower_status_message(status));                                                  \
+            exit(1);                                                                                     \
+        }                                                                                                \
+    } while (0)
+
+#define CHECK_STRING(actual, expected)                                                                   \
+    do {                                                                                                 \
+        if (strcmp((actual), (expected)) != 0) {                                                         \
+            fprintf(stderr, "%s:%d: expected '%s', got '%s'\n", __FILE__, __LINE__, (expected), (actual)); \
+            exit(1);                                                                                     \
+        }                                                                                                \
+    } while (0)
+
+static const char *SERVICE_ID = "test_c_client";
+
+static WatchtowerClientHandle *create_client(const char *watchtower_url) {
+    WatchtowerClientHandle *client = NULL;
+    CHECK_STATUS(watchtower_client_new(&watchtower_url, 1, "admin", "password", &client), WATCHTOWER_STATUS_OK);
+    return client;
+}
+
+int main(void) {
+    const char *watchtower_url = getenv("WATCHTOWER_URL");
+    if (watchtower_url == NULL) {
+        watchtower_url = "http://localhost:8088";
+    }
+    WatchtowerClientHandle *client = create_client(watchtower_url);
+
+    WatchtowerRegistrationHandle *registration = NULL;
+    CHECK_STATUS(watchtower_register(client, SERVICE_ID, "127.0.0.1", 9800, &registration), WATCHTOWER_STATUS_OK);
+    char *instance_id = NULL;
+    CHECK_STATUS(watchtower_registration_instance_id(registration, &instance_id), WATCHTOWER_STATUS_OK);
+    if (strlen(instance_id) == 0) {
+        fprintf(stderr, "The instance id is empty\n");
+        return 1;
+    }
+    watchtower_string_free(instance_id);
+
+    char *url = NULL;
+    CHECK_STATUS(watchtower_get_service_url(client, SERVICE_ID, &url), WATCHTOWER_STATUS_OK);
+    CHECK_STRING(url, "127.0.0.1:9800");
+    watchtower_string_free(url);
+    CHECK_STATUS(watchtower_build_service_url(client, SERVICE_ID, "http", "/health", &url), WATCHTOWER_STATUS_OK);
+    CHECK_STRING(url, "http://127.0.0.1:9800/health");
+    watchtower_string_free(url);
+
+    /* Releasing the registration cancels the lease, which a client without a cache of the service sees */
+    watchtower_registration_free(registration);
+    WatchtowerClientHandle *other_client = create_client(watchtower_url);
+    url = NULL;
+    CHECK_STATUS(watchtower_get_service_url(other_client, SERVICE_ID, &url), WATCHTOWER_STATUS_NOT_FOUND);
+    if (url != NULL) {
+        fprintf(stderr, "The url was written on failure\n");
+        return 1;
+    }
+
+    CHECK_STATUS(watchtower_get_service_url(NULL, SERVICE_ID, &url), WATCHTOWER_STATUS_INVALID_ARGUMENT);
+
+    watchtower_client_free(other_client);
+    watchtower_client_free(client);
+    printf("test_client passed\n");
+    return 0;
+}
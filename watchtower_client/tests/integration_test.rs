@@ -1,4 +1,8 @@
-use watchtower_client::{WatchtowerClient, Error};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use watchtower_client::{
+    load_balancer::{ConsistentHashLoadBalancer, RequestContext},
+    blocking, WatchtowerClient, Error, HttpClient, InstanceStatus, RegistrationOptions, CallOutcome, CircuitState
+};
 
 const WATCHTOWER_URL: &str = "http://localhost:8088";
 
@@ -16,17 +20,25 @@ fn get_watchtower_urls() -> Vec<String> {
 
 #[actix_rt::test]
 async fn test_register_and_get_service() {
-    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let watchtower_client = WatchtowerClient::builder(get_watchtower_urls(), USERNAME, PASSWORD)
+        .heartbeat_interval(Duration::from_millis(100))
+        .build()
+        .unwrap();
 
     let url = "127.0.0.1";
     let port = 1234;
     let service_id = "test_register_and_get_service";
-    watchtower_client.register(service_id, url, port).await.unwrap();
+    let _registration = watchtower_client.register(service_id, url, port).await.unwrap();
 
     let service_url = watchtower_client.get_service_url(service_id).await.unwrap();
     assert_eq!(service_url, format!("{}:{}", url, port));
-    let sleep_time = std::time::Duration::from_millis(40 * 1000);
-    std::thread::sleep(sleep_time);
+
+    // The heartbeat keeps renewing the lease, which is never older than a few heartbeats
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+    let http_client = HttpClient::new(get_watchtower_urls(), USERNAME.to_string(), PASSWORD.to_string());
+    let leases = http_client.get_all_leases(service_id).await.unwrap();
+    assert_eq!(leases.len(), 1);
+    assert!(leases[0].lease_age_ms < 300);
     let service_url = watchtower_client.get_service_url(service_id).await.unwrap();
     assert_eq!(service_url, format!("{}:{}", url, port));
     watchtower_client.cancel().await.unwrap();
@@ -44,23 +56,51 @@ async fn test_unauthorized() {
     let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, "whatever");
     let maybe_service = watchtower_client.get_service_url("foo").await;
     assert_eq!(maybe_service, Err(Error::Unauthorized));
-    assert_eq!(watchtower_client.register("bar", "127.0.0.1", 1234).await, Err(Error::Unauthorized));
+    assert_eq!(watchtower_client.register("bar", "127.0.0.1", 1234).await.err(), Some(Error::Unauthorized));
 }
 
 #[actix_rt::test]
-async fn test_register_twice() {
+async fn test_register_multiple_instances() {
     let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
 
     let url = "127.0.0.1";
-    let port = 1234;
-    let service_id = "test_register_twice";
-    watchtower_client.register(service_id, url, port).await.unwrap();
+    let http_service_id = "test_register_multiple_instances_http";
+    let admin_service_id = "test_register_multiple_instances_admin";
+    let http_registration = watchtower_client.register(http_service_id, url, 1234).await.unwrap();
+    let admin_registration = watchtower_client.register(admin_service_id, url, 1235).await.unwrap();
+    assert_ne!(http_registration.instance_id(), admin_registration.instance_id());
+    assert_eq!(watchtower_client.get_registrations().len(), 2);
 
-    assert_eq!(watchtower_client.register("bar", "127.0.0.1", 1234).await, Err(Error::InstanceAlreadyRegistered));
+    assert_eq!(watchtower_client.get_service_url(http_service_id).await.unwrap(), format!("{}:{}", url, 1234));
+    assert_eq!(watchtower_client.get_service_url(admin_service_id).await.unwrap(), format!("{}:{}", url, 1235));
+
+    http_registration.ping().await.unwrap();
+    http_registration.cancel().await.unwrap();
+    assert_eq!(http_registration.ping().await, Err(Error::InvalidPing));
+    assert_eq!(watchtower_client.get_registrations().len(), 1);
 
     watchtower_client.cancel().await.unwrap();
-    watchtower_client.register(service_id, url, port).await.unwrap();
-    watchtower_client.cancel().await.unwrap();
+    assert_eq!(admin_registration.cancel().await, Err(Error::NotFound));
+    assert_eq!(watchtower_client.cancel().await, Err(Error::NotFound));
+}
+
+#[actix_rt::test]
+async fn test_set_status() {
+    let url = "127.0.0.1";
+    let port = 3456;
+    let service_id = "test_set_status";
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let registration = watchtower_client.register(service_id, url, port).await.unwrap();
+
+    registration.set_status(InstanceStatus::OutOfService).await.unwrap();
+    assert_eq!(registration.instance_info().unwrap().status, InstanceStatus::OutOfService);
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    assert_eq!(other_client.get_service_url(service_id).await, Err(Error::NoAvailableInstance));
+
+    registration.set_status(InstanceStatus::Up).await.unwrap();
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    assert_eq!(other_client.get_service_url(service_id).await.unwrap(), format!("{}:{}", url, port));
+    registration.cancel().await.unwrap();
 }
 
 #[actix_rt::test]
@@ -70,8 +110,178 @@ async fn test_register_then_cancel() {
     let url = "127.0.0.1";
     let port = 2345;
     let service_id = "test_register_then_cancel";
-    watchtower_client.register(service_id, url, port).await.unwrap();
+    let _registration = watchtower_client.register(service_id, url, port).await.unwrap();
     watchtower_client.cancel().await.unwrap();
 
     assert_eq!(watchtower_client.get_service_url("test_register_then_cancel").await, Err(Error::NotFound));
 }
+
+#[actix_rt::test]
+async fn test_drop_registration() {
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+
+    let service_id = "test_drop_registration";
+    let registration = watchtower_client.register(service_id, "127.0.0.1", 3456).await.unwrap();
+    drop(registration);
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+
+    assert!(watchtower_client.get_registrations().is_empty());
+    assert_eq!(other_client.get_service_url(service_id).await, Err(Error::NotFound));
+}
+
+#[actix_rt::test]
+async fn test_detach_then_deregister_all() {
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+
+    let url = "127.0.0.1";
+    let port = 4567;
+    let service_id = "test_detach_then_deregister_all";
+    watchtower_client.register(service_id, url, port).await.unwrap().detach();
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+    assert_eq!(other_client.get_service_url(service_id).await.unwrap(), format!("{}:{}", url, port));
+
+    watchtower_client.deregister_all(Duration::from_secs(5)).await.unwrap();
+    assert!(watchtower_client.get_registrations().is_empty());
+    let fresh_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    assert_eq!(fresh_client.get_service_url(service_id).await, Err(Error::NotFound));
+}
+
+#[tokio::test]
+async fn test_register_from_tokio_runtime() {
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+
+    let url = "127.0.0.1";
+    let port = 5678;
+    let service_id = "test_register_from_tokio_runtime";
+    let registration = watchtower_client.register(service_id, url, port).await.unwrap();
+
+    assert_eq!(watchtower_client.get_service_url(service_id).await.unwrap(), format!("{}:{}", url, port));
+    registration.cancel().await.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_consistent_hash_load_balancer() {
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    other_client.set_load_balancer("test_consistent_hash", Arc::new(ConsistentHashLoadBalancer::new())).await;
+
+    let service_id = "test_consistent_hash";
+    let mut registrations = Vec::new();
+    for port in 6000..6005 {
+        registrations.push(watchtower_client.register(service_id, "127.0.0.1", port).await.unwrap());
+    }
+
+    let context = RequestContext::with_hash_key("user-42");
+    let service_url = other_client.get_service_url_with_context(service_id, &context).await.unwrap();
+    for _ in 0..10 {
+        assert_eq!(other_client.get_service_url_with_context(service_id, &context).await.unwrap(), service_url);
+    }
+    let selected_instance = other_client.select_instance(service_id, &context).await.unwrap();
+    assert_eq!(selected_instance.url(), service_url);
+
+    watchtower_client.cancel().await.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_weighted_instances() {
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+
+    let service_id = "test_weighted_instances";
+    let stable_registration = watchtower_client.register(service_id, "127.0.0.1", 7000).await.unwrap();
+    let canary_registration = watchtower_client
+        .register_with_options(service_id, "127.0.0.1", 7001, RegistrationOptions { weight: 0, ..RegistrationOptions::default() })
+        .await
+        .unwrap();
+
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    for _ in 0..5 {
+        assert_eq!(other_client.get_service_url(service_id).await.unwrap(), "127.0.0.1:7000");
+    }
+
+    stable_registration.set_weight(0).await.unwrap();
+    canary_registration.set_weight(3).await.unwrap();
+    assert_eq!(canary_registration.instance_info().unwrap().weight, 3);
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    for _ in 0..5 {
+        assert_eq!(other_client.get_service_url(service_id).await.unwrap(), "127.0.0.1:7001");
+    }
+
+    watchtower_client.cancel().await.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_failing_instance_is_ejected() {
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+
+    let service_id = "test_failing_instance_is_ejected";
+    let failing_registration = watchtower_client.register(service_id, "127.0.0.1", 7100).await.unwrap();
+    let _healthy_registration = watchtower_client.register(service_id, "127.0.0.1", 7101).await.unwrap();
+
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    for _ in 0..5 {
+        other_client.report(service_id, failing_registration.instance_id(), CallOutcome::failure(Duration::from_millis(10)));
+    }
+    for _ in 0..5 {
+        assert_eq!(other_client.get_service_url(service_id).await.unwrap(), "127.0.0.1:7101");
+    }
+
+    let health = other_client.get_instance_health();
+    assert_eq!(health.len(), 1);
+    assert_eq!(health[0].instance_id, failing_registration.instance_id());
+    assert_eq!(health[0].state, CircuitState::Open);
+
+    watchtower_client.cancel().await.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_get_instances() {
+    let service_id = "test_get_instances";
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let _first_registration = watchtower_client.register(service_id, "127.0.0.1", 9401).await.unwrap();
+    let _second_registration = watchtower_client.register(service_id, "127.0.0.1", 9402).await.unwrap();
+
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let mut ports: Vec<u16> = other_client.get_instances(service_id).await.unwrap()
+        .iter()
+        .map(|instance_info| instance_info.port)
+        .collect();
+    ports.sort_unstable();
+    assert_eq!(ports, vec![9401, 9402]);
+
+    let instance_info = other_client.choose_instance(service_id).await.unwrap();
+    assert!(ports.contains(&instance_info.port));
+    let service_url = other_client.build_service_url(service_id, "http", "/health").await.unwrap();
+    assert!(service_url.starts_with("http://127.0.0.1:94"));
+    assert!(service_url.ends_with("/health"));
+    assert_eq!(other_client.get_instances("test_get_instances_non_existent").await, Err(Error::NotFound));
+}
+
+#[actix_rt::test]
+async fn test_instance_metadata() {
+    let service_id = "test_instance_metadata";
+    let watchtower_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let mut metadata = HashMap::new();
+    metadata.insert("grpc_port".to_string(), "9501".to_string());
+    let options = RegistrationOptions { metadata: metadata.clone(), ..RegistrationOptions::default() };
+    let _registration = watchtower_client.register_with_options(service_id, "127.0.0.1", 9500, options).await.unwrap();
+
+    let other_client = WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let instance_info = other_client.choose_instance(service_id).await.unwrap();
+    assert_eq!(instance_info.metadata, metadata);
+}
+
+#[test]
+fn test_blocking_client() {
+    let service_id = "test_blocking_client";
+    let watchtower_client = blocking::WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    let registration = watchtower_client.register(service_id, "127.0.0.1", 9600).unwrap();
+
+    let other_client = blocking::WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    assert_eq!(other_client.get_service_url(service_id).unwrap(), "127.0.0.1:9600");
+
+    drop(registration);
+    let other_client = blocking::WatchtowerClient::new(get_watchtower_urls(), USERNAME, PASSWORD);
+    assert!(other_client.get_instances(service_id).is_err());
+}
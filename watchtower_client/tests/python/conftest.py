@@ -0,0 +1,18 @@
+import os
+
+import pytest
+
+from watchtower_client import PyWatchtowerClientBuilder
+
+USERNAME = "admin"
+PASSWORD = "password"
+
+
+@pytest.fixture
+def watchtower_urls():
+    return os.environ.get("WATCHTOWER_URLS", "http://localhost:8088").split(",")
+
+
+@pytest.fixture
+def builder(watchtower_urls):
+    return PyWatchtowerClientBuilder(watchtower_urls, USERNAME, PASSWORD).cache_ttl(0.1)
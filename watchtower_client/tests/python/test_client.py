@@ -0,0 +1,68 @@
+import asyncio
+
+import pytest
+
+from watchtower_client import (
+    NotFoundError,
+    PyAsyncWatchtowerClient,
+    PyWatchtowerClient,
+    UnauthorizedError,
+    WatchtowerError,
+)
+
+
+def test_register_and_get_instances(builder):
+    watchtower_client = builder.build()
+    service_id = "test_python_register_and_get_instances"
+    instance_id = watchtower_client.register(
+        service_id, "127.0.0.1", 9700, weight=2, metadata={"grpc_port": "9701"}
+    )
+    try:
+        assert [instance.instance_id for instance in watchtower_client.get_registrations()] == [instance_id]
+        instances = watchtower_client.get_instances(service_id)
+        assert len(instances) == 1
+        assert instances[0].instance_id == instance_id
+        assert instances[0].status == "UP"
+        assert instances[0].weight == 2
+        assert instances[0].metadata == {"grpc_port": "9701"}
+        assert watchtower_client.get_service_url(service_id) == "127.0.0.1:9700"
+        assert watchtower_client.build_service_url(service_id, path="/health") == "http://127.0.0.1:9700/health"
+        watchtower_client.ping()
+    finally:
+        watchtower_client.cancel()
+
+
+def test_errors_are_subclasses(builder):
+    watchtower_client = builder.build()
+    with pytest.raises(NotFoundError):
+        watchtower_client.get_instances("test_python_non_existent_service")
+    assert issubclass(NotFoundError, WatchtowerError)
+
+
+def test_invalid_durations(watchtower_urls):
+    for value in (-1.0, float("nan"), float("inf")):
+        with pytest.raises(ValueError):
+            PyWatchtowerClient(watchtower_urls, "admin", "password", request_timeout=value)
+
+
+def test_unauthorized(watchtower_urls):
+    watchtower_client = PyWatchtowerClient(watchtower_urls, "admin", "wrong_password", max_attempts=1)
+    with pytest.raises(UnauthorizedError):
+        watchtower_client.get_instances("test_python_unauthorized")
+
+
+def test_async_register(watchtower_urls):
+    service_id = "test_python_async_register"
+
+    async def run():
+        watchtower_client = PyAsyncWatchtowerClient(watchtower_urls, "admin", "password", cache_ttl=0.1)
+        async with watchtower_client.register(service_id, "127.0.0.1", 9710, metadata={"zone": "a"}) as instance_id:
+            instances = await watchtower_client.get_instances(service_id)
+            assert [instance.instance_id for instance in instances] == [instance_id]
+            assert instances[0].metadata == {"zone": "a"}
+        assert watchtower_client.get_registrations() == []
+        await asyncio.sleep(0.2)
+        with pytest.raises(NotFoundError):
+            await watchtower_client.get_instances(service_id)
+
+    asyncio.run(run())
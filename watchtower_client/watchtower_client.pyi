@@ -0,0 +1,159 @@
+from types import TracebackType
+from typing import Awaitable, Dict, Generator, List, Optional, Type
+
+class WatchtowerError(Exception): ...
+class InternalError(WatchtowerError): ...
+class NotFoundError(WatchtowerError): ...
+class UnauthorizedError(WatchtowerError): ...
+class InstanceAlreadyRegisteredError(WatchtowerError): ...
+class MaxRetryReachedError(WatchtowerError): ...
+class InvalidPingError(WatchtowerError): ...
+class NoAvailableInstanceError(WatchtowerError): ...
+class RequestTimeoutError(WatchtowerError): ...
+class NoRuntimeError(WatchtowerError): ...
+class InvalidConfigError(WatchtowerError): ...
+class InvalidUrlError(WatchtowerError): ...
+
+class PyInstanceInfo:
+    """An instance of a service, as returned by `get_instances` and `choose_instance`"""
+
+    @property
+    def instance_id(self) -> str: ...
+    @property
+    def ip_addr(self) -> str: ...
+    @property
+    def port(self) -> int: ...
+    @property
+    def status(self) -> str:
+        """The status as sent by the registry, such as `UP`"""
+    @property
+    def weight(self) -> int: ...
+    @property
+    def metadata(self) -> Dict[str, str]: ...
+    def url(self, scheme: str = "http", path: str = "") -> str:
+        """Builds the url of `path` on the instance with `scheme`, such as `http://127.0.0.1:8080/health`"""
+
+class PyWatchtowerClient:
+    """Durations are given in seconds, and unset options keep the defaults of `WatchtowerClientBuilder`"""
+
+    def __init__(
+        self,
+        watchtower_urls: List[str],
+        username: str,
+        password: str,
+        *,
+        connect_timeout: Optional[float] = None,
+        request_timeout: Optional[float] = None,
+        max_attempts: Optional[int] = None,
+        initial_backoff: Optional[float] = None,
+        max_backoff: Optional[float] = None,
+        heartbeat_interval: Optional[float] = None,
+        cache_ttl: Optional[float] = None,
+        refresh_interval: Optional[float] = None,
+        idle_timeout: Optional[float] = None,
+        max_staleness: Optional[float] = None,
+        cache_file: Optional[str] = None,
+        user_agent: Optional[str] = None,
+    ) -> None: ...
+    def register(
+        self,
+        service_id: str,
+        ip_addr: str,
+        port: int,
+        *,
+        weight: Optional[int] = None,
+        metadata: Optional[Dict[str, str]] = None,
+    ) -> str:
+        """Registers an instance of a service, whose lease is renewed in the background until `cancel` is called
+
+        Returns the instance id.
+        """
+    def get_registrations(self) -> List[PyInstanceInfo]:
+        """Returns the instances registered through this client"""
+    def ping(self) -> None: ...
+    def cancel(self) -> None: ...
+    def get_service_url(self, service_id: str) -> str: ...
+    def get_instances(self, service_id: str) -> List[PyInstanceInfo]: ...
+    def choose_instance(self, service_id: str) -> PyInstanceInfo: ...
+    def build_service_url(self, service_id: str, scheme: str = "http", path: str = "") -> str: ...
+
+class PyWatchtowerClientBuilder:
+    """Configures a `PyWatchtowerClient` or a `PyAsyncWatchtowerClient` one option at a time
+
+    Every setter returns the builder, so calls can be chained.
+    """
+
+    def __init__(self, watchtower_urls: List[str], username: str, password: str) -> None: ...
+    def connect_timeout(self, timeout: float) -> PyWatchtowerClientBuilder: ...
+    def request_timeout(self, timeout: float) -> PyWatchtowerClientBuilder: ...
+    def max_attempts(self, max_attempts: int) -> PyWatchtowerClientBuilder: ...
+    def backoff(self, initial_backoff: float, max_backoff: float) -> PyWatchtowerClientBuilder: ...
+    def heartbeat_interval(self, interval: float) -> PyWatchtowerClientBuilder: ...
+    def cache_ttl(self, ttl: float) -> PyWatchtowerClientBuilder: ...
+    def refresh_interval(self, interval: float) -> PyWatchtowerClientBuilder: ...
+    def idle_timeout(self, timeout: float) -> PyWatchtowerClientBuilder: ...
+    def max_staleness(self, max_staleness: float) -> PyWatchtowerClientBuilder: ...
+    def cache_file(self, path: str) -> PyWatchtowerClientBuilder: ...
+    def user_agent(self, user_agent: str) -> PyWatchtowerClientBuilder: ...
+    def build(self) -> PyWatchtowerClient: ...
+    def build_async(self) -> PyAsyncWatchtowerClient: ...
+
+class PyAsyncRegistration:
+    """An instance to register, as returned by `PyAsyncWatchtowerClient.register`
+
+    Awaiting it registers the instance until `cancel` is called on the client. Used with `async with`, the instance is
+    registered on entry and its lease cancelled on exit.
+    """
+
+    def __await__(self) -> Generator[None, None, str]: ...
+    def __aenter__(self) -> Awaitable[str]: ...
+    def __aexit__(
+        self,
+        exc_type: Optional[Type[BaseException]],
+        exc_value: Optional[BaseException],
+        traceback: Optional[TracebackType],
+    ) -> Awaitable[None]: ...
+
+class PyAsyncWatchtowerClient:
+    """A client whose methods return awaitables, for asyncio programs
+
+    Requests run on the runtime of the client, so they do not block the event loop.
+    """
+
+    def __init__(
+        self,
+        watchtower_urls: List[str],
+        username: str,
+        password: str,
+        *,
+        connect_timeout: Optional[float] = None,
+        request_timeout: Optional[float] = None,
+        max_attempts: Optional[int] = None,
+        initial_backoff: Optional[float] = None,
+        max_backoff: Optional[float] = None,
+        heartbeat_interval: Optional[float] = None,
+        cache_ttl: Optional[float] = None,
+        refresh_interval: Optional[float] = None,
+        idle_timeout: Optional[float] = None,
+        max_staleness: Optional[float] = None,
+        cache_file: Optional[str] = None,
+        user_agent: Optional[str] = None,
+    ) -> None: ...
+    def register(
+        self,
+        service_id: str,
+        ip_addr: str,
+        port: int,
+        *,
+        weight: Optional[int] = None,
+        metadata: Optional[Dict[str, str]] = None,
+    ) -> PyAsyncRegistration:
+        """Returns the instance to register, to be awaited or used with `async with`"""
+    def get_registrations(self) -> List[PyInstanceInfo]:
+        """Returns the instances registered through this client"""
+    def ping(self) -> Awaitable[None]: ...
+    def cancel(self) -> Awaitable[None]: ...
+    def get_service_url(self, service_id: str) -> Awaitable[str]: ...
+    def get_instances(self, service_id: str) -> Awaitable[List[PyInstanceInfo]]: ...
+    def choose_instance(self, service_id: str) -> Awaitable[PyInstanceInfo]: ...
+    def build_service_url(self, service_id: str, scheme: str = "http", path: str = "") -> Awaitable[str]: ...
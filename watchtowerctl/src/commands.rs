@@ -0,0 +1,441 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use serde::{Serialize, Deserialize};
+use watchtower_client::{
+    Error, HttpClient, InstanceInfo, InstanceStatus, InstanceUpdate, RegistryHealth, RetryPolicy
+};
+use crate::{
+    config::Settings,
+    error::{CtlError, Result},
+    output::{format_duration_ms, format_metadata, format_time_of_day, print_json, OutputFormat, Table}
+};
+
+/// The version of the snapshot format, increased on every incompatible change
+const SNAPSHOT_VERSION: u32 = 1;
+/// How often a lease held by `register --hold` is renewed, well within the lease ttl of the registry
+const HOLD_RENEW_INTERVAL_SEC: u64 = 10;
+const REQUEST_TIMEOUT_SEC: u64 = 10;
+
+/// What every command needs to reach the registry and print its results
+pub struct Context {
+    pub settings: Settings,
+    pub http_client: HttpClient,
+    pub output: OutputFormat
+}
+
+impl Context {
+    pub fn new(settings: Settings, output: OutputFormat) -> Result<Self> {
+        let http_client = HttpClient::with_client(
+            create_reqwest_client()?,
+            settings.urls.clone(),
+            settings.username.clone(),
+            settings.password.clone(),
+            RetryPolicy::default()
+        );
+        Ok(Context {
+            settings,
+            http_client,
+            output
+        })
+    }
+}
+
+fn create_reqwest_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SEC))
+        .build()
+        .map_err(|err| CtlError::Config(format!("Unable to create the http client: {}", err)))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Returns the status as sent by the registry, such as `OUT_OF_SERVICE`
+fn status_name(status: InstanceStatus) -> String {
+    serde_json::to_value(status).ok()
+        .and_then(|status| status.as_str().map(|status| status.to_string()))
+        .unwrap_or_default()
+}
+
+/// Lists every service with its number of instances
+pub async fn list_services(context: &Context) -> Result<()> {
+    let services: BTreeMap<String, Vec<InstanceInfo>> = context.http_client.get_all_services().await
+        .map_err(CtlError::request("Unable to list the services".to_string()))?
+        .into_iter()
+        .collect();
+    if context.output == OutputFormat::Json {
+        return print_json(&services);
+    }
+
+    let mut table = Table::new(vec!["SERVICE", "INSTANCES", "UP"]);
+    for (service_id, instance_infos) in &services {
+        let up = instance_infos.iter().filter(|instance_info| instance_info.status == InstanceStatus::Up).count();
+        table.add_row(vec![service_id.clone(), instance_infos.len().to_string(), up.to_string()]);
+    }
+    println!("{}", table.render());
+    Ok(())
+}
+
+/// Shows the instances of a service, with their metadata and the age of their lease
+pub async fn show_instances(context: &Context, service_id: &str) -> Result<()> {
+    let mut leases = context.http_client.get_all_leases(service_id).await
+        .map_err(CtlError::request(format!("Unable to get the instances of {}", service_id)))?;
+    leases.sort_by(|a, b| a.instance_info.instance_id.cmp(&b.instance_info.instance_id));
+    if context.output == OutputFormat::Json {
+        return print_json(&leases);
+    }
+
+    let mut table = Table::new(vec!["INSTANCE ID", "ADDRESS", "STATUS", "WEIGHT", "LEASE AGE", "METADATA"]);
+    for lease in &leases {
+        let instance_info = &lease.instance_info;
+        table.add_row(vec![
+            instance_info.instance_id.clone(),
+            instance_info.address(),
+            status_name(instance_info.status),
+            instance_info.weight.to_string(),
+            format_duration_ms(lease.lease_age_ms),
+            format_metadata(&instance_info.metadata)
+        ]);
+    }
+    println!("{}", table.render());
+    Ok(())
+}
+
+/// Registers an instance, and keeps renewing its lease until interrupted if `hold` is set
+///
+/// Without `hold`, the lease expires unless the instance renews it itself.
+pub async fn register(context: &Context, service_id: &str, instance_info: InstanceInfo, hold: bool) -> Result<()> {
+    let http_client = &context.http_client;
+    http_client.register(service_id, &instance_info).await
+        .map_err(CtlError::request(format!("Unable to register an instance of {}", service_id)))?;
+    match context.output {
+        OutputFormat::Json => print_json(&serde_json::json!({
+            "service_id": service_id,
+            "instance_id": instance_info.instance_id
+        }))?,
+        OutputFormat::Table => println!("Registered instance {} of {}", instance_info.instance_id, service_id)
+    }
+    if !hold {
+        return Ok(());
+    }
+
+    eprintln!("Renewing the lease every {}s, press Ctrl-C to deregister", HOLD_RENEW_INTERVAL_SEC);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            _ = tokio::time::delay_for(Duration::from_secs(HOLD_RENEW_INTERVAL_SEC)) => {}
+        }
+        // A lease lost to eviction is taken again
+        let result = match http_client.renew(service_id, &instance_info).await {
+            Err(Error::NotFound) => http_client.register(service_id, &instance_info).await,
+            result => result
+        };
+        if let Err(err) = result {
+            eprintln!("Unable to renew the lease of {}: {}", instance_info.instance_id, err);
+        }
+    }
+    http_client.cancel(service_id, &instance_info).await
+        .map_err(CtlError::request(format!("Unable to deregister {}", instance_info.instance_id)))?;
+    eprintln!("Deregistered instance {} of {}", instance_info.instance_id, service_id);
+    Ok(())
+}
+
+/// Cancels the lease of an instance
+pub async fn deregister(context: &Context, service_id: &str, instance_id: &str) -> Result<()> {
+    let context_message = format!("Unable to deregister instance {} of {}", instance_id, service_id);
+    // Cancelling an unknown instance succeeds, so its existence is checked first to report typos
+    let instance_infos = context.http_client.get_all_instances(service_id).await
+        .map_err(CtlError::request(context_message.clone()))?;
+    let instance_info = instance_infos.into_iter()
+        .find(|instance_info| instance_info.instance_id == instance_id)
+        .ok_or_else(|| CtlError::Request(context_message.clone(), Error::NotFound))?;
+    context.http_client.cancel(service_id, &instance_info).await
+        .map_err(CtlError::request(context_message))?;
+    print_done(context, &format!("Deregistered instance {} of {}", instance_id, service_id))
+}
+
+/// Changes the status of an instance, for example to take it out of service
+pub async fn set_status(context: &Context, service_id: &str, instance_id: &str, status: InstanceStatus) -> Result<()> {
+    let update = InstanceUpdate { status: Some(status), ..InstanceUpdate::default() };
+    context.http_client.update_instance(service_id, instance_id, &update).await
+        .map_err(CtlError::request(format!("Unable to update instance {} of {}", instance_id, service_id)))?;
+    print_done(context, &format!("Set the status of instance {} of {} to {}", instance_id, service_id, status_name(status)))
+}
+
+fn print_done(context: &Context, message: &str) -> Result<()> {
+    match context.output {
+        OutputFormat::Json => print_json(&serde_json::json!({ "message": message })),
+        OutputFormat::Table => {
+            println!("{}", message);
+            Ok(())
+        }
+    }
+}
+
+/// A change to the instances of a watched service
+#[derive(Serialize, Debug)]
+pub struct WatchEvent {
+    pub event: &'static str,
+    pub timestamp_ms: u64,
+    pub instance: InstanceInfo
+}
+
+/// Returns the events from the instances last seen to `current`, where an instance whose address, status, weight or
+/// metadata differs is changed
+fn diff(previous: &BTreeMap<String, InstanceInfo>, current: &BTreeMap<String, InstanceInfo>, timestamp_ms: u64) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+    for (instance_id, instance_info) in current {
+        let event = match previous.get(instance_id) {
+            None => "added",
+            Some(known) if known.ip_addr != instance_info.ip_addr
+                || known.port != instance_info.port
+                || known.status != instance_info.status
+                || known.weight != instance_info.weight
+                || known.metadata != instance_info.metadata => "changed",
+            Some(_) => continue
+        };
+        events.push(WatchEvent { event, timestamp_ms, instance: instance_info.clone() });
+    }
+    for (instance_id, instance_info) in previous {
+        if !current.contains_key(instance_id) {
+            events.push(WatchEvent { event: "removed", timestamp_ms, instance: instance_info.clone() });
+        }
+    }
+    events
+}
+
+/// Prints the changes to the instances of a service until interrupted, starting with the current instances
+pub async fn watch(context: &Context, service_id: &str, interval: Duration) -> Result<()> {
+    let mut known_instance_infos = BTreeMap::new();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        match context.http_client.get_all_instances(service_id).await {
+            Ok(instance_infos) => {
+                let instance_infos = instance_infos.into_iter()
+                    .map(|instance_info| (instance_info.instance_id.clone(), instance_info))
+                    .collect();
+                for event in diff(&known_instance_infos, &instance_infos, now_millis()) {
+                    print_event(context, &event)?;
+                }
+                known_instance_infos = instance_infos;
+            },
+            Err(Error::NotFound) => {
+                for event in diff(&known_instance_infos, &BTreeMap::new(), now_millis()) {
+                    print_event(context, &event)?;
+                }
+                known_instance_infos.clear();
+            },
+            // The instances last seen are kept while the registry is unreachable
+            Err(err) => eprintln!("Unable to get the instances of {}: {}", service_id, err)
+        }
+
+        tokio::select! {
+            _ = &mut ctrl_c => return Ok(()),
+            _ = tokio::time::delay_for(interval) => {}
+        }
+    }
+}
+
+fn print_event(context: &Context, event: &WatchEvent) -> Result<()> {
+    match context.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string(event)?),
+        OutputFormat::Table => {
+            let instance_info = &event.instance;
+            println!(
+                "{}  {:<7}  {}  {}  {}  weight={}  {}",
+                format_time_of_day(event.timestamp_ms),
+                event.event.to_uppercase(),
+                instance_info.instance_id,
+                instance_info.address(),
+                status_name(instance_info.status),
+                instance_info.weight,
+                format_metadata(&instance_info.metadata)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The instances of every service at a point in time
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub version: u32,
+    pub taken_at_ms: u64,
+    pub services: BTreeMap<String, Vec<InstanceInfo>>
+}
+
+/// Writes a snapshot of the registry to `path`, or to stdout if none is given
+pub async fn dump_snapshot(context: &Context, path: Option<&Path>) -> Result<()> {
+    let services = context.http_client.get_all_services().await
+        .map_err(CtlError::request("Unable to list the services".to_string()))?;
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        taken_at_ms: now_millis(),
+        services: services.into_iter().collect()
+    };
+    match path {
+        Some(path) => {
+            std::fs::write(path, serde_json::to_string_pretty(&snapshot)?)?;
+            let instance_count: usize = snapshot.services.values().map(|instance_infos| instance_infos.len()).sum();
+            eprintln!("Saved {} instances of {} services to {}", instance_count, snapshot.services.len(), path.display());
+            Ok(())
+        },
+        None => print_json(&snapshot)
+    }
+}
+
+/// Registers every instance of the snapshot at `path`
+///
+/// The leases of restored instances expire unless the instances renew them, as after any registration.
+pub async fn restore_snapshot(context: &Context, path: &Path) -> Result<()> {
+    let snapshot: Snapshot = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(CtlError::Config(format!(
+            "Unsupported snapshot version {}, expected {}", snapshot.version, SNAPSHOT_VERSION
+        )));
+    }
+
+    let mut restored_count = 0;
+    let mut failed_instance_ids = Vec::new();
+    let mut last_error = None;
+    for (service_id, instance_infos) in &snapshot.services {
+        for instance_info in instance_infos {
+            match context.http_client.register(service_id, instance_info).await {
+                Ok(()) => restored_count += 1,
+                Err(err) => {
+                    eprintln!("Unable to restore instance {} of {}: {}", instance_info.instance_id, service_id, err);
+                    failed_instance_ids.push(instance_info.instance_id.clone());
+                    last_error = Some(err);
+                }
+            }
+        }
+    }
+    match context.output {
+        OutputFormat::Json => print_json(&serde_json::json!({
+            "restored": restored_count,
+            "failed": failed_instance_ids
+        }))?,
+        OutputFormat::Table => println!("Restored {} instances of {} services", restored_count, snapshot.services.len())
+    }
+    if let Some(err) = last_error {
+        return Err(CtlError::Request(format!("Unable to restore {} instances", failed_instance_ids.len()), err));
+    }
+    Ok(())
+}
+
+/// The health of a node of the cluster, or why it could not be reached
+#[derive(Serialize, Debug)]
+pub struct NodeStatus {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<RegistryHealth>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+/// Shows the health of every node of the profile, failing if any is unreachable or not ready
+pub async fn show_cluster(context: &Context) -> Result<()> {
+    let mut node_statuses = Vec::new();
+    for url in &context.settings.urls {
+        // Every node is asked once, as retries would move on to the next node
+        let retry_policy = RetryPolicy { max_attempts: 1, ..RetryPolicy::default() };
+        let http_client = HttpClient::with_client(
+            create_reqwest_client()?,
+            vec![url.clone()],
+            context.settings.username.clone(),
+            context.settings.password.clone(),
+            retry_policy
+        );
+        let result = http_client.get_health().await;
+        node_statuses.push(NodeStatus {
+            url: url.clone(),
+            error: result.as_ref().err().map(|err| err.to_string()),
+            health: result.ok()
+        });
+    }
+
+    match context.output {
+        OutputFormat::Json => print_json(&node_statuses)?,
+        OutputFormat::Table => {
+            let mut table = Table::new(vec!["NODE", "STATUS", "SYNCED", "EVICTION", "SERVICES", "INSTANCES"]);
+            for node_status in &node_statuses {
+                let row = match (&node_status.health, &node_status.error) {
+                    (Some(health), _) => vec![
+                        node_status.url.clone(),
+                        if health.is_ready { "ready" } else { "not ready" }.to_string(),
+                        health.is_synced.to_string(),
+                        if health.is_eviction_stalled {
+                            format!("stalled for {}s", health.seconds_since_last_eviction)
+                        } else {
+                            "running".to_string()
+                        },
+                        health.services.to_string(),
+                        health.instances.to_string()
+                    ],
+                    (None, error) => vec![
+                        node_status.url.clone(),
+                        format!("unreachable ({})", error.as_deref().unwrap_or_default()),
+                        "-".to_string(),
+                        "-".to_string(),
+                        "-".to_string(),
+                        "-".to_string()
+                    ]
+                };
+                table.add_row(row);
+            }
+            println!("{}", table.render());
+        }
+    }
+    let is_healthy = node_statuses.iter().all(|node_status| node_status.health.as_ref().is_some_and(|health| health.is_ready));
+    if is_healthy { Ok(()) } else { Err(CtlError::Unhealthy) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn create_instance_info(instance_id: &str, port: u16, status: InstanceStatus) -> (String, InstanceInfo) {
+        let instance_info = InstanceInfo {
+            instance_id: instance_id.to_string(),
+            ip_addr: "127.0.0.1".to_string(),
+            port,
+            status,
+            weight: 1,
+            metadata: HashMap::new()
+        };
+        (instance_id.to_string(), instance_info)
+    }
+
+    #[test]
+    fn test_diff() {
+        let previous = vec![
+            create_instance_info("a", 8080, InstanceStatus::Up),
+            create_instance_info("b", 8081, InstanceStatus::Up),
+            create_instance_info("c", 8082, InstanceStatus::Up)
+        ].into_iter().collect();
+        let current = vec![
+            create_instance_info("a", 8080, InstanceStatus::Up),
+            create_instance_info("b", 8081, InstanceStatus::OutOfService),
+            create_instance_info("d", 8083, InstanceStatus::Up)
+        ].into_iter().collect();
+
+        let events = diff(&previous, &current, 0).into_iter()
+            .map(|event| (event.event, event.instance.instance_id))
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec![("changed", "b".to_string()), ("added", "d".to_string()), ("removed", "c".to_string())]);
+        assert!(diff(&current, &current, 0).is_empty());
+    }
+
+    #[test]
+    fn test_status_name() {
+        assert_eq!(status_name(InstanceStatus::OutOfService), "OUT_OF_SERVICE");
+    }
+}
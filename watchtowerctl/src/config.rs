@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf}
+};
+use serde::Deserialize;
+use crate::error::{CtlError, Result};
+
+const DEFAULT_URL: &str = "http://localhost:8088";
+const DEFAULT_PROFILE: &str = "default";
+
+/// The config file, which holds the urls and credentials of every registry as a named profile
+///
+/// ```toml
+/// default_profile = "staging"
+///
+/// [profiles.staging]
+/// urls = ["http://10.0.0.1:8088", "http://10.0.0.2:8088"]
+/// username = "admin"
+/// password = "password"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    #[serde(default)]
+    pub urls: Vec<String>,
+    pub username: Option<String>,
+    pub password: Option<String>
+}
+
+/// The settings given on the command line or in the environment, which take precedence over the profile
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub profile: Option<String>,
+    pub urls: Vec<String>,
+    pub username: Option<String>,
+    pub password: Option<String>
+}
+
+/// The registry to connect to
+#[derive(Debug, PartialEq)]
+pub struct Settings {
+    pub urls: Vec<String>,
+    pub username: String,
+    pub password: String
+}
+
+impl ConfigFile {
+    pub fn parse(contents: &str) -> Result<Self> {
+        toml::from_str(contents).map_err(|err| CtlError::Config(format!("Invalid config file: {}", err)))
+    }
+
+    /// Reads the config file at `path`, or at the default path if none is given
+    ///
+    /// A missing file at the default path is treated as an empty config.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, is_explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(ConfigFile::default())
+            }
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => ConfigFile::parse(&contents),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !is_explicit => Ok(ConfigFile::default()),
+            Err(err) => Err(CtlError::Config(format!("Unable to read {}: {}", path.display(), err)))
+        }
+    }
+
+    /// Merges the selected profile with `overrides`
+    ///
+    /// An unknown profile is an error, unless it is the implicit default one.
+    pub fn resolve(&self, overrides: Overrides) -> Result<Settings> {
+        let is_explicit = overrides.profile.is_some() || self.default_profile.is_some();
+        let name = overrides.profile.as_deref()
+            .or(self.default_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE);
+        let profile = match self.profiles.get(name) {
+            Some(profile) => profile.clone(),
+            None if is_explicit => return Err(CtlError::Config(format!("Unknown profile {}", name))),
+            None => Profile::default()
+        };
+
+        let urls = if !overrides.urls.is_empty() {
+            overrides.urls
+        } else if !profile.urls.is_empty() {
+            profile.urls
+        } else {
+            vec![DEFAULT_URL.to_string()]
+        };
+        let missing = |field: &str| {
+            CtlError::Config(format!("No {} for profile {}, set it in the config file or with --{}", field, name, field))
+        };
+        Ok(Settings {
+            urls,
+            username: overrides.username.or(profile.username).ok_or_else(|| missing("username"))?,
+            password: overrides.password.or(profile.password).ok_or_else(|| missing("password"))?
+        })
+    }
+}
+
+/// Returns `$XDG_CONFIG_HOME/watchtower/config.toml`, falling back to `~/.config`
+fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("watchtower").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        default_profile = "staging"
+
+        [profiles.staging]
+        urls = ["http://10.0.0.1:8088", "http://10.0.0.2:8088"]
+        username = "admin"
+        password = "staging_password"
+
+        [profiles.local]
+        username = "admin"
+        password = "password"
+    "#;
+
+    #[test]
+    fn test_resolve_profile() {
+        let config = ConfigFile::parse(CONFIG).unwrap();
+        let settings = config.resolve(Overrides::default()).unwrap();
+        assert_eq!(settings.urls, vec!["http://10.0.0.1:8088", "http://10.0.0.2:8088"]);
+        assert_eq!(settings.password, "staging_password");
+
+        let overrides = Overrides { profile: Some("local".to_string()), ..Overrides::default() };
+        assert_eq!(config.resolve(overrides).unwrap().urls, vec![DEFAULT_URL]);
+
+        let overrides = Overrides {
+            urls: vec!["http://127.0.0.1:9000".to_string()],
+            password: Some("other_password".to_string()),
+            ..Overrides::default()
+        };
+        let settings = config.resolve(overrides).unwrap();
+        assert_eq!(settings.urls, vec!["http://127.0.0.1:9000"]);
+        assert_eq!((settings.username.as_str(), settings.password.as_str()), ("admin", "other_password"));
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let config = ConfigFile::parse(CONFIG).unwrap();
+        let overrides = Overrides { profile: Some("production".to_string()), ..Overrides::default() };
+        assert!(config.resolve(overrides).is_err());
+
+        // Without a config file, credentials must be given explicitly
+        assert!(ConfigFile::default().resolve(Overrides::default()).is_err());
+        let overrides = Overrides {
+            username: Some("admin".to_string()),
+            password: Some("password".to_string()),
+            ..Overrides::default()
+        };
+        assert_eq!(ConfigFile::default().resolve(overrides).unwrap().urls, vec![DEFAULT_URL]);
+        assert!(ConfigFile::parse("profiles = 1").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+#[derive(Debug)]
+pub enum CtlError {
+    /// A request to the registry failed
+    Request(String, watchtower_client::Error),
+    /// The configuration or the arguments are invalid
+    Config(String),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// Some nodes of the cluster are unreachable or not ready
+    Unhealthy
+}
+
+impl CtlError {
+    /// Wraps a client error with a description of the request, such as `Unable to list the services`
+    pub fn request(context: String) -> impl FnOnce(watchtower_client::Error) -> CtlError {
+        move |err| CtlError::Request(context, err)
+    }
+}
+
+impl std::fmt::Display for CtlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CtlError::Request(context, err) => write!(f, "{}: {}", context, err),
+            CtlError::Config(message) => f.write_str(message),
+            CtlError::Io(err) => write!(f, "{}", err),
+            CtlError::Json(err) => write!(f, "invalid json: {}", err),
+            CtlError::Unhealthy => f.write_str("some nodes are unreachable or not ready")
+        }
+    }
+}
+
+impl std::error::Error for CtlError {}
+
+impl From<std::io::Error> for CtlError {
+    fn from(error: std::io::Error) -> Self {
+        CtlError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for CtlError {
+    fn from(error: serde_json::Error) -> Self {
+        CtlError::Json(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CtlError>;
@@ -0,0 +1,187 @@
+mod commands;
+mod config;
+mod error;
+mod output;
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::Duration
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use watchtower_client::{InstanceInfo, InstanceStatus};
+use crate::{
+    commands::Context,
+    config::{ConfigFile, Overrides},
+    error::{CtlError, Result},
+    output::OutputFormat
+};
+
+/// Inspects and operates a watchtower registry
+#[derive(Parser, Debug)]
+#[command(name = "watchtowerctl", version)]
+struct Cli {
+    /// The config file holding the profiles, by default ~/.config/watchtower/config.toml
+    #[arg(long, global = true, env = "WATCHTOWERCTL_CONFIG")]
+    config: Option<PathBuf>,
+    /// The profile of the config file to use
+    #[arg(short, long, global = true, env = "WATCHTOWER_PROFILE")]
+    profile: Option<String>,
+    /// The url of a registry node, which may be repeated, overriding those of the profile
+    #[arg(long = "url", global = true, env = "WATCHTOWER_URLS", value_delimiter = ',')]
+    urls: Vec<String>,
+    #[arg(long, global = true, env = "WATCHTOWER_USERNAME")]
+    username: Option<String>,
+    #[arg(long, global = true, env = "WATCHTOWER_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Lists every service with its number of instances
+    Services,
+    /// Shows the instances of a service, with their metadata and lease age
+    Instances {
+        service_id: String
+    },
+    /// Registers an instance of a service
+    Register {
+        service_id: String,
+        #[arg(long)]
+        ip: String,
+        #[arg(long)]
+        port: u16,
+        /// A random id is generated if none is given
+        #[arg(long)]
+        instance_id: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        weight: u32,
+        #[arg(long, value_enum, default_value_t = Status::Up)]
+        status: Status,
+        /// A metadata entry of the instance, which may be repeated
+        #[arg(long = "metadata", value_name = "KEY=VALUE", value_parser = parse_metadata)]
+        metadata: Vec<(String, String)>,
+        /// Keeps renewing the lease until interrupted, then deregisters the instance
+        #[arg(long)]
+        hold: bool
+    },
+    /// Cancels the lease of an instance
+    Deregister {
+        service_id: String,
+        instance_id: String
+    },
+    /// Changes the status of an instance
+    SetStatus {
+        service_id: String,
+        instance_id: String,
+        #[arg(value_enum)]
+        status: Status
+    },
+    /// Prints the changes to the instances of a service until interrupted
+    Watch {
+        service_id: String,
+        /// The polling interval in seconds
+        #[arg(long, default_value_t = 2)]
+        interval: u64
+    },
+    /// Saves or restores the instances of every service
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Shows the health of every node, failing if any is unreachable or not ready
+    Cluster
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Writes the instances of every service as JSON
+    Dump {
+        /// The file to write to, stdout if none is given
+        #[arg(long)]
+        file: Option<PathBuf>
+    },
+    /// Registers every instance of a snapshot
+    Restore {
+        file: PathBuf
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Status {
+    Up,
+    Down,
+    Starting,
+    OutOfService
+}
+
+impl From<Status> for InstanceStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Up => InstanceStatus::Up,
+            Status::Down => InstanceStatus::Down,
+            Status::Starting => InstanceStatus::Starting,
+            Status::OutOfService => InstanceStatus::OutOfService
+        }
+    }
+}
+
+fn parse_metadata(entry: &str) -> std::result::Result<(String, String), String> {
+    match entry.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {}", entry))
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let overrides = Overrides {
+        profile: cli.profile,
+        urls: cli.urls,
+        username: cli.username,
+        password: cli.password
+    };
+    let settings = ConfigFile::load(cli.config.as_deref())?.resolve(overrides)?;
+    let context = Context::new(settings, cli.output)?;
+
+    match cli.command {
+        Command::Services => commands::list_services(&context).await,
+        Command::Instances { service_id } => commands::show_instances(&context, &service_id).await,
+        Command::Register { service_id, ip, port, instance_id, weight, status, metadata, hold } => {
+            let instance_info = InstanceInfo {
+                instance_id: instance_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+                ip_addr: ip,
+                port,
+                status: status.into(),
+                weight,
+                metadata: metadata.into_iter().collect::<HashMap<_, _>>()
+            };
+            commands::register(&context, &service_id, instance_info, hold).await
+        },
+        Command::Deregister { service_id, instance_id } => {
+            commands::deregister(&context, &service_id, &instance_id).await
+        },
+        Command::SetStatus { service_id, instance_id, status } => {
+            commands::set_status(&context, &service_id, &instance_id, status.into()).await
+        },
+        Command::Watch { service_id, interval } => {
+            if interval == 0 {
+                return Err(CtlError::Config("The interval must be at least 1 second".to_string()));
+            }
+            commands::watch(&context, &service_id, Duration::from_secs(interval)).await
+        },
+        Command::Snapshot(SnapshotCommand::Dump { file }) => commands::dump_snapshot(&context, file.as_deref()).await,
+        Command::Snapshot(SnapshotCommand::Restore { file }) => commands::restore_snapshot(&context, &file).await,
+        Command::Cluster => commands::show_cluster(&context).await
+    }
+}
+
+#[tokio::main(basic_scheduler)]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = run(cli).await {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use clap::ValueEnum;
+use serde::Serialize;
+use crate::error::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Columns aligned for reading
+    Table,
+    /// Pretty-printed JSON, or one object per line for streamed events
+    Json
+}
+
+/// A table whose columns are aligned on their widest cell
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>
+}
+
+impl Table {
+    pub fn new(headers: Vec<&'static str>) -> Self {
+        Table {
+            headers,
+            rows: Vec::new()
+        }
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn render(&self) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let headers = self.headers.iter().map(|header| header.to_string()).collect::<Vec<_>>();
+        let mut lines = Vec::with_capacity(self.rows.len() + 1);
+        for row in std::iter::once(&headers).chain(&self.rows) {
+            let cells = row.iter().zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>();
+            lines.push(cells.join("  ").trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Formats a duration in milliseconds with its two largest units, such as `1.5s` or `2m5s`
+pub fn format_duration_ms(duration_ms: u64) -> String {
+    let seconds = duration_ms / 1000;
+    match seconds {
+        0..=59 => format!("{:.1}s", duration_ms as f64 / 1000.0),
+        60..=3599 => format!("{}m{}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{}m", seconds / 3600, seconds % 3600 / 60)
+    }
+}
+
+/// Formats a wall-clock time in milliseconds since the Unix epoch as a UTC time of day, such as `13:04:05Z`
+pub fn format_time_of_day(timestamp_ms: u64) -> String {
+    let seconds = timestamp_ms / 1000 % 86_400;
+    format!("{:02}:{:02}:{:02}Z", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+/// Formats metadata as `key=value` pairs sorted by key, or `-` if there are none
+pub fn format_metadata(metadata: &HashMap<String, String>) -> String {
+    if metadata.is_empty() {
+        return "-".to_string();
+    }
+    let mut pairs = metadata.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let mut table = Table::new(vec!["SERVICE", "INSTANCES"]);
+        table.add_row(vec!["traffic_control".to_string(), "2".to_string()]);
+        table.add_row(vec!["auth".to_string(), "10".to_string()]);
+        assert_eq!(table.render(), "SERVICE          INSTANCES\ntraffic_control  2\nauth             10");
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_duration_ms(1500), "1.5s");
+        assert_eq!(format_duration_ms(125_000), "2m5s");
+        assert_eq!(format_duration_ms(7_500_000), "2h5m");
+        assert_eq!(format_time_of_day(86_400_000 + 13 * 3_600_000 + 4 * 60_000 + 5_000), "13:04:05Z");
+
+        let metadata = vec![("zone", "a"), ("grpc_port", "9090")].into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        assert_eq!(format_metadata(&metadata), "grpc_port=9090,zone=a");
+        assert_eq!(format_metadata(&HashMap::new()), "-");
+    }
+}
@@ -0,0 +1,82 @@
+use std::process::{Command, Output};
+
+const WATCHTOWER_URL: &str = "http://localhost:8088";
+
+const USERNAME: &str = "admin";
+const PASSWORD: &str = "password";
+
+fn get_watchtower_urls() -> String {
+    std::env::var("WATCHTOWER_URLS").unwrap_or_else(|_| WATCHTOWER_URL.to_string())
+}
+
+fn watchtowerctl(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_watchtowerctl"))
+        .args(["--url", &get_watchtower_urls(), "--username", USERNAME, "--password", PASSWORD])
+        .args(args)
+        .env_remove("WATCHTOWER_PROFILE")
+        .env("WATCHTOWERCTL_CONFIG", "/dev/null")
+        .output()
+        .unwrap()
+}
+
+fn get_instances(service_id: &str) -> serde_json::Value {
+    let output = watchtowerctl(&["-o", "json", "instances", service_id]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_register_and_deregister() {
+    let service_id = "test_ctl_register_and_deregister";
+    let output = watchtowerctl(&[
+        "register", service_id, "--ip", "127.0.0.1", "--port", "1234", "--instance-id", "ctl_instance",
+        "--metadata", "zone=a"
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let leases = get_instances(service_id);
+    assert_eq!(leases[0]["instance_info"]["instance_id"], "ctl_instance");
+    assert_eq!(leases[0]["instance_info"]["metadata"]["zone"], "a");
+
+    let output = watchtowerctl(&["set-status", service_id, "ctl_instance", "out-of-service"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(get_instances(service_id)[0]["instance_info"]["status"], "OUT_OF_SERVICE");
+
+    let output = watchtowerctl(&["instances", service_id]);
+    let table = String::from_utf8(output.stdout).unwrap();
+    assert!(table.starts_with("INSTANCE ID"));
+    assert!(table.contains("127.0.0.1:1234"));
+
+    assert!(watchtowerctl(&["deregister", service_id, "ctl_instance"]).status.success());
+    assert!(!watchtowerctl(&["deregister", service_id, "ctl_instance"]).status.success());
+}
+
+#[test]
+fn test_snapshot_dump() {
+    let service_id = "test_ctl_snapshot_dump";
+    let output = watchtowerctl(&["register", service_id, "--ip", "127.0.0.1", "--port", "1235"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = watchtowerctl(&["snapshot", "dump"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let snapshot: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(snapshot["version"], 1);
+    assert_eq!(snapshot["services"][service_id][0]["port"], 1235);
+
+    let instance_id = snapshot["services"][service_id][0]["instance_id"].as_str().unwrap();
+    assert!(watchtowerctl(&["deregister", service_id, instance_id]).status.success());
+}
+
+#[test]
+fn test_missing_credentials() {
+    let output = Command::new(env!("CARGO_BIN_EXE_watchtowerctl"))
+        .args(["--url", &get_watchtower_urls(), "services"])
+        .env_remove("WATCHTOWER_USERNAME")
+        .env_remove("WATCHTOWER_PASSWORD")
+        .env_remove("WATCHTOWER_PROFILE")
+        .env("WATCHTOWERCTL_CONFIG", "/dev/null")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No username"));
+}